@@ -0,0 +1,114 @@
+//! `eventfd` implementation.
+
+use crate::core::file::fd::FileLike;
+use crate::core::random::random_u32;
+use crate::utils::task::task_yield_interruptable;
+use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
+use axfs_ng::api::FileFlags;
+use axio::PollState;
+use axsync::Mutex;
+use core::any::Any;
+use undefined_vfs::types::{Metadata, NodePermission};
+
+/// The maximum value of the counter, a write that would exceed it blocks.
+const EVENTFD_MAX: u64 = u64::MAX - 1;
+
+pub struct EventFd {
+    counter: Mutex<u64>,
+    /// Whether a read decrements the counter by 1 instead of resetting it.
+    semaphore: bool,
+    inode: u64,
+    file_flags: Mutex<FileFlags>,
+}
+
+impl EventFd {
+    pub fn new(initval: u64, semaphore: bool, file_flags: FileFlags) -> Self {
+        Self {
+            counter: Mutex::new(initval),
+            semaphore,
+            inode: random_u32() as _,
+            file_flags: Mutex::new(file_flags | FileFlags::READ | FileFlags::WRITE),
+        }
+    }
+
+    pub fn is_non_block(&self) -> bool {
+        self.file_flags.lock().contains(FileFlags::NON_BLOCK)
+    }
+}
+
+impl FileLike for EventFd {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        if buf.len() < size_of::<u64>() {
+            return Err(LinuxError::EINVAL);
+        }
+        loop {
+            let mut counter = self.counter.lock();
+            if *counter > 0 {
+                let value = if self.semaphore { 1 } else { *counter };
+                *counter -= value;
+                buf[..size_of::<u64>()].copy_from_slice(&value.to_ne_bytes());
+                return Ok(size_of::<u64>());
+            }
+            drop(counter);
+            if self.is_non_block() {
+                return Err(LinuxError::EAGAIN);
+            }
+            // Counter is zero, wait for a write
+            task_yield_interruptable()?;
+        }
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        let bytes: [u8; size_of::<u64>()] = buf
+            .get(..size_of::<u64>())
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or(LinuxError::EINVAL)?;
+        let value = u64::from_ne_bytes(bytes);
+        if value == u64::MAX {
+            return Err(LinuxError::EINVAL);
+        }
+        loop {
+            let mut counter = self.counter.lock();
+            if EVENTFD_MAX - *counter >= value {
+                *counter += value;
+                return Ok(size_of::<u64>());
+            }
+            drop(counter);
+            if self.is_non_block() {
+                return Err(LinuxError::EAGAIN);
+            }
+            // Counter would overflow, wait for a read
+            task_yield_interruptable()?;
+        }
+    }
+
+    fn status(&self) -> LinuxResult<Metadata> {
+        Ok(Metadata {
+            inode: self.inode,
+            n_link: 1,
+            mode: NodePermission::OWNER_READ | NodePermission::OWNER_WRITE,
+            ..Default::default()
+        })
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        let counter = *self.counter.lock();
+        Ok(PollState {
+            readable: counter > 0,
+            writable: counter < EVENTFD_MAX,
+        })
+    }
+
+    fn get_flags(&self) -> FileFlags {
+        *self.file_flags.lock()
+    }
+
+    fn set_flags(&self, flags: FileFlags) {
+        *self.file_flags.lock() = flags;
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+}
@@ -1,22 +1,44 @@
 use crate::core::file::fd::{FileDescriptor, FileLike, fd_lookup};
 use crate::core::file::{ApiFile, FsLocation};
+use crate::core::fs::pseudo::file::SimpleFile;
 use alloc::sync::Arc;
-use axerrno::LinuxResult;
+use axerrno::{LinuxError, LinuxResult};
 use axfs_ng::api::FileFlags;
-use axio::PollState;
+use axio::{PollState, SeekFrom};
+use axsignal::{SignalInfo, Signo};
 use axsync::{Mutex, MutexGuard};
 use core::any::Any;
-use undefined_vfs::types::Metadata;
+use linux_raw_sys::general::SI_KERNEL;
+use starry_core::page_cache::{self, CachedFile};
+use starry_core::resource::{RLIMIT_INFINITY, ResourceLimitType};
+use starry_core::task::{current_process_data, current_thread_data};
+use undefined_vfs::types::{Metadata, NodeType};
 
 /// File-like wrapper for [axfs_ng::api::File].
+///
+/// Regular files are read and written through the page cache, which writes
+/// the data back later. They may not grow beyond `RLIMIT_FSIZE`.
 pub struct File {
     inner: Mutex<ApiFile>,
+    cache: Option<Arc<CachedFile>>,
 }
 
 impl File {
     pub fn new(inner: ApiFile) -> Self {
+        let location = inner.location();
+        // the content of pseudo files is generated on every read
+        let cached = location
+            .metadata()
+            .is_ok_and(|metadata| metadata.node_type == NodeType::RegularFile)
+            && location.entry().downcast::<SimpleFile>().is_err();
+        let cache = if cached {
+            page_cache::open(location.clone()).ok()
+        } else {
+            None
+        };
         Self {
             inner: Mutex::new(inner),
+            cache,
         }
     }
 
@@ -34,15 +56,113 @@ impl File {
         let err = file_like.type_mismatch_error();
         file_like.into_any().downcast::<Self>().map_err(|_| err)
     }
+
+    /// Read the file at `offset`, without changing the file position.
+    pub fn read_at(&self, buf: &mut [u8], offset: u64) -> LinuxResult<usize> {
+        match &self.cache {
+            Some(cache) if self.get_flags().contains(FileFlags::READ) => {
+                cache.read_at(buf, offset as usize)
+            }
+            _ => self.inner().read_at(buf, offset),
+        }
+    }
+
+    /// The part of `buf` which can be written at `offset` without going
+    /// beyond `RLIMIT_FSIZE`, `SIGXFSZ` is sent if none of it can.
+    fn within_fsize<'a>(&self, buf: &'a [u8], offset: u64) -> LinuxResult<&'a [u8]> {
+        if self.cache.is_none() || buf.is_empty() {
+            return Ok(buf);
+        }
+        let limit = fsize_limit();
+        if limit == RLIMIT_INFINITY {
+            return Ok(buf);
+        }
+        if offset >= limit {
+            exceed_fsize();
+            return Err(LinuxError::EFBIG);
+        }
+        Ok(&buf[..buf.len().min((limit - offset) as usize)])
+    }
+
+    /// Write the file at `offset`, without changing the file position.
+    pub fn write_at(&self, buf: &[u8], offset: u64) -> LinuxResult<usize> {
+        let Some(cache) = &self.cache else {
+            return self.inner().write_at(buf, offset);
+        };
+        if !self.get_flags().contains(FileFlags::WRITE) {
+            return Err(LinuxError::EBADF);
+        }
+        let buf = self.within_fsize(buf, offset)?;
+        cache.write_at(buf, offset as usize)?;
+        Ok(buf.len())
+    }
+
+    /// Truncate or extend the file to `len`.
+    pub fn resize(&self, len: u64) -> LinuxResult<()> {
+        let Some(cache) = &self.cache else {
+            return self.inner().resize(len);
+        };
+        if !self.get_flags().contains(FileFlags::WRITE) {
+            return Err(LinuxError::EINVAL);
+        }
+        if len > cache.size() as u64 && len > fsize_limit() {
+            exceed_fsize();
+            return Err(LinuxError::EFBIG);
+        }
+        cache.resize(len as usize)
+    }
+}
+
+fn fsize_limit() -> u64 {
+    current_process_data()
+        .resource_limits
+        .lock()
+        .get_soft(&ResourceLimitType::FSIZE)
+}
+
+/// Tell the current thread it tried to grow a file beyond `RLIMIT_FSIZE`.
+fn exceed_fsize() {
+    current_thread_data()
+        .signal
+        .send_signal(SignalInfo::new(Signo::SIGXFSZ, SI_KERNEL));
 }
 
 impl FileLike for File {
     fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
-        self.inner().read(buf)
+        let mut inner = self.inner();
+        match &self.cache {
+            Some(cache) if inner.get_flags().contains(FileFlags::READ) => {
+                let pos = inner.seek(SeekFrom::Current(0))?;
+                let read_len = cache.read_at(buf, pos as usize)?;
+                inner.seek(SeekFrom::Start(pos + read_len as u64))?;
+                Ok(read_len)
+            }
+            _ => inner.read(buf),
+        }
     }
 
     fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
-        self.inner().write(buf)
+        let mut inner = self.inner();
+        let Some(cache) = &self.cache else {
+            return inner.write(buf);
+        };
+        let flags = inner.get_flags();
+        if !flags.contains(FileFlags::WRITE) {
+            return Err(LinuxError::EBADF);
+        }
+        let (offset, write_len) = if flags.contains(FileFlags::APPEND) {
+            // the end is only known once the pages are locked, so the limit
+            // is checked against where it is now
+            let buf = self.within_fsize(buf, cache.size() as u64)?;
+            (cache.append(buf)?, buf.len())
+        } else {
+            let offset = inner.seek(SeekFrom::Current(0))? as usize;
+            let buf = self.within_fsize(buf, offset as u64)?;
+            cache.write_at(buf, offset)?;
+            (offset, buf.len())
+        };
+        inner.seek(SeekFrom::Start((offset + write_len) as u64))?;
+        Ok(write_len)
     }
 
     fn status(&self) -> LinuxResult<Metadata> {
@@ -4,12 +4,16 @@ use undefined_vfs::mount::Location;
 
 pub mod dir;
 pub mod epoll;
+pub mod eventfd;
 pub mod fd;
 pub mod file;
 pub mod pathfd;
+pub mod pidfd;
 pub mod pipe;
+pub mod signalfd;
 pub mod stdio;
 pub mod stub;
+pub mod timerfd;
 
 pub type ApiFile = api::File<RawMutex>;
 pub type ApiDir = api::Directory<RawMutex>;
@@ -0,0 +1,79 @@
+//! `pidfd` implementation.
+
+use crate::core::file::fd::{FdFlags, FileLike, fd_add};
+use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
+use axfs_ng::api::FileFlags;
+use axio::PollState;
+use axsync::Mutex;
+use core::any::Any;
+use core::ffi::c_int;
+use undefined_process::process::Process;
+use undefined_vfs::types::{Metadata, NodePermission};
+
+/// A file descriptor referring to a process, which stays valid even after the
+/// PID has been reused.
+pub struct PidFd {
+    process: Arc<Process>,
+    file_flags: Mutex<FileFlags>,
+}
+
+impl PidFd {
+    pub fn new(process: Arc<Process>, file_flags: FileFlags) -> Self {
+        Self {
+            process,
+            file_flags: Mutex::new(file_flags | FileFlags::READ),
+        }
+    }
+
+    pub fn process(&self) -> &Arc<Process> {
+        &self.process
+    }
+}
+
+impl FileLike for PidFd {
+    fn read(&self, _buf: &mut [u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn status(&self) -> LinuxResult<Metadata> {
+        Ok(Metadata {
+            inode: self.process.get_pid() as _,
+            n_link: 1,
+            mode: NodePermission::OWNER_READ | NodePermission::OWNER_WRITE,
+            ..Default::default()
+        })
+    }
+
+    /// A pidfd becomes readable when the process exits.
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: self.process.is_zombie(),
+            writable: false,
+        })
+    }
+
+    fn get_flags(&self) -> FileFlags {
+        *self.file_flags.lock()
+    }
+
+    fn set_flags(&self, flags: FileFlags) {
+        *self.file_flags.lock() = flags;
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+}
+
+/// Create a pidfd referring to `process`, the pidfd is always close-on-exec.
+pub fn pidfd_create(process: Arc<Process>, file_flags: FileFlags) -> LinuxResult<c_int> {
+    fd_add(
+        Arc::new(PidFd::new(process, file_flags)),
+        FdFlags::CLOSE_ON_EXEC,
+    )
+}
@@ -0,0 +1,194 @@
+//! `signalfd` implementation.
+
+use crate::core::file::fd::FileLike;
+use crate::core::random::random_u32;
+use crate::utils::task::task_yield_interruptable;
+use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
+use axfs_ng::api::FileFlags;
+use axio::PollState;
+use axsignal::{SignalInfo, SignalSet, Signo};
+use axsync::Mutex;
+use core::any::Any;
+use linux_raw_sys::general::{SI_MESGQ, SI_QUEUE, SI_TIMER};
+use starry_core::task::current_thread_data;
+use undefined_vfs::types::{Metadata, NodePermission};
+
+/// The record read from a signalfd, equivalent to C's `struct signalfd_siginfo`.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SignalfdSiginfo {
+    pub ssi_signo: u32,
+    pub ssi_errno: i32,
+    pub ssi_code: i32,
+    pub ssi_pid: u32,
+    pub ssi_uid: u32,
+    pub ssi_fd: i32,
+    pub ssi_tid: u32,
+    pub ssi_band: u32,
+    pub ssi_overrun: u32,
+    pub ssi_trapno: u32,
+    pub ssi_status: i32,
+    pub ssi_int: i32,
+    pub ssi_ptr: u64,
+    pub ssi_utime: u64,
+    pub ssi_stime: u64,
+    pub ssi_addr: u64,
+    pub ssi_addr_lsb: u16,
+    __pad2: u16,
+    pub ssi_syscall: i32,
+    pub ssi_call_addr: u64,
+    pub ssi_arch: u32,
+    __pad: [u8; 28],
+}
+
+impl From<&SignalInfo> for SignalfdSiginfo {
+    fn from(sig: &SignalInfo) -> Self {
+        let signo = sig.signo();
+        let mut info = SignalfdSiginfo {
+            ssi_signo: signo as u32,
+            ssi_code: sig.code() as i32,
+            ..Default::default()
+        };
+        // Safety: the layout of the union is determined by the signal and its code.
+        unsafe {
+            let fields = &sig.0.__bindgen_anon_1.__bindgen_anon_1;
+            info.ssi_errno = fields.si_errno;
+            let fields = &fields._sifields;
+            match info.ssi_code {
+                SI_TIMER => {
+                    info.ssi_tid = fields._timer._tid as _;
+                    info.ssi_overrun = fields._timer._overrun as _;
+                    info.ssi_int = fields._timer._sigval.sival_int;
+                    info.ssi_ptr = fields._timer._sigval.sival_ptr as _;
+                }
+                _ if matches!(
+                    signo,
+                    Signo::SIGSEGV | Signo::SIGBUS | Signo::SIGILL | Signo::SIGFPE
+                ) && info.ssi_code > 0 =>
+                {
+                    info.ssi_addr = fields._sigfault._addr as _;
+                }
+                _ => {
+                    info.ssi_pid = fields._kill._pid as _;
+                    info.ssi_uid = fields._kill._uid as _;
+                    if signo == Signo::SIGCHLD && info.ssi_code > 0 {
+                        info.ssi_status = fields._sigchld._status;
+                        info.ssi_utime = fields._sigchld._utime as _;
+                        info.ssi_stime = fields._sigchld._stime as _;
+                    }
+                    if matches!(info.ssi_code, SI_QUEUE | SI_MESGQ) {
+                        info.ssi_int = fields._rt._sigval.sival_int;
+                        info.ssi_ptr = fields._rt._sigval.sival_ptr as _;
+                    }
+                }
+            }
+        }
+        info
+    }
+}
+
+pub struct SignalFd {
+    mask: Mutex<SignalSet>,
+    inode: u64,
+    file_flags: Mutex<FileFlags>,
+}
+
+impl SignalFd {
+    pub fn new(mask: SignalSet, file_flags: FileFlags) -> Self {
+        Self {
+            mask: Mutex::new(Self::sanitize(mask)),
+            inode: random_u32() as _,
+            file_flags: Mutex::new(file_flags | FileFlags::READ),
+        }
+    }
+
+    /// `SIGKILL` and `SIGSTOP` can not be received via a signalfd.
+    fn sanitize(mut mask: SignalSet) -> SignalSet {
+        mask.remove(Signo::SIGKILL);
+        mask.remove(Signo::SIGSTOP);
+        mask
+    }
+
+    pub fn set_mask(&self, mask: SignalSet) {
+        *self.mask.lock() = Self::sanitize(mask);
+    }
+
+    pub fn is_non_block(&self) -> bool {
+        self.file_flags.lock().contains(FileFlags::NON_BLOCK)
+    }
+
+    /// Whether a signal in the mask is pending for the calling thread.
+    fn has_pending(&self) -> bool {
+        let mask = *self.mask.lock();
+        let pending = current_thread_data().signal.pending();
+        (1..=64)
+            .filter_map(Signo::from_repr)
+            .any(|signo| mask.has(signo) && pending.has(signo))
+    }
+}
+
+impl FileLike for SignalFd {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        const RECORD_SIZE: usize = size_of::<SignalfdSiginfo>();
+        if buf.len() < RECORD_SIZE {
+            return Err(LinuxError::EINVAL);
+        }
+        let thr_data = current_thread_data();
+        let mut read_size = 0;
+        loop {
+            let mask = *self.mask.lock();
+            while read_size + RECORD_SIZE <= buf.len() {
+                let Some(sig) = thr_data.signal.dequeue_signal(&mask) else {
+                    break;
+                };
+                thr_data.process_data.posix_timers.delivered(&sig);
+                let info = SignalfdSiginfo::from(&sig);
+                // Safety: `SignalfdSiginfo` is a plain C struct.
+                let bytes: [u8; RECORD_SIZE] = unsafe { core::mem::transmute(info) };
+                buf[read_size..read_size + RECORD_SIZE].copy_from_slice(&bytes);
+                read_size += RECORD_SIZE;
+            }
+            if read_size > 0 {
+                return Ok(read_size);
+            }
+            if self.is_non_block() {
+                return Err(LinuxError::EAGAIN);
+            }
+            // No signal in the mask is pending, wait for one
+            task_yield_interruptable()?;
+        }
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn status(&self) -> LinuxResult<Metadata> {
+        Ok(Metadata {
+            inode: self.inode,
+            n_link: 1,
+            mode: NodePermission::OWNER_READ | NodePermission::OWNER_WRITE,
+            ..Default::default()
+        })
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: self.has_pending(),
+            writable: false,
+        })
+    }
+
+    fn get_flags(&self) -> FileFlags {
+        *self.file_flags.lock()
+    }
+
+    fn set_flags(&self, flags: FileFlags) {
+        *self.file_flags.lock() = flags;
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+}
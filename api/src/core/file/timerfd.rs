@@ -0,0 +1,147 @@
+//! `timerfd` implementation.
+
+use crate::core::file::fd::FileLike;
+use crate::core::random::random_u32;
+use crate::utils::task::task_yield_interruptable;
+use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
+use axfs_ng::api::FileFlags;
+use axhal::time::monotonic_time_nanos;
+use axio::PollState;
+use axsync::Mutex;
+use core::any::Any;
+use core::sync::atomic::{AtomicU64, Ordering};
+use starry_core::itimer::ITimerValue;
+use starry_core::posix_timer::TimerClock;
+use starry_core::timer::{AlarmTimer, clock_set_seq};
+use undefined_vfs::types::{Metadata, NodePermission};
+
+pub struct TimerFd {
+    clock: TimerClock,
+    alarm: AlarmTimer,
+    /// The number of expirations since the last read.
+    expirations: Arc<AtomicU64>,
+    /// The realtime clock sequence to compare with, if armed with
+    /// `TFD_TIMER_CANCEL_ON_SET`.
+    cancel_seq: Mutex<Option<u64>>,
+    inode: u64,
+    file_flags: Mutex<FileFlags>,
+}
+
+impl TimerFd {
+    pub fn new(clock: TimerClock, file_flags: FileFlags) -> Self {
+        Self {
+            clock,
+            alarm: AlarmTimer::default(),
+            expirations: Arc::new(AtomicU64::new(0)),
+            cancel_seq: Mutex::new(None),
+            inode: random_u32() as _,
+            file_flags: Mutex::new(file_flags | FileFlags::READ),
+        }
+    }
+
+    pub fn is_non_block(&self) -> bool {
+        self.file_flags.lock().contains(FileFlags::NON_BLOCK)
+    }
+
+    /// Whether the realtime clock was set since the timer was armed with
+    /// `TFD_TIMER_CANCEL_ON_SET`.
+    fn is_canceled(&self) -> bool {
+        self.cancel_seq
+            .lock()
+            .is_some_and(|seq| seq != clock_set_seq())
+    }
+
+    pub fn get(&self) -> ITimerValue {
+        self.alarm.get()
+    }
+
+    /// Arm or disarm the timer, returns the old value.
+    ///
+    /// If `absolute` is set, `new.remained_ns` is the expiration time on the
+    /// clock of the timer instead of a relative one, and `cancel_on_set`
+    /// makes reads fail with `ECANCELED` once the realtime clock is set.
+    pub fn set(&self, new: ITimerValue, absolute: bool, cancel_on_set: bool) -> ITimerValue {
+        let old = self.get();
+        *self.cancel_seq.lock() =
+            (absolute && cancel_on_set && self.clock == TimerClock::Realtime).then(clock_set_seq);
+        self.expirations.store(0, Ordering::Release);
+
+        let expirations = self.expirations.clone();
+        let on_expire = move |missed| {
+            expirations.fetch_add(missed + 1, Ordering::AcqRel);
+        };
+        if absolute && self.clock == TimerClock::Realtime {
+            // follows the realtime clock when it is set
+            self.alarm
+                .set_realtime(new.remained_ns, new.interval_ns, on_expire);
+            return old;
+        }
+
+        let mut remained = new.remained_ns;
+        if absolute && remained != 0 {
+            // An expiration time in the past expires at once.
+            remained = remained.saturating_sub(monotonic_time_nanos()).max(1);
+        }
+        let deadline = match remained {
+            0 => 0,
+            remained => monotonic_time_nanos() + remained,
+        };
+        self.alarm.set(deadline, new.interval_ns, on_expire);
+        old
+    }
+}
+
+impl FileLike for TimerFd {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        if buf.len() < size_of::<u64>() {
+            return Err(LinuxError::EINVAL);
+        }
+        loop {
+            if self.is_canceled() {
+                return Err(LinuxError::ECANCELED);
+            }
+            let expirations = self.expirations.swap(0, Ordering::AcqRel);
+            if expirations > 0 {
+                buf[..size_of::<u64>()].copy_from_slice(&expirations.to_ne_bytes());
+                return Ok(size_of::<u64>());
+            }
+            if self.is_non_block() {
+                return Err(LinuxError::EAGAIN);
+            }
+            task_yield_interruptable()?;
+        }
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn status(&self) -> LinuxResult<Metadata> {
+        Ok(Metadata {
+            inode: self.inode,
+            n_link: 1,
+            mode: NodePermission::OWNER_READ | NodePermission::OWNER_WRITE,
+            ..Default::default()
+        })
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: self.expirations.load(Ordering::Acquire) > 0 || self.is_canceled(),
+            writable: false,
+        })
+    }
+
+    fn get_flags(&self) -> FileFlags {
+        *self.file_flags.lock()
+    }
+
+    fn set_flags(&self, flags: FileFlags) {
+        *self.file_flags.lock() = flags;
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+}
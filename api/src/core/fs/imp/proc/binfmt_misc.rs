@@ -0,0 +1,136 @@
+//! `/proc/sys/fs/binfmt_misc`, which registers interpreters for file formats.
+
+use crate::core::fs::pseudo::dir::PseudoDirOps;
+use crate::core::fs::pseudo::dynamic::{DirMaker, DynNodeOps, DynamicDir, DynamicFs};
+use crate::core::fs::pseudo::file::{SimpleFile, SimpleFileOps};
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use starry_core::binfmt_misc;
+use undefined_vfs::types::{NodePermission, NodeType};
+use undefined_vfs::{VfsError, VfsResult};
+
+/// What can be written to `status` or to the file of an entry.
+enum Command {
+    Disable,
+    Enable,
+    Remove,
+}
+
+fn parse_command(data: &[u8]) -> VfsResult<Command> {
+    match core::str::from_utf8(data)
+        .map_err(|_| VfsError::EINVAL)?
+        .trim()
+    {
+        "0" => Ok(Command::Disable),
+        "1" => Ok(Command::Enable),
+        "-1" => Ok(Command::Remove),
+        _ => Err(VfsError::EINVAL),
+    }
+}
+
+/// The write-only `register` file.
+struct Register;
+
+impl SimpleFileOps for Register {
+    fn read_all(&self) -> VfsResult<Cow<[u8]>> {
+        Ok(Cow::Borrowed(&[]))
+    }
+
+    fn write_all(&self, data: &[u8]) -> VfsResult<()> {
+        let rule = core::str::from_utf8(data).map_err(|_| VfsError::EINVAL)?;
+        binfmt_misc::register(rule)?;
+        Ok(())
+    }
+}
+
+/// The `status` file, which enables or disables all entries at once.
+struct Status;
+
+impl SimpleFileOps for Status {
+    fn read_all(&self) -> VfsResult<Cow<[u8]>> {
+        let status: &[u8] = if binfmt_misc::is_enabled() {
+            b"enabled\n"
+        } else {
+            b"disabled\n"
+        };
+        Ok(Cow::Borrowed(status))
+    }
+
+    fn write_all(&self, data: &[u8]) -> VfsResult<()> {
+        match parse_command(data)? {
+            Command::Disable => binfmt_misc::set_enabled(false),
+            Command::Enable => binfmt_misc::set_enabled(true),
+            Command::Remove => binfmt_misc::clear_entries(),
+        }
+        Ok(())
+    }
+}
+
+/// The file of a registered entry.
+struct Entry(String);
+
+impl SimpleFileOps for Entry {
+    fn read_all(&self) -> VfsResult<Cow<[u8]>> {
+        let entry = binfmt_misc::get_entry(&self.0).ok_or(VfsError::ENOENT)?;
+        Ok(Cow::Owned(entry.status().into_bytes()))
+    }
+
+    fn write_all(&self, data: &[u8]) -> VfsResult<()> {
+        let entry = binfmt_misc::get_entry(&self.0).ok_or(VfsError::ENOENT)?;
+        match parse_command(data)? {
+            Command::Disable => entry.set_enabled(false),
+            Command::Enable => entry.set_enabled(true),
+            Command::Remove => {
+                binfmt_misc::remove_entry(&self.0);
+            }
+        }
+        Ok(())
+    }
+}
+
+struct EntryDir {
+    fs: Arc<DynamicFs>,
+}
+
+impl PseudoDirOps for EntryDir {
+    fn list_children<'a>(&'a self) -> Box<dyn Iterator<Item = Cow<'a, str>> + 'a> {
+        Box::new(binfmt_misc::entry_names().into_iter().map(Cow::Owned))
+    }
+
+    fn get_child(&self, name: &str) -> VfsResult<DynNodeOps> {
+        let entry = binfmt_misc::get_entry(name).ok_or(VfsError::ENOENT)?;
+        Ok(SimpleFile::create(
+            self.fs.clone(),
+            NodeType::RegularFile,
+            NodePermission::OWNER_READ | NodePermission::OWNER_WRITE,
+            Entry(entry.name().into()),
+        )
+        .into())
+    }
+}
+
+pub(super) fn binfmt_misc_builder(fs: Arc<DynamicFs>) -> DirMaker {
+    let mut root = DynamicDir::builder(fs.clone());
+    root.add(
+        "register",
+        SimpleFile::create(
+            fs.clone(),
+            NodeType::RegularFile,
+            NodePermission::OWNER_WRITE,
+            Register,
+        ),
+    );
+    root.add(
+        "status",
+        SimpleFile::create(
+            fs.clone(),
+            NodeType::RegularFile,
+            NodePermission::OWNER_READ | NodePermission::OWNER_WRITE,
+            Status,
+        ),
+    );
+    root.set_pseudo_ops(EntryDir { fs });
+    root.build()
+}
@@ -1,14 +1,19 @@
+mod binfmt_misc;
 mod process;
+mod sysctl;
 mod task_stat;
 
 use crate::core::file::pipe::PIPE_MAX_SIZE;
+use crate::core::fs::imp::proc::binfmt_misc::binfmt_misc_builder;
 use crate::core::fs::imp::proc::process::ProcessInfoDir;
+use crate::core::fs::imp::proc::sysctl::sysctl;
 use crate::core::fs::pseudo::dynamic::{DirMaker, DynamicDir, DynamicFs};
 use crate::core::fs::pseudo::file::SimpleFile;
 use alloc::format;
 use alloc::string::ToString;
 use alloc::sync::Arc;
 use axsync::RawMutex;
+use starry_core::aslr;
 use starry_core::task::current_process;
 use undefined_vfs::fs::Filesystem;
 use undefined_vfs::types::{NodePermission, NodeType};
@@ -662,6 +667,7 @@ fn builder(fs: Arc<DynamicFs>) -> DirMaker {
         "lease-break-time",
         SimpleFile::new(fs.clone(), || LEASE_BREAK_TIME.to_string()),
     );
+    FS.add("binfmt_misc", binfmt_misc_builder(fs.clone()));
     kernel.add(
         "pid_max",
         SimpleFile::new(fs.clone(), || PID_MAX.to_string()),
@@ -673,10 +679,25 @@ fn builder(fs: Arc<DynamicFs>) -> DirMaker {
         "core_pattern",
         SimpleFile::new(fs.clone(), || CORE_PATTERN.to_string()),
     );
+    kernel.add(
+        "randomize_va_space",
+        sysctl(
+            fs.clone(),
+            aslr::randomize_va_space,
+            aslr::set_randomize_va_space,
+        ),
+    );
+    // '/proc/sys/vm'
+    let mut vm = DynamicDir::builder(fs.clone());
+    vm.add(
+        "mmap_rnd_bits",
+        sysctl(fs.clone(), aslr::mmap_rnd_bits, aslr::set_mmap_rnd_bits),
+    );
     // '/proc/sys'
     let mut sys = DynamicDir::builder(fs.clone());
     sys.add("kernel", kernel.build());
     sys.add("fs", FS.build());
+    sys.add("vm", vm.build());
     root.add("sys", sys.build());
     let mut sysvipc = DynamicDir::builder(fs.clone());
     sysvipc.add("shm", SimpleFile::new(fs.clone(), || EMPTY));
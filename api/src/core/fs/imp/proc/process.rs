@@ -48,7 +48,17 @@ fn process_info_builder(fs: Arc<DynamicFs>, pid: Pid) -> Option<DirMaker> {
     );
 
     // status
-    root.add("status", SimpleFile::new(fs.clone(), || DUMMY_STATUS));
+    let data = process_data.clone();
+    root.add(
+        "status",
+        SimpleFile::new(fs.clone(), move || {
+            let locked = data.addr_space().lock().locked_size();
+            DUMMY_STATUS.replace(
+                "VmLck:         0 kB",
+                &format!("VmLck: {:>9} kB", locked / 1024),
+            )
+        }),
+    );
 
     // maps
     root.add("maps", SimpleFile::new(fs.clone(), || DUMMY_MAPS));
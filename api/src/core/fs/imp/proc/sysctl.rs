@@ -0,0 +1,43 @@
+//! Tunable numbers in `/proc/sys`.
+
+use crate::core::fs::pseudo::dynamic::DynamicFs;
+use crate::core::fs::pseudo::file::{SimpleFile, SimpleFileOps};
+use alloc::borrow::Cow;
+use alloc::format;
+use alloc::sync::Arc;
+use axerrno::LinuxResult;
+use undefined_vfs::{VfsError, VfsResult};
+
+struct Sysctl<G, S> {
+    get: G,
+    set: S,
+}
+
+impl<G, S> SimpleFileOps for Sysctl<G, S>
+where
+    G: Fn() -> usize + Send + Sync,
+    S: Fn(usize) -> LinuxResult<()> + Send + Sync,
+{
+    fn read_all(&self) -> VfsResult<Cow<[u8]>> {
+        Ok(Cow::Owned(format!("{}\n", (self.get)()).into_bytes()))
+    }
+
+    fn write_all(&self, data: &[u8]) -> VfsResult<()> {
+        let value = core::str::from_utf8(data)
+            .map_err(|_| VfsError::EINVAL)?
+            .trim()
+            .parse()
+            .map_err(|_| VfsError::EINVAL)?;
+        (self.set)(value)?;
+        Ok(())
+    }
+}
+
+/// A file holding a number, which is validated by `set` when written.
+pub(super) fn sysctl(
+    fs: Arc<DynamicFs>,
+    get: impl Fn() -> usize + Send + Sync + 'static,
+    set: impl Fn(usize) -> LinuxResult<()> + Send + Sync + 'static,
+) -> Arc<SimpleFile> {
+    SimpleFile::new(fs, Sysctl { get, set })
+}
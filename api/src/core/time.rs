@@ -1,6 +1,7 @@
 use axerrno::{LinuxError, LinuxResult};
 use axhal::time::NANOS_PER_SEC;
 use core::time::Duration;
+use starry_core::itimer::ITimerValue;
 
 /// Nanosecond-precision timeout specification, equivalent to C's `struct timespec`.
 #[repr(C)]
@@ -31,6 +32,34 @@ impl TimeSpec {
     }
 }
 
+/// Interval timer specification, equivalent to C's `struct itimerspec`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct ITimerSpec {
+    /// The reload value, zero for a one-shot timer
+    pub interval: TimeSpec,
+    /// The time until the next expiration, zero if the timer is disarmed
+    pub value: TimeSpec,
+}
+
+impl ITimerSpec {
+    pub fn to_itimer_value(&self) -> LinuxResult<ITimerValue> {
+        Ok(ITimerValue {
+            interval_ns: self.interval.to_duration()?.as_nanos() as u64,
+            remained_ns: self.value.to_duration()?.as_nanos() as u64,
+        })
+    }
+}
+
+impl From<ITimerValue> for ITimerSpec {
+    fn from(value: ITimerValue) -> Self {
+        ITimerSpec {
+            interval: Duration::from_nanos(value.interval_ns).into(),
+            value: Duration::from_nanos(value.remained_ns).into(),
+        }
+    }
+}
+
 // 注意：尝试转换不合法的时间会返回默认值
 impl From<TimeSpec> for Duration {
     fn from(ts: TimeSpec) -> Self {
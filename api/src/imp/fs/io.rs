@@ -8,7 +8,7 @@ use core::cmp::min;
 
 pub fn sys_truncate_impl(file: &File, length: u64) -> LinuxResult<isize> {
     // set file size to length
-    file.inner().resize(length)?;
+    file.resize(length)?;
     Ok(0)
 }
 
@@ -26,7 +26,7 @@ pub fn sys_pwrite_impl(file: &File, buf: &[u8], offset: isize) -> LinuxResult<is
     if offset < 0 {
         return Err(LinuxError::EINVAL);
     }
-    let write_len = file.inner().write_at(buf, offset as _).map_err(|e| {
+    let write_len = file.write_at(buf, offset as _).map_err(|e| {
         if e == LinuxError::EACCES {
             LinuxError::EBADF
         } else {
@@ -40,7 +40,7 @@ pub fn sys_pread_impl(file: &File, buf: &mut [u8], offset: isize) -> LinuxResult
     if offset < 0 {
         return Err(LinuxError::EINVAL);
     }
-    let read_len = file.inner().read_at(buf, offset as _).map_err(|e| {
+    let read_len = file.read_at(buf, offset as _).map_err(|e| {
         if e == LinuxError::EACCES {
             LinuxError::EBADF
         } else {
@@ -101,9 +101,9 @@ pub fn sys_copy_file_range_impl(
         let buffer = &mut buf[..expected_len];
         let read_len = match &in_file {
             FileWrapper::FileLike(in_file) => in_file.read(buffer)?,
-            FileWrapper::FileLikeSeekable(in_file) => in_file
-                .inner()
-                .read_at(buffer, (*in_offset + transferred) as _)?,
+            FileWrapper::FileLikeSeekable(in_file) => {
+                in_file.read_at(buffer, (*in_offset + transferred) as _)?
+            }
         };
 
         if read_len == 0 {
@@ -111,9 +111,9 @@ pub fn sys_copy_file_range_impl(
         }
         let write_len = match &out_file {
             FileWrapper::FileLike(out_file) => out_file.write(&buffer[..read_len])?,
-            FileWrapper::FileLikeSeekable(out_file) => out_file
-                .inner()
-                .write_at(&buffer[..read_len], (*out_offset + transferred) as _)?,
+            FileWrapper::FileLikeSeekable(out_file) => {
+                out_file.write_at(&buffer[..read_len], (*out_offset + transferred) as _)?
+            }
         };
 
         transferred += write_len;
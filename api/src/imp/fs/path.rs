@@ -10,6 +10,7 @@ use linux_raw_sys::general::{
     AT_SYMLINK_FOLLOW, AT_SYMLINK_NOFOLLOW, R_OK, RENAME_EXCHANGE, RENAME_NOREPLACE,
     RENAME_WHITEOUT, W_OK, X_OK,
 };
+use starry_core::page_cache;
 use undefined_vfs::types::{MetadataUpdate, NodePermission, NodeType};
 
 bitflags! {
@@ -39,8 +40,13 @@ pub fn sys_rename_impl(
             return Err(LinuxError::EEXIST);
         } else {
             // needn't unlink the old path
+            let replaced = new_path.metadata()?;
             let parent = new_path.parent().ok_or(LinuxError::EINVAL)?;
+            let moved = old_path.metadata()?;
             parent.rename(old_path.name(), &parent, new_path.name())?;
+            if (replaced.device, replaced.inode) != (moved.device, moved.inode) {
+                page_cache::unlinked(&replaced);
+            }
         }
     } else {
         parent.rename(old_path.name(), &new_path, new_name.as_str())?;
@@ -87,9 +93,11 @@ pub fn sys_unlink_impl(
         if flags.contains(UnlinkFlags::NO_REMOVE_FILE) {
             return Err(LinuxError::ENOTDIR);
         }
+        let metadata = path.metadata()?;
         path.parent()
             .ok_or(LinuxError::EPERM)?
             .unlink(path.name(), false)?;
+        page_cache::unlinked(&metadata);
     } else {
         // other types of files, like symlink, socket, etc.
         return Err(LinuxError::EPERM);
@@ -1,14 +1,30 @@
 use axerrno::LinuxResult;
+use memory_addr::VirtAddr;
+use starry_core::resource::ResourceLimitType;
 use starry_core::task::current_process_data;
 use syscall_trace::syscall_trace;
 
+/// Move the program break to `addr`, returns the new one, or the old one if
+/// it cannot be moved.
 #[syscall_trace]
 pub fn sys_brk(addr: usize) -> LinuxResult<isize> {
-    let mut return_val: isize = current_process_data().get_heap_top() as isize;
-    let heap_bottom = current_process_data().get_heap_bottom();
-    if addr != 0 && addr >= heap_bottom && addr <= heap_bottom + axconfig::plat::USER_HEAP_SIZE {
-        current_process_data().set_heap_top(addr);
-        return_val = addr as isize;
+    let process_data = current_process_data();
+    let (data_limit, as_limit) = {
+        let limits = process_data.resource_limits.lock();
+        (
+            limits.get_soft(&ResourceLimitType::DATA),
+            limits.get_soft(&ResourceLimitType::AS),
+        )
+    };
+    let aspace = process_data.addr_space();
+    let mut aspace = aspace.lock();
+    if addr != 0
+        && let Err(err) = aspace.set_brk(VirtAddr::from(addr), data_limit, as_limit)
+    {
+        debug!(
+            "[sys_brk] failed to move the break to {:#x}: {:?}",
+            addr, err
+        );
     }
-    Ok(return_val)
+    Ok(aspace.brk().as_usize() as isize)
 }
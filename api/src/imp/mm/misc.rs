@@ -1,7 +1,86 @@
-use axerrno::LinuxResult;
+use crate::ptr::{PtrWrapper, UserOutPtr};
+use alloc::vec::Vec;
+use axerrno::{LinuxError, LinuxResult};
+use axhal::paging::PageSize;
+use linux_raw_sys::general::{
+    MADV_COLD, MADV_DODUMP, MADV_DOFORK, MADV_DONTDUMP, MADV_DONTFORK, MADV_DONTNEED, MADV_FREE,
+    MADV_HUGEPAGE, MADV_MERGEABLE, MADV_NOHUGEPAGE, MADV_NORMAL, MADV_PAGEOUT, MADV_RANDOM,
+    MADV_SEQUENTIAL, MADV_UNMERGEABLE, MADV_WILLNEED,
+};
+use memory_addr::{PAGE_SIZE_4K, VirtAddr, VirtAddrRange, is_aligned_4k};
+use starry_core::task::current_process_data;
 use syscall_trace::syscall_trace;
 
+/// Give advice about the use of the memory in the range.
+///
+/// The hints about the access pattern, transparent huge pages, which are not
+/// supported, merging, reclaim and core dumps are accepted, but change
+/// nothing.
 #[syscall_trace]
-pub fn sys_madvise(addr: usize, len: usize, flags: usize) -> LinuxResult<isize> {
+pub fn sys_madvise(addr: usize, length: usize, advice: u32) -> LinuxResult<isize> {
+    if !is_aligned_4k(addr) {
+        return Err(LinuxError::EINVAL);
+    }
+    let length = memory_addr::align_up_4k(length);
+    if length == 0 {
+        return Ok(0);
+    }
+
+    let current = current_process_data();
+    let aspace = current.addr_space();
+    let mut aspace = aspace.lock();
+    let start_addr = VirtAddr::from(addr);
+    // the whole range has to be mapped
+    let range = VirtAddrRange::from_start_size(start_addr, length);
+    if aspace
+        .find_free_area(start_addr, PAGE_SIZE_4K, range, PageSize::Size4K)
+        .is_some()
+    {
+        return Err(LinuxError::ENOMEM);
+    }
+    match advice {
+        MADV_DONTNEED => aspace.discard(start_addr, length)?,
+        MADV_FREE => aspace.free(start_addr, length)?,
+        MADV_WILLNEED => aspace.prefetch(start_addr, length)?,
+        MADV_DONTFORK => aspace.set_dont_fork(start_addr, length, true),
+        MADV_DOFORK => aspace.set_dont_fork(start_addr, length, false),
+        MADV_NORMAL | MADV_RANDOM | MADV_SEQUENTIAL | MADV_HUGEPAGE | MADV_NOHUGEPAGE
+        | MADV_MERGEABLE | MADV_UNMERGEABLE | MADV_COLD | MADV_PAGEOUT | MADV_DONTDUMP
+        | MADV_DODUMP => {}
+        _ => return Err(LinuxError::EINVAL),
+    }
+    if matches!(advice, MADV_DONTNEED | MADV_FREE) {
+        axhal::arch::flush_tlb(None);
+    }
+    Ok(0)
+}
+
+/// Report whether the pages in the range are in memory, one byte per page.
+#[syscall_trace]
+pub fn sys_mincore(addr: usize, length: usize, vec: UserOutPtr<u8>) -> LinuxResult<isize> {
+    if !is_aligned_4k(addr) {
+        return Err(LinuxError::EINVAL);
+    }
+    let length = memory_addr::align_up_4k(length);
+    let pages = length / PAGE_SIZE_4K;
+    let vec = vec.get_as_mut_slice(pages)?;
+
+    let current = current_process_data();
+    let aspace = current.addr_space();
+    let aspace = aspace.lock();
+    let start_addr = VirtAddr::from(addr);
+    let range = VirtAddrRange::from_start_size(start_addr, length);
+    if aspace
+        .find_free_area(start_addr, PAGE_SIZE_4K, range, PageSize::Size4K)
+        .is_some()
+    {
+        return Err(LinuxError::ENOMEM);
+    }
+    let residency: Vec<u8> = (0..pages)
+        .map(|index| aspace.is_resident(start_addr + index * PAGE_SIZE_4K) as u8)
+        .collect();
+    // the vector may fault, which needs the address space
+    drop(aspace);
+    vec.copy_from_slice(&residency);
     Ok(0)
 }
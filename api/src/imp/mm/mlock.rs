@@ -0,0 +1,88 @@
+use axerrno::{LinuxError, LinuxResult};
+use axhal::paging::PageSize;
+use linux_raw_sys::general::{MCL_CURRENT, MCL_FUTURE, MCL_ONFAULT, MLOCK_ONFAULT};
+use memory_addr::{MemoryAddr, PAGE_SIZE_4K, VirtAddr, VirtAddrRange};
+use starry_core::aspace::UserAddrSpace;
+use starry_core::resource::ResourceLimitType;
+use starry_core::task::current_process_data;
+use syscall_trace::syscall_trace;
+
+/// The page-aligned range covering `length` bytes at `addr`, which has to be
+/// mapped as a whole.
+fn mapped_range(aspace: &UserAddrSpace, addr: usize, length: usize) -> LinuxResult<VirtAddrRange> {
+    let start = VirtAddr::from(addr).align_down_4k();
+    let end = VirtAddr::from(addr + length).align_up_4k();
+    let range = VirtAddrRange::new(start, end);
+    if aspace
+        .find_free_area(start, PAGE_SIZE_4K, range, PageSize::Size4K)
+        .is_some()
+    {
+        return Err(LinuxError::ENOMEM);
+    }
+    Ok(range)
+}
+
+fn memlock_limit() -> u64 {
+    current_process_data()
+        .resource_limits
+        .lock()
+        .get_soft(&ResourceLimitType::MEMLOCK)
+}
+
+#[syscall_trace]
+pub fn sys_mlock(addr: usize, length: usize) -> LinuxResult<isize> {
+    sys_mlock2(addr, length, 0)
+}
+
+/// Lock the pages in the range in memory, loading them right away unless
+/// `MLOCK_ONFAULT` is set.
+#[syscall_trace]
+pub fn sys_mlock2(addr: usize, length: usize, flags: u32) -> LinuxResult<isize> {
+    if flags & !MLOCK_ONFAULT != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let limit = memlock_limit();
+    let current = current_process_data();
+    let aspace = current.addr_space();
+    let mut aspace = aspace.lock();
+    let range = mapped_range(&aspace, addr, length)?;
+    aspace.lock(range.start, range.size(), flags & MLOCK_ONFAULT != 0, limit)?;
+    Ok(0)
+}
+
+#[syscall_trace]
+pub fn sys_munlock(addr: usize, length: usize) -> LinuxResult<isize> {
+    let current = current_process_data();
+    let aspace = current.addr_space();
+    let mut aspace = aspace.lock();
+    let range = mapped_range(&aspace, addr, length)?;
+    aspace.unlock(range.start, range.size());
+    Ok(0)
+}
+
+/// Lock all the current mappings with `MCL_CURRENT`, and the ones made from
+/// now on with `MCL_FUTURE`.
+#[syscall_trace]
+pub fn sys_mlockall(flags: u32) -> LinuxResult<isize> {
+    if flags & !(MCL_CURRENT | MCL_FUTURE | MCL_ONFAULT) != 0
+        || flags & (MCL_CURRENT | MCL_FUTURE) == 0
+    {
+        return Err(LinuxError::EINVAL);
+    }
+    let limit = memlock_limit();
+    let current = current_process_data();
+    let aspace = current.addr_space();
+    aspace.lock().lock_all(
+        flags & MCL_CURRENT != 0,
+        flags & MCL_FUTURE != 0,
+        flags & MCL_ONFAULT != 0,
+        limit,
+    )?;
+    Ok(0)
+}
+
+#[syscall_trace]
+pub fn sys_munlockall() -> LinuxResult<isize> {
+    current_process_data().addr_space().lock().unlock_all();
+    Ok(0)
+}
@@ -7,17 +7,22 @@ use crate::{
     ptr::{PtrWrapper, UserPtr},
     syscall_instrument,
 };
-use alloc::vec;
 use axerrno::{LinuxError, LinuxResult};
+use axfs_ng::api::FileFlags;
 use axhal::paging::{MappingFlags, PageSize};
 use core::cmp::min;
 use linux_raw_sys::general::{
-    MAP_ANONYMOUS, MAP_FIXED, MAP_FIXED_NOREPLACE, MAP_HUGE_1GB, MAP_HUGE_2MB, MAP_HUGETLB,
-    MAP_NORESERVE, MAP_PRIVATE, MAP_SHARED, MAP_STACK, PROT_EXEC, PROT_GROWSDOWN, PROT_GROWSUP,
+    MAP_ANONYMOUS, MAP_FIXED, MAP_FIXED_NOREPLACE, MAP_GROWSDOWN, MAP_HUGE_1GB, MAP_HUGE_2MB,
+    MAP_HUGETLB, MAP_NORESERVE, MAP_PRIVATE, MAP_SHARED, MAP_STACK, MREMAP_DONTUNMAP, MREMAP_FIXED,
+    MREMAP_MAYMOVE, MS_ASYNC, MS_INVALIDATE, MS_SYNC, PROT_EXEC, PROT_GROWSDOWN, PROT_GROWSUP,
     PROT_READ, PROT_WRITE,
 };
 use macro_rules_attribute::apply;
-use memory_addr::{MemoryAddr, PhysAddr, VirtAddr, VirtAddrRange, align_up};
+use memory_addr::{
+    MemoryAddr, PAGE_SIZE_4K, PhysAddr, VirtAddr, VirtAddrRange, align_up, is_aligned_4k,
+};
+use starry_core::page_cache;
+use starry_core::resource::ResourceLimitType;
 use starry_core::task::current_process_data;
 use syscall_trace::syscall_trace;
 
@@ -76,6 +81,8 @@ bitflags::bitflags! {
         const MAP_NORESERVE = MAP_NORESERVE;
         /// Allocation is for a stack.
         const MAP_STACK = MAP_STACK;
+        /// The mapping grows down as the memory below it is accessed.
+        const MAP_GROWSDOWN = MAP_GROWSDOWN;
         /// Huge page
         const HUGETLB = MAP_HUGETLB;
         /// Huge page 2m size
@@ -95,7 +102,8 @@ pub fn sys_mmap(
     offset: isize,
 ) -> LinuxResult<isize> {
     let current = current_process_data();
-    let mut aspace = current.addr_space.lock();
+    let aspace = current.addr_space();
+    let mut aspace = aspace.lock();
     let permission_flags = MmapProt::from_bits_truncate(prot);
     let map_flags = MmapFlags::from_bits_truncate(flags);
 
@@ -126,6 +134,21 @@ pub fn sys_mmap(
 
     let aligned_length = align_up(length, page_size.into());
 
+    let (memlock_limit, as_limit, data_limit) = {
+        let limits = current.resource_limits.lock();
+        (
+            limits.get_soft(&ResourceLimitType::MEMLOCK),
+            limits.get_soft(&ResourceLimitType::AS),
+            limits.get_soft(&ResourceLimitType::DATA),
+        )
+    };
+    // the mappings made after `mlockall(MCL_FUTURE)` are locked
+    if aspace.lock_future().is_some()
+        && (aspace.locked_size() + aligned_length) as u64 > memlock_limit
+    {
+        return Err(LinuxError::EAGAIN);
+    }
+
     let addr = VirtAddr::from(addr);
     let start_addr = if map_flags.intersects(MmapFlags::MAP_FIXED | MmapFlags::MAP_FIXED_NOREPLACE)
     {
@@ -141,15 +164,25 @@ pub fn sys_mmap(
         // currently we find free area in the whole address space
         // in Linux, the boundary is above or equal to the value specified by `/proc/sys/vm/mmap_min_addr`
         let range = VirtAddrRange::new(aspace.base(), aspace.end());
-        let addr = addr.align_down(page_size);
+        let addr = match addr.as_usize() {
+            0 => aspace.mmap_base().align_up(page_size),
+            _ => addr.align_down(page_size),
+        };
         aspace
             .find_free_area(addr, length, range, page_size)
             .or(aspace.find_free_area(aspace.base(), length, range, page_size))
             .ok_or(LinuxError::ENOMEM)?
     };
 
-    let populate = fd > 0 && !map_flags.contains(MmapFlags::MAP_ANONYMOUS);
-    let writeable = permission_flags.contains(MmapProt::PROT_WRITE) && populate;
+    let file_backed = !map_flags.contains(MmapFlags::MAP_ANONYMOUS);
+
+    // private writable memory other than a stack is counted against
+    // `RLIMIT_DATA` as well
+    let data = !map_flags.intersects(MmapFlags::MAP_SHARED | MmapFlags::MAP_GROWSDOWN)
+        && permission_flags.contains(MmapProt::PROT_WRITE);
+    if !aspace.may_expand(aligned_length, data, as_limit, data_limit) {
+        return Err(LinuxError::ENOMEM);
+    }
 
     fn try_get_device_memory(fd: FileDescriptor) -> Option<DeviceMem> {
         let device = get_device_by_fd(fd)?;
@@ -157,7 +190,7 @@ pub fn sys_mmap(
     }
 
     let map_permission: MappingFlags = permission_flags.into();
-    if populate && let Some(device_memory) = try_get_device_memory(fd) {
+    if file_backed && let Some(device_memory) = try_get_device_memory(fd) {
         // If the file is a device, we can use the device memory directly.
         let phys_addr = PhysAddr::from(device_memory.physical_addr);
         aspace.map_linear(
@@ -182,38 +215,43 @@ pub fn sys_mmap(
         return Ok(start_addr.as_usize() as _);
     }
 
-    if map_flags.contains(MmapFlags::MAP_SHARED) {
-        // TODO: 仅在MAP_ANONYMOUS时才zero
-        aspace.map_shared(start_addr, aligned_length, map_permission, true, page_size)?;
-    } else {
-        aspace.map_alloc(
+    if file_backed {
+        // The pages are the ones cached for the file, written back to it if
+        // the mapping is shared, or copied on write otherwise.
+        let file = File::from_fd(fd)?;
+        let file_flags = file.get_flags();
+        let shared = map_flags.contains(MmapFlags::MAP_SHARED);
+        if !file_flags.contains(FileFlags::READ)
+            || (shared
+                && permission_flags.contains(MmapProt::PROT_WRITE)
+                && !file_flags.contains(FileFlags::WRITE))
+        {
+            return Err(LinuxError::EACCES);
+        }
+        if offset < 0 || offset as usize % PAGE_SIZE_4K != 0 || page_size != PageSize::Size4K {
+            return Err(LinuxError::EINVAL);
+        }
+        let cached_file = page_cache::open(file.inner().location().clone())?;
+        aspace.map_file(
             start_addr,
             aligned_length,
             map_permission,
-            populate,
-            page_size,
+            cached_file,
+            offset as usize / PAGE_SIZE_4K,
+            start_addr + aligned_length,
+            shared,
         )?;
+    } else if map_flags.contains(MmapFlags::MAP_SHARED) {
+        // the pages are always 4K, like the ones of a file
+        aspace.map_shared(start_addr, aligned_length, map_permission)?;
+    } else if map_flags.contains(MmapFlags::MAP_GROWSDOWN) && page_size == PageSize::Size4K {
+        aspace.map_stack(start_addr, aligned_length, map_permission, false)?;
+    } else {
+        aspace.map_alloc(start_addr, aligned_length, map_permission, false, page_size)?;
     }
-
-    if populate {
-        let file = File::from_fd(fd)?;
-        let file_size = file.status()?.size as usize;
-
-        if writeable {
-            error!(
-                "we don't support PROT_WRITE for mmap with fd yet. file: {}.",
-                file.inner().location().absolute_path()?.as_str()
-            );
-        }
-        let mut file = file.inner();
-        if offset < 0 || offset as usize >= file_size {
-            return Err(LinuxError::EINVAL);
-        }
-        let offset = offset as usize;
-        let length = min(length, file_size - offset);
-        let mut buf = vec![0u8; length];
-        file.read_at(&mut buf, offset as _)?;
-        aspace.write(start_addr, page_size, &buf)?;
+    if aspace.lock_future() == Some(false) {
+        aspace.populate_area(start_addr, aligned_length, MappingFlags::empty())?;
+        current.usage.own.update_maxrss(aspace.resident_size());
     }
     Ok(start_addr.as_usize() as _)
 }
@@ -224,7 +262,8 @@ pub fn sys_munmap(addr: UserPtr<usize>, length: usize) -> LinuxResult<isize> {
     let addr = unsafe { addr.get_unchecked() };
 
     let current = current_process_data();
-    let mut aspace = current.addr_space.lock();
+    let aspace = current.addr_space();
+    let mut aspace = aspace.lock();
     let length = memory_addr::align_up_4k(length);
     let start_addr = VirtAddr::from(addr as usize);
     aspace.unmap(start_addr, length)?;
@@ -237,19 +276,155 @@ pub fn sys_mprotect(addr: UserInPtr<usize>, length: usize, prot: u32) -> LinuxRe
     // Safety: addr is used for mapping, and we won't directly access it.
     let addr = unsafe { addr.get_unchecked() };
 
-    // TODO: implement PROT_GROWSUP & PROT_GROWSDOWN
     let Some(permission_flags) = MmapProt::from_bits(prot) else {
         return Err(LinuxError::EINVAL);
     };
-    if permission_flags.contains(MmapProt::PROT_GROWDOWN | MmapProt::PROT_GROWSUP) {
+    // there are no mappings growing up
+    if permission_flags.contains(MmapProt::PROT_GROWSUP) {
         return Err(LinuxError::EINVAL);
     }
 
     let current = current_process_data();
-    let mut aspace = current.addr_space.lock();
-    let length = memory_addr::align_up_4k(length);
-    let start_addr = VirtAddr::from(addr as usize);
+    let aspace = current.addr_space();
+    let mut aspace = aspace.lock();
+    let mut length = memory_addr::align_up_4k(length);
+    let mut start_addr = VirtAddr::from(addr as usize);
+    // the change goes down to the start of the stack
+    if permission_flags.contains(MmapProt::PROT_GROWDOWN) {
+        let stack_start = aspace.stack_start(start_addr).ok_or(LinuxError::EINVAL)?;
+        length += start_addr - stack_start;
+        start_addr = stack_start;
+    }
     aspace.protect(start_addr, length, permission_flags.into())?;
 
     Ok(0)
 }
+
+bitflags::bitflags! {
+    /// flags for sys_msync
+    #[derive(Debug)]
+    struct MsyncFlags: u32 {
+        /// Schedule the write-back.
+        const MS_ASYNC = MS_ASYNC;
+        /// Invalidate the other mappings of the file.
+        const MS_INVALIDATE = MS_INVALIDATE;
+        /// Write back and wait for it.
+        const MS_SYNC = MS_SYNC;
+    }
+}
+
+/// Write the changes made through the shared file mappings in the range back
+/// to the files.
+///
+/// The write-back is always done at once, even with `MS_ASYNC`. The other
+/// shared mappings of a file are mapped to the same pages, so there is
+/// nothing to invalidate for `MS_INVALIDATE`.
+#[syscall_trace]
+pub fn sys_msync(addr: usize, length: usize, flags: u32) -> LinuxResult<isize> {
+    let Some(flags) = MsyncFlags::from_bits(flags) else {
+        return Err(LinuxError::EINVAL);
+    };
+    if flags.contains(MsyncFlags::MS_ASYNC | MsyncFlags::MS_SYNC) || !is_aligned_4k(addr) {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let current = current_process_data();
+    let aspace = current.addr_space();
+    let mut aspace = aspace.lock();
+    let start_addr = VirtAddr::from(addr);
+    let length = memory_addr::align_up_4k(length);
+    // the whole range has to be mapped
+    let range = VirtAddrRange::from_start_size(start_addr, length);
+    if aspace
+        .find_free_area(start_addr, PAGE_SIZE_4K, range, PageSize::Size4K)
+        .is_some()
+    {
+        return Err(LinuxError::ENOMEM);
+    }
+    aspace.sync(start_addr, length)?;
+    Ok(0)
+}
+
+bitflags::bitflags! {
+    /// flags for sys_mremap
+    #[derive(Debug)]
+    struct MremapFlags: u32 {
+        /// The mapping may be moved if it cannot grow in place.
+        const MREMAP_MAYMOVE = MREMAP_MAYMOVE;
+        /// Move the mapping to the given address, replacing what is there.
+        const MREMAP_FIXED = MREMAP_FIXED;
+        /// Leave the old range mapped, but empty.
+        const MREMAP_DONTUNMAP = MREMAP_DONTUNMAP;
+    }
+}
+
+/// Resize a mapping, moving it if needed and allowed.
+///
+/// Creating another mapping of the same pages with an `old_size` of 0 is not
+/// supported.
+#[syscall_trace]
+pub fn sys_mremap(
+    old_addr: usize,
+    old_size: usize,
+    new_size: usize,
+    flags: u32,
+    new_addr: usize,
+) -> LinuxResult<isize> {
+    let Some(flags) = MremapFlags::from_bits(flags) else {
+        return Err(LinuxError::EINVAL);
+    };
+    let may_move = flags.contains(MremapFlags::MREMAP_MAYMOVE);
+    if !is_aligned_4k(old_addr)
+        || old_size == 0
+        || new_size == 0
+        || (flags.intersects(MremapFlags::MREMAP_FIXED | MremapFlags::MREMAP_DONTUNMAP)
+            && !may_move)
+        || (flags.contains(MremapFlags::MREMAP_DONTUNMAP) && old_size != new_size)
+    {
+        return Err(LinuxError::EINVAL);
+    }
+    let old_size = memory_addr::align_up_4k(old_size);
+    let new_size = memory_addr::align_up_4k(new_size);
+    let old_start = VirtAddr::from(old_addr);
+
+    let new_start = if flags.contains(MremapFlags::MREMAP_FIXED) {
+        let new_start = VirtAddr::from(new_addr);
+        if !is_aligned_4k(new_addr)
+            || VirtAddrRange::from_start_size(old_start, old_size)
+                .overlaps(VirtAddrRange::from_start_size(new_start, new_size))
+        {
+            return Err(LinuxError::EINVAL);
+        }
+        Some(new_start)
+    } else {
+        None
+    };
+
+    let current = current_process_data();
+    let (as_limit, data_limit) = {
+        let limits = current.resource_limits.lock();
+        (
+            limits.get_soft(&ResourceLimitType::AS),
+            limits.get_soft(&ResourceLimitType::DATA),
+        )
+    };
+    let aspace = current.addr_space();
+    let mut aspace = aspace.lock();
+    if new_size > old_size {
+        let data = aspace.is_data_at(old_start);
+        if !aspace.may_expand(new_size - old_size, data, as_limit, data_limit) {
+            return Err(LinuxError::ENOMEM);
+        }
+    }
+    let start_addr = aspace.remap(
+        old_start,
+        old_size,
+        new_size,
+        may_move,
+        new_start,
+        flags.contains(MremapFlags::MREMAP_DONTUNMAP),
+    )?;
+    axhal::arch::flush_tlb(None);
+    current.usage.own.update_maxrss(aspace.resident_size());
+    Ok(start_addr.as_usize() as _)
+}
@@ -1,7 +1,9 @@
 mod brk;
 mod misc;
+mod mlock;
 mod mmap;
 
 pub use self::brk::*;
 pub use self::misc::*;
+pub use self::mlock::*;
 pub use self::mmap::*;
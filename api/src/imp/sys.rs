@@ -1,6 +1,9 @@
 use axerrno::LinuxResult;
+use core::sync::atomic::Ordering;
+use starry_core::task::current_process_data;
+use syscall_trace::syscall_trace;
 
-use crate::ptr::{PtrWrapper, UserPtr};
+use crate::ptr::{PtrWrapper, UserOutPtr, UserPtr, nullable};
 
 const OS_NAME: &str = "UndefinedOS";
 const OS_VERSION: &str = "10.0.0";
@@ -46,3 +49,29 @@ pub fn sys_uname(name: UserPtr<UtsName>) -> LinuxResult<isize> {
     unsafe { *name.get()? = UtsName::default() };
     Ok(0)
 }
+
+/// Set the execution domain of the process, or only query it if `persona` is
+/// `0xffffffff`. Returns the old one.
+///
+/// Only `ADDR_NO_RANDOMIZE` has an effect, on the next `execve`.
+#[syscall_trace]
+pub fn sys_personality(persona: u32) -> LinuxResult<isize> {
+    let personality = &current_process_data().personality;
+    let old = match persona {
+        0xffff_ffff => personality.load(Ordering::Acquire),
+        persona => personality.swap(persona, Ordering::AcqRel),
+    };
+    Ok(old as isize)
+}
+
+/// Get the CPU and the NUMA node the thread is running on, the latter is
+/// always 0.
+pub fn sys_getcpu(cpu: UserOutPtr<u32>, node: UserOutPtr<u32>) -> LinuxResult<isize> {
+    if let Some(cpu) = nullable!(cpu.get_as_mut_ref())? {
+        *cpu = axhal::cpu::this_cpu_id() as u32;
+    }
+    if let Some(node) = nullable!(node.get_as_mut_ref())? {
+        *node = 0;
+    }
+    Ok(0)
+}
@@ -1,20 +1,28 @@
-use crate::core::file::fd::FD_TABLE;
+use crate::core::file::fd::{FD_TABLE, FdFlags, fd_add};
+use crate::core::file::pidfd::PidFd;
+use crate::core::file::stub::StubFd;
+use crate::interface::user::identity::sys_getuid;
+use crate::ptr::UserOutPtr;
 use alloc::string::ToString;
 use alloc::sync::Arc;
 use axerrno::{LinuxError, LinuxResult};
-use axfs_ng::api::FS_CONTEXT;
+use axfs_ng::api::{FS_CONTEXT, FileFlags};
 use axhal::arch::UspaceContext;
 use axsignal::Signo;
 use axtask::current;
 use bitflags::bitflags;
+use core::ffi::c_int;
 use core::sync::atomic::Ordering;
 use linux_raw_sys::general::*;
 use spin::Mutex;
 use starry_core::mm::copy_from_kernel;
-use starry_core::process::{ProcessData, create_thread_data, get_process_data};
+use starry_core::process::{ProcessData, VforkDone, create_thread_data, get_process_data};
+use starry_core::resource::ResourceLimitType;
 use starry_core::task::{
-    TaskExt, create_user_task, current_process, current_process_data, read_trapframe_from_kstack,
+    TaskExt, count_context_switch, create_user_task, current_process, current_process_data,
+    read_trapframe_from_kstack,
 };
+use undefined_process::process::get_all_processes;
 
 bitflags! {
     /// Options for use with [`sys_clone`].
@@ -32,6 +40,9 @@ bitflags! {
         /// The calling process and the child process share the same table
         /// of signal handlers.
         const SIGHAND = CLONE_SIGHAND;
+        /// A pidfd referring to the child process is allocated in the
+        /// parent's file descriptor table.
+        const PIDFD = CLONE_PIDFD;
         /// If the calling process is being traced, then trace the child
         /// also.
         const PTRACE = CLONE_PTRACE;
@@ -84,9 +95,27 @@ pub fn sys_clone_impl(
     new_sp: usize,
     tls: usize,
     addr_child_tid: usize,
+    addr_pidfd: UserOutPtr<c_int>,
     exit_signal: Option<Signo>,
 ) -> LinuxResult<isize> {
     debug!("[sys_clone_impl] clone with flags: {clone_flags:?}, exit_signal: {exit_signal:?})");
+    // check the pidfd location before anything is created
+    let addr_pidfd = if clone_flags.contains(CloneFlags::PIDFD) {
+        Some(addr_pidfd.get_as_mut_ref()?)
+    } else {
+        None
+    };
+    // the processes of the real user are counted, all of them as there are no
+    // others, and like on Linux root is not limited
+    if !clone_flags.contains(CloneFlags::THREAD) && sys_getuid()? != 0 {
+        let nproc_limit = current_process_data()
+            .resource_limits
+            .lock()
+            .get_soft(&ResourceLimitType::NPROC);
+        if get_all_processes().len() as u64 >= nproc_limit {
+            return Err(LinuxError::EAGAIN);
+        }
+    }
     // duplicate trap frame
     let trap_frame = read_trapframe_from_kstack(current().get_kernel_stack_top().unwrap());
     let mut new_uctx = UspaceContext::from(&trap_frame);
@@ -111,11 +140,14 @@ pub fn sys_clone_impl(
     let name = current().name().to_string() + "_";
     let mut new_task = create_user_task(name, new_uctx);
 
+    // the pidfd is allocated before the child is, so that nothing can fail
+    // once the child exists, and is filled in after
+    let mut pidfd = None;
     // init task extended data
     let (thread, thread_data) = if clone_flags.contains(CloneFlags::THREAD) {
         // create thread
         // clone address space
-        let page_table = current_process_data().addr_space.lock().page_table_root();
+        let page_table = current_process_data().addr_space().lock().page_table_root();
         new_task.ctx_mut().set_page_table_root(page_table);
 
         let thread = current_process().create_thread();
@@ -129,19 +161,19 @@ pub fn sys_clone_impl(
         // create process
         // construct process data
         // address space
-        let addr_space =
-            if clone_flags.contains(CloneFlags::VM) && !clone_flags.contains(CloneFlags::VFORK) {
-                // create another reference to the same address space
-                // we clone the `Arc` itself rather than the data
-                current_process_data().addr_space.clone()
-            } else {
-                // clone the address space
-                let addr_space = &current_process_data().addr_space;
-                let mut addr_space = addr_space.lock();
-                let mut new_addr_space = addr_space.try_clone()?;
-                copy_from_kernel(&mut new_addr_space)?;
-                Arc::new(Mutex::new(new_addr_space))
-            };
+        let addr_space = if clone_flags.contains(CloneFlags::VM) {
+            // create another reference to the same address space
+            // we clone the `Arc` itself rather than the data
+            // a vfork child borrows it until it calls execve or exits
+            current_process_data().addr_space()
+        } else {
+            // clone the address space
+            let addr_space = current_process_data().addr_space();
+            let mut addr_space = addr_space.lock();
+            let mut new_addr_space = addr_space.try_clone()?;
+            copy_from_kernel(&mut new_addr_space)?;
+            Arc::new(Mutex::new(new_addr_space))
+        };
         let page_table = addr_space.lock().page_table_root();
         new_task.ctx_mut().set_page_table_root(page_table);
         // parent
@@ -157,6 +189,9 @@ pub fn sys_clone_impl(
         } else {
             Arc::default()
         };
+        if addr_pidfd.is_some() {
+            pidfd = Some(fd_add(Arc::new(StubFd::new()), FdFlags::CLOSE_ON_EXEC)?);
+        }
         // fork new process
         let new_process = parent.fork();
         let new_thread = new_process.get_main_thread().unwrap();
@@ -166,8 +201,12 @@ pub fn sys_clone_impl(
             signal_actions,
             exit_signal,
         );
-        process_data.set_heap_bottom(current_process_data().get_heap_bottom());
-        process_data.set_heap_top(current_process_data().get_heap_top());
+        process_data.personality.store(
+            current_process_data().personality.load(Ordering::Acquire),
+            Ordering::Release,
+        );
+        *process_data.resource_limits.lock() =
+            current_process_data().resource_limits.lock().clone();
         let thread_data = create_thread_data(Arc::new(process_data), new_thread.get_tid());
 
         (new_thread, thread_data)
@@ -180,9 +219,14 @@ pub fn sys_clone_impl(
             .deref_from(&thread_data.namespace)
             .init_shared(FD_TABLE.share());
     } else {
+        let fd_table = FD_TABLE.copy_inner();
+        // the pidfd is only the parent's
+        if let Some(fd) = pidfd {
+            let _ = fd_table.remove(fd);
+        }
         FD_TABLE
             .deref_from(&thread_data.namespace)
-            .init_new(FD_TABLE.copy_inner());
+            .init_new(fd_table);
     }
 
     if clone_flags.contains(CloneFlags::FS) {
@@ -207,6 +251,22 @@ pub fn sys_clone_impl(
             .store(addr_child_tid, Ordering::Relaxed);
     }
 
+    if let (Some(addr_pidfd), Some(fd)) = (addr_pidfd, pidfd) {
+        let pidfd = Arc::new(PidFd::new(thread.get_process(), FileFlags::empty()));
+        // replacing the placeholder can not fail
+        FD_TABLE
+            .add_at(fd, pidfd, FdFlags::CLOSE_ON_EXEC)
+            .expect("the pidfd is allocated");
+        *addr_pidfd = fd;
+    }
+
+    // the parent is suspended until the vfork child releases its memory
+    let vfork_done = clone_flags.contains(CloneFlags::VFORK).then(|| {
+        let vfork_done = Arc::new(VforkDone::default());
+        *thread_data.vfork_done.lock() = Some(vfork_done.clone());
+        vfork_done
+    });
+
     // create `TaskExt`
     let tid = thread.get_tid();
     new_task.init_task_ext(TaskExt::new(thread, thread_data));
@@ -214,6 +274,13 @@ pub fn sys_clone_impl(
     // spawn the task
     axtask::spawn_task(new_task);
 
+    if let Some(vfork_done) = vfork_done {
+        count_context_switch(true);
+        // like Linux, a killed parent returns at once, and dies on its way
+        // back to user space
+        vfork_done.wait();
+    }
+
     // return the thread id of the new thread
     Ok(tid as _)
 }
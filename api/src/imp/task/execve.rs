@@ -1,56 +1,155 @@
-use crate::core::file::fd::FD_TABLE;
-use crate::utils::path::resolve_path_at_cwd;
-use alloc::string::String;
+use crate::core::file::FsLocation;
+use crate::core::file::fd::{FD_TABLE, close_all_file_like};
+use crate::imp::task::signal::send_signal_thread;
+use crate::utils::task::task_yield;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
 use alloc::vec::Vec;
-use axerrno::{AxError, LinuxResult};
-use axhal::arch::TrapFrame;
+use axerrno::{LinuxError, LinuxResult};
+use axfs_ng::api::FS_CONTEXT;
+use axhal::arch::UspaceContext;
+use axsignal::{SignalInfo, Signo};
 use axtask::current;
 use core::default::Default;
+use core::sync::atomic::Ordering;
+use linux_raw_sys::general::SI_KERNEL;
+use spin::Mutex;
+use starry_core::aslr::LayoutOffsets;
 use starry_core::mm;
 use starry_core::mm::map_trampoline;
-use starry_core::task::{current_process, current_process_data};
+use starry_core::page_cache::{self, CachedFile};
+use starry_core::process::{ThreadData, create_thread_data, get_thread_data};
+use starry_core::task::{
+    current_process_data, current_thread, current_thread_data, respawn_current_task,
+};
+use starry_core::vdso::map_vdso;
+use undefined_process::thread::Thread;
+use undefined_vfs::types::NodeType;
+
+/// Open the program to execute at `location`.
+fn open_program(location: FsLocation) -> LinuxResult<Arc<CachedFile>> {
+    match location.metadata()?.node_type {
+        NodeType::RegularFile => {}
+        // only left unresolved with `AT_SYMLINK_NOFOLLOW`
+        NodeType::Symlink => return Err(LinuxError::ELOOP),
+        _ => return Err(LinuxError::EACCES),
+    }
+    page_cache::open(location)
+}
 
 pub fn sys_execve_impl(
-    tf: &mut TrapFrame,
-    path: String,
+    location: FsLocation,
     args: Vec<String>,
     envs: Vec<String>,
 ) -> LinuxResult<isize> {
-    // we must check if the program exists before unmapping the address space
-    let _ = resolve_path_at_cwd(Some(&args[0]))?;
-
-    if current_process().get_threads().len() > 1 {
-        // TODO: kill other threads except leader thread
-        // because we need to unmap the address space
-        error!("[sys_execve] execve is not supported in multi-threaded process");
-    }
+    let path = location.absolute_path()?.to_string();
+    let file = open_program(location)?;
 
     debug!("[execve] args = {:?}, envs = {:?}", &args, &envs);
 
-    let process_data = current_process_data();
-
-    // clear address space
-    // TODO: create a new address space instead of clearing the current one
-    // because the current address space may be shared with other processes/threads
-    // e.g. VFORK
-    // and we can easily return to the old address space if execve fails
-    let addr_space = &process_data.addr_space;
-    let mut addr_space = addr_space.lock();
-    addr_space.unmap_user_areas()?;
+    // The program is loaded into a new address space, so that the old one is
+    // still there if it fails, and a vfork parent gets its memory back intact.
+    let mut addr_space = mm::new_user_aspace_empty()?;
+    addr_space.set_layout(LayoutOffsets::new(
+        current_process_data().personality.load(Ordering::Acquire),
+    ));
     // for signals
     map_trampoline(&mut addr_space)?;
-    axhal::arch::flush_tlb(None);
+    map_vdso(&mut addr_space)?;
 
     // load executable binary
     let (entry_point, user_stack_base) =
-        // TODO: 这里面的错误码可能需要更细化，上面检查过了不存在的情况，这里应该不会是这个问题了
-        mm::load_user_app(&mut addr_space, &args, &envs).map_err(|_| {
+        mm::load_user_app_file(&mut addr_space, &file, &args, &envs).inspect_err(|_| {
             error!("Failed to load app {}", path);
-            AxError::NotFound
         })?;
+    // copied last, so that a failed exec can simply drop the new one
+    mm::copy_from_kernel(&mut addr_space)?;
+
+    kill_other_threads()?;
+
+    let page_table_root = addr_space.page_table_root();
+    current_process_data().replace_addr_space(Arc::new(Mutex::new(addr_space)));
+    exec_reset(&path, args);
+    current_thread_data().vfork_release();
+
+    let (thread, thread_data) = if current_thread().is_main_thread() {
+        (current_thread(), current_thread_data())
+    } else {
+        take_over_leader()
+    };
+
+    // the page table of the running task cannot be switched, so the thread
+    // goes on in a new task on the new address space
+    let uctx = UspaceContext::new(entry_point.as_usize(), user_stack_base, 0);
+    drop((path, envs, file));
+    respawn_current_task(thread, thread_data, uctx, page_table_root)
+}
+
+/// Kill the other threads of the process and wait for them to exit.
+///
+/// It cannot fail once they are killed, unless the current thread is killed
+/// as well, e.g. by another thread calling `exit_group`, and exits with the
+/// signal on its way back to user space.
+fn kill_other_threads() -> LinuxResult<()> {
+    let thread = current_thread();
+    let process = thread.get_process();
+
+    let others: Vec<_> = process
+        .get_threads()
+        .into_iter()
+        .filter(|other| other.get_tid() != thread.get_tid())
+        .collect();
+    if others.is_empty() {
+        return Ok(());
+    }
+
+    let sig = SignalInfo::new(Signo::SIGKILL, SI_KERNEL);
+    for other in others {
+        if let Some(other_data) = get_thread_data(other.get_tid()) {
+            other_data.killed_by_exec.store(true, Ordering::Release);
+        }
+        let _ = send_signal_thread(other.get_tid(), sig.clone());
+    }
+    while process.get_threads().len() > 1 {
+        if current_thread_data().is_killed() {
+            return Err(LinuxError::EINTR);
+        }
+        task_yield();
+    }
+    Ok(())
+}
+
+/// Let the current thread, the last one of the process, take over the ID of
+/// the leader after the leader was killed by `execve`.
+///
+/// Returns the thread and its data to go on with.
+fn take_over_leader() -> (Arc<Thread>, Arc<ThreadData>) {
+    let thread = current_thread();
+    let thread_data = current_thread_data();
+
+    let leader = thread.get_process().take_over_leader(&thread);
+    let leader_data = create_thread_data(thread_data.process_data.clone(), leader.get_tid());
+    FD_TABLE
+        .deref_from(&leader_data.namespace)
+        .init_shared(FD_TABLE.share());
+    FS_CONTEXT
+        .deref_from(&leader_data.namespace)
+        .init_shared(FS_CONTEXT.share());
+    let blocked = thread_data.signal.with_blocked_mut(|blocked| *blocked);
+    leader_data
+        .signal
+        .with_blocked_mut(|leader_blocked| *leader_blocked = blocked);
+    // release the file descriptor table of the old thread
+    close_all_file_like();
+    (leader, leader_data)
+}
+
+/// Reset the process attributes that do not survive `execve`.
+fn exec_reset(path: &str, args: Vec<String>) {
+    let process_data = current_process_data();
 
     // set name and path
-    current().set_name(&path);
+    current().set_name(path);
     *process_data.command_line.lock() = args;
 
     // handle close on exec
@@ -60,10 +159,5 @@ pub fn sys_execve_impl(
     // TODO: reset signal dispositions, mmap, shm, etc.
     *process_data.signal.actions.lock() = Default::default();
     process_data.shared_memory.lock().clear();
-
-    // new user context
-    tf.set_ip(entry_point.as_usize());
-    tf.set_sp(user_stack_base.as_usize());
-
-    Ok(0)
+    process_data.posix_timers.clear();
 }
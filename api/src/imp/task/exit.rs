@@ -4,7 +4,7 @@ use crate::ptr::{PtrWrapper, UserPtr};
 use axsignal::{SignalInfo, Signo};
 use core::sync::atomic::Ordering;
 use linux_raw_sys::general::{SI_KERNEL, WEXITED};
-use starry_core::process::get_process_data;
+use starry_core::process::{exit_record, get_process_data};
 use starry_core::task::{
     current_process, current_process_data, current_thread, current_thread_data,
 };
@@ -43,9 +43,24 @@ pub fn sys_exit_impl(exit_code: u32, exit_signal: u32, exit_group: bool) -> ! {
             });
             axtask::yield_now();
         }
+        // the usage and the like are kept for the parent
+        let process_data = current_process_data();
+        let rss = process_data.addr_space().lock().resident_size();
+        process_data.usage.own.update_maxrss(rss);
+        process_data.keep_exit_record(current_process().get_pid());
+        // the memory is released, so a vfork parent can go on
+        current_thread_data().vfork_release();
+        if current_thread().is_main_thread() {
+            process_data.exit_done.set_exit_code(exit_code as _);
+        }
         current_thread().exit(exit_status as _);
         let process = current_process();
         if process.is_zombie() {
+            process_data.exit_done.complete();
+            // a parentless process is released at once, nobody will wait
+            if process.get_parent().is_none() {
+                exit_record(process.get_pid(), false);
+            }
             // threads have exited
             // send signals
             if let Some(parent) = process.get_parent() {
@@ -7,7 +7,7 @@ use linux_raw_sys::general::{
     FUTEX_BITSET_MATCH_ANY, FUTEX_CMD_MASK, FUTEX_CMP_REQUEUE, FUTEX_REQUEUE, FUTEX_WAIT,
     FUTEX_WAIT_BITSET, FUTEX_WAKE, FUTEX_WAKE_BITSET, timespec,
 };
-use starry_core::task::current_process_data;
+use starry_core::task::{count_context_switch, current_process_data, wait_killable};
 use syscall_trace::syscall_trace;
 
 fn new_futex() -> Arc<WaitQueue> {
@@ -40,10 +40,11 @@ pub fn sys_futex(
                 .or_insert_with(new_futex)
                 .clone();
 
+            count_context_switch(true);
             if !timeout.is_null() {
                 wq.wait_timeout(timespec_to_timevalue(*timeout.get_as_ref()?), false);
-            } else {
-                wq.wait();
+            } else if wait_killable(&wq) {
+                return Err(LinuxError::EINTR);
             }
 
             Ok(0)
@@ -106,10 +107,11 @@ pub fn sys_futex(
                 .or_insert_with(new_futex)
                 .clone();
 
+            count_context_switch(true);
             if !timeout.is_null() {
                 wq.wait_timeout(timespec_to_timevalue(*timeout.get_as_ref()?), true);
-            } else {
-                wq.wait();
+            } else if wait_killable(&wq) {
+                return Err(LinuxError::EINTR);
             }
             Ok(0)
         }
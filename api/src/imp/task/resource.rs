@@ -1,7 +1,13 @@
 use axerrno::{LinuxError, LinuxResult};
+use axhal::time::{NANOS_PER_MICROS, NANOS_PER_SEC};
+use core::sync::atomic::Ordering;
+use linux_raw_sys::general::{
+    __kernel_old_timeval, RUSAGE_CHILDREN, RUSAGE_SELF, RUSAGE_THREAD, rusage,
+};
 use starry_core::process::*;
 use starry_core::resource::{ResourceLimit, ResourceLimitType};
-use starry_core::task::current_process_data;
+use starry_core::rusage::Usage;
+use starry_core::task::{current_process_data, current_thread_data};
 use undefined_process::Pid;
 
 pub fn sys_setrlimit_impl(
@@ -25,6 +31,11 @@ pub fn sys_setrlimit_impl(
     if !limits.set(resource, limit.clone()) {
         return Err(LinuxError::EINVAL); // soft > hard
     }
+    if matches!(resource, ResourceLimitType::CPU) {
+        process_data
+            .cpu_limit_check_secs
+            .store(0, Ordering::Relaxed);
+    }
     Ok(0)
 }
 
@@ -38,3 +49,46 @@ pub fn sys_getrlimit_impl(resource: &ResourceLimitType, pid: Pid) -> LinuxResult
     };
     Ok(process_data.resource_limits.lock().get(resource))
 }
+
+/// Convert a [`Usage`] to the `rusage` of Linux.
+pub fn usage_to_rusage(usage: &Usage) -> rusage {
+    fn nanos_to_timeval(ns: u64) -> __kernel_old_timeval {
+        __kernel_old_timeval {
+            tv_sec: (ns / NANOS_PER_SEC) as _,
+            tv_usec: (ns % NANOS_PER_SEC / NANOS_PER_MICROS) as _,
+        }
+    }
+    // Safety: all zeros is a valid `rusage`.
+    let mut rusage: rusage = unsafe { core::mem::zeroed() };
+    rusage.ru_utime = nanos_to_timeval(usage.utime_ns);
+    rusage.ru_stime = nanos_to_timeval(usage.stime_ns);
+    rusage.ru_maxrss = (usage.maxrss / 1024) as _;
+    rusage.ru_minflt = usage.minflt as _;
+    rusage.ru_majflt = usage.majflt as _;
+    rusage.ru_nvcsw = usage.nvcsw as _;
+    rusage.ru_nivcsw = usage.nivcsw as _;
+    rusage
+}
+
+/// The resource usage of the current process, of its children which are
+/// waited for, or of the current thread, depending on `who`.
+pub fn sys_getrusage_impl(who: i32) -> LinuxResult<Usage> {
+    debug!("[sys_getrusage_impl] get usage of: {who}");
+
+    let process_data = current_process_data();
+    let usage = &process_data.usage;
+    if who != RUSAGE_CHILDREN {
+        let rss = process_data.addr_space().lock().resident_size();
+        usage.own.update_maxrss(rss);
+    }
+    match who {
+        RUSAGE_CHILDREN => Ok(usage.children.get()),
+        who if who == RUSAGE_SELF as i32 => Ok(usage.own.get()),
+        who if who == RUSAGE_THREAD as i32 => Ok(Usage {
+            // the resident set is the one of the process
+            maxrss: usage.own.get().maxrss,
+            ..current_thread_data().usage.get()
+        }),
+        _ => Err(LinuxError::EINVAL),
+    }
+}
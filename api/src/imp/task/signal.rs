@@ -1,11 +1,16 @@
 use arceos_posix_api::ctypes::timespec;
 use axerrno::{LinuxError, LinuxResult};
 use core::any::Any;
+use core::ffi::c_ulong;
+use core::sync::atomic::Ordering;
 use core::{mem, time::Duration};
 use linux_raw_sys::general::{
-    MINSIGSTKSZ, SI_TKILL, SI_USER, SIG_BLOCK, SIG_SETMASK, SIG_UNBLOCK, kernel_sigaction, siginfo,
+    CLD_CONTINUED, CLD_STOPPED, MINSIGSTKSZ, SA_NOCLDSTOP, SI_TKILL, SI_USER, SIG_BLOCK,
+    SIG_SETMASK, SIG_UNBLOCK, SIGRTMIN, kernel_sigaction, siginfo,
 };
 
+use crate::core::file::fd::FileLike;
+use crate::core::file::pidfd::PidFd;
 use crate::ptr::{PtrWrapper, UserConstPtr, UserPtr};
 
 use crate::imp::task::sys_exit_impl;
@@ -14,19 +19,37 @@ use axhal::{
     trap::{POST_TRAP, register_trap_handler},
 };
 use axsignal::{SignalInfo, SignalOSAction, SignalSet, SignalStack, Signo};
-use starry_core::process::{get_process_data, get_thread_data};
-use starry_core::task::{current_process, current_process_data, current_thread_data};
+use starry_core::process::{ProcessData, get_process_data, get_thread_data};
+use starry_core::resource::ResourceLimitType;
+use starry_core::task::{
+    count_context_switch, current_process, current_process_data, current_thread_data,
+    time_stat_on_user_trap,
+};
+use starry_core::vdso;
 use syscall_trace::syscall_trace;
 use undefined_process::Pid;
-use undefined_process::process::get_all_processes;
+use undefined_process::process::{get_all_processes, get_process};
 use undefined_process::process_group::get_process_group;
 use undefined_process::thread::get_thread;
 
+/// Block the current thread while its process is stopped.
+fn wait_while_stopped() {
+    let process_data = current_process_data();
+    if process_data.job.is_stopped() {
+        count_context_switch(true);
+        process_data.job.wait_while_stopped();
+    }
+}
+
 pub fn check_signals(tf: &mut TrapFrame, restore_blocked: Option<SignalSet>) -> bool {
+    // the other threads of a stopped process stop as well
+    wait_while_stopped();
+
     let signal = &current_thread_data().signal;
     let Some((sig, os_action)) = signal.check_signals(tf, restore_blocked) else {
         return false;
     };
+    current_process_data().posix_timers.delivered(&sig);
 
     let signo = sig.signo();
     const CORE_DUMP: u32 = 0x80;
@@ -37,7 +60,10 @@ pub fn check_signals(tf: &mut TrapFrame, restore_blocked: Option<SignalSet>) ->
                 signo,
                 sig.code()
             );
-            sys_exit_impl(0, signo as u32, true);
+            // a thread killed by `execve` in another thread exits on its own
+            let exit_group = !(signo == Signo::SIGKILL
+                && current_thread_data().killed_by_exec.load(Ordering::Acquire));
+            sys_exit_impl(0, signo as u32, exit_group);
         }
         SignalOSAction::CoreDump => {
             // TODO: implement core dump
@@ -46,19 +72,27 @@ pub fn check_signals(tf: &mut TrapFrame, restore_blocked: Option<SignalSet>) ->
                 signo,
                 sig.code()
             );
-            sys_exit_impl(0, CORE_DUMP + signo as u32, true);
+            // no core is dumped beyond `RLIMIT_CORE`, e.g. when it is 0
+            let core_limit = current_process_data()
+                .resource_limits
+                .lock()
+                .get_soft(&ResourceLimitType::CORE);
+            let core_dump = if core_limit > 0 { CORE_DUMP } else { 0 };
+            sys_exit_impl(0, core_dump + signo as u32, true);
         }
         SignalOSAction::Stop => {
-            // TODO: implement stop
             debug!(
                 "[signal] handle signal: signo: {:?}, code: {}, os_action: Stop",
                 signo,
                 sig.code()
             );
-            sys_exit_impl(0, signo as u32, true);
+            if current_process_data().job.stop(signo) {
+                notify_parent(current_process().get_pid(), CLD_STOPPED);
+            }
+            wait_while_stopped();
         }
         SignalOSAction::Continue => {
-            // TODO: implement continue
+            // already continued when it was sent, see `wake_stopped`
         }
         SignalOSAction::Handler => {
             // do nothing
@@ -73,17 +107,62 @@ fn post_trap_callback(tf: &mut TrapFrame, from_user: bool) {
         return;
     }
 
+    time_stat_on_user_trap();
+    vdso::tick();
     check_signals(tf, None);
 }
 
-fn check_sigset_size(size: usize) -> LinuxResult<()> {
+pub(crate) fn check_sigset_size(size: usize) -> LinuxResult<()> {
     if size != size_of::<SignalSet>() {
         return Err(LinuxError::EINVAL);
     }
     Ok(())
 }
 
-fn parse_signo(signo: u32) -> LinuxResult<Signo> {
+/// Read an optional signal mask passed by `ppoll`, `pselect6` or `epoll_pwait`.
+pub(crate) fn read_sigmask(
+    set: UserConstPtr<SignalSet>,
+    sigsetsize: usize,
+) -> LinuxResult<Option<SignalSet>> {
+    set.nullable(|set| {
+        check_sigset_size(sigsetsize)?;
+        Ok(unsafe { *set.get()? })
+    })
+}
+
+/// Install `mask` as the signal mask of the current thread while running `f`,
+/// and restore the original mask afterwards.
+///
+/// If a signal handler is set up during the wait, the original mask is saved in
+/// the signal frame (see `task_yield_interruptable`) and restored by
+/// `rt_sigreturn`, so it must not be restored here.
+pub fn with_sigmask<R>(
+    mask: Option<SignalSet>,
+    f: impl FnOnce() -> LinuxResult<R>,
+) -> LinuxResult<R> {
+    let Some(mut mask) = mask else {
+        return f();
+    };
+    mask.remove(Signo::SIGKILL);
+    mask.remove(Signo::SIGSTOP);
+
+    let thr_data = current_thread_data();
+    let old_blocked = thr_data
+        .signal
+        .with_blocked_mut(|blocked| mem::replace(blocked, mask));
+    *thr_data.saved_sigmask.lock() = Some(old_blocked);
+
+    let result = f();
+
+    if let Some(old_blocked) = thr_data.saved_sigmask.lock().take() {
+        thr_data
+            .signal
+            .with_blocked_mut(|blocked| *blocked = old_blocked);
+    }
+    result
+}
+
+pub(crate) fn parse_signo(signo: u32) -> LinuxResult<Signo> {
     Signo::from_repr(signo as u8).ok_or(LinuxError::EINVAL)
 }
 
@@ -149,16 +228,103 @@ pub fn sys_rt_sigpending(set: UserPtr<SignalSet>, sigsetsize: usize) -> LinuxRes
     Ok(0)
 }
 
+/// Tell the parent of the process `pid` that it has stopped or continued,
+/// with `SIGCHLD` unless the parent asks not to with `SA_NOCLDSTOP`.
+fn notify_parent(pid: Pid, code: u32) {
+    let Some(parent) = get_process(pid).and_then(|process| process.get_parent()) else {
+        return;
+    };
+    let Some(parent_data) = get_process_data(parent.get_pid()) else {
+        return;
+    };
+    // Safety: all zeros is a valid `kernel_sigaction`.
+    let mut action: kernel_sigaction = unsafe { mem::zeroed() };
+    parent_data.signal.actions.lock()[Signo::SIGCHLD].to_ctype(&mut action);
+    if action.sa_flags & SA_NOCLDSTOP as c_ulong == 0 {
+        parent_data
+            .signal
+            .send_signal(SignalInfo::new(Signo::SIGCHLD, code));
+    }
+    parent_data.child_exit_wq.notify_all(false);
+}
+
+/// Continue the stopped process `pid` as soon as `SIGCONT` is sent to it, or
+/// wake it up to be killed by `SIGKILL`, even if its threads are stopped or
+/// blocked in a killable wait.
+///
+/// `SIGCONT` also discards the pending stop signals of the process, and a
+/// stop signal the pending `SIGCONT`, so that only the last one counts.
+fn wake_stopped(pid: Pid, process_data: &ProcessData, signo: Signo) {
+    match signo {
+        Signo::SIGCONT => {
+            discard_signals(pid, stop_signals());
+            if process_data.job.resume(true) {
+                notify_parent(pid, CLD_CONTINUED);
+            }
+        }
+        Signo::SIGSTOP | Signo::SIGTSTP | Signo::SIGTTIN | Signo::SIGTTOU => {
+            let mut set = SignalSet::default();
+            set.add(Signo::SIGCONT);
+            discard_signals(pid, set);
+        }
+        Signo::SIGKILL => {
+            process_data.job.resume(false);
+            if let Some(process) = get_process(pid) {
+                for thread in process.get_threads() {
+                    if let Some(thread_data) = get_thread_data(thread.get_tid()) {
+                        thread_data.wake_killable();
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn stop_signals() -> SignalSet {
+    let mut set = SignalSet::default();
+    for signo in [
+        Signo::SIGSTOP,
+        Signo::SIGTSTP,
+        Signo::SIGTTIN,
+        Signo::SIGTTOU,
+    ] {
+        set.add(signo);
+    }
+    set
+}
+
+/// Remove the signals in `set` pending for the process `pid` or any of its
+/// threads.
+fn discard_signals(pid: Pid, set: SignalSet) {
+    let Some(process) = get_process(pid) else {
+        return;
+    };
+    for thread in process.get_threads() {
+        if let Some(thread_data) = get_thread_data(thread.get_tid()) {
+            // dequeues the signals pending for the process as well
+            while thread_data.signal.dequeue_signal(&set).is_some() {}
+        }
+    }
+}
+
 pub fn send_signal_thread(tid: Pid, sig: SignalInfo) -> LinuxResult<()> {
     info!("Send signal {:?} to thread {}", sig.signo(), tid);
     let thread_data = get_thread_data(tid).ok_or(LinuxError::EPERM)?;
+    let signo = sig.signo();
     thread_data.signal.send_signal(sig);
+    if let Some(thread) = get_thread(tid) {
+        let pid = thread.get_process().get_pid();
+        wake_stopped(pid, &thread_data.process_data, signo);
+    }
     Ok(())
 }
 pub fn send_signal_process(pid: Pid, sig: SignalInfo) -> LinuxResult<()> {
     info!("Send signal {:?} to process {}", sig.signo(), pid);
     let process_data = get_process_data(pid).ok_or(LinuxError::EPERM)?;
+    let signo = sig.signo();
     process_data.signal.send_signal(sig);
+    wake_stopped(pid, &process_data, signo);
     Ok(())
 }
 pub fn send_signal_process_group(pgid: Pid, sig: SignalInfo) -> usize {
@@ -249,6 +415,31 @@ fn check_thread(tid: Pid, tgid: Pid) -> LinuxResult<()> {
     Ok(())
 }
 
+/// The number of signals pending for all the processes and threads.
+///
+/// Only whether a signal is pending is known, not how many times a real-time
+/// one is queued, so each pending signal is counted once.
+fn pending_signal_count() -> usize {
+    fn count(pending: SignalSet) -> usize {
+        (1..=64)
+            .filter_map(Signo::from_repr)
+            .filter(|&signo| pending.has(signo))
+            .count()
+    }
+    let mut total = 0;
+    for process in get_all_processes() {
+        if let Some(process_data) = get_process_data(process.get_pid()) {
+            total += count(process_data.signal.pending());
+        }
+        for thread in process.get_threads() {
+            if let Some(thread_data) = get_thread_data(thread.get_tid()) {
+                total += count(thread_data.signal.pending());
+            }
+        }
+    }
+    total
+}
+
 fn make_queue_signal_info(
     tgid: Pid,
     signo: u32,
@@ -260,6 +451,17 @@ fn make_queue_signal_info(
     if sig.code() != SI_USER && current_process().get_pid() != tgid {
         return Err(LinuxError::EPERM);
     }
+    // like Linux, only the real-time signals queued with their information
+    // fail beyond `RLIMIT_SIGPENDING`
+    if signo as u32 >= SIGRTMIN && sig.code() != SI_USER {
+        let limit = current_process_data()
+            .resource_limits
+            .lock()
+            .get_soft(&ResourceLimitType::SIGPENDING);
+        if pending_signal_count() as u64 >= limit {
+            return Err(LinuxError::EAGAIN);
+        }
+    }
     Ok(sig)
 }
 
@@ -291,6 +493,33 @@ pub fn sys_rt_tgsigqueueinfo(
     Ok(0)
 }
 
+/// Send a signal to the process referred to by a pidfd.
+#[syscall_trace]
+pub fn sys_pidfd_send_signal(
+    pidfd: i32,
+    signo: u32,
+    sig: UserConstPtr<SignalInfo>,
+    flags: u32,
+) -> LinuxResult<isize> {
+    if flags != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let process = PidFd::from_fd(pidfd)?.process().clone();
+    if process.is_zombie() {
+        return Err(LinuxError::ESRCH);
+    }
+    let pid = process.get_pid();
+    let sig = match sig.nullable(|_| Ok(()))? {
+        None => make_siginfo(signo, SI_USER)?,
+        Some(()) if signo == 0 => None,
+        Some(()) => Some(make_queue_signal_info(pid, signo, sig)?),
+    };
+    if let Some(sig) = sig {
+        send_signal_process(pid, sig)?;
+    }
+    Ok(0)
+}
+
 pub fn sys_rt_sigreturn(tf: &mut TrapFrame) -> LinuxResult<isize> {
     current_thread_data().signal.restore(tf);
     Ok(tf.retval() as isize)
@@ -320,6 +549,7 @@ pub fn sys_rt_sigtimedwait(
     let Some(sig) = current_thread_data().signal.wait_timeout(set, timeout) else {
         return Err(LinuxError::EAGAIN);
     };
+    current_process_data().posix_timers.delivered(&sig);
 
     if let Some(info) = info.nullable(UserPtr::get)? {
         unsafe { *info = sig.0 };
@@ -1,11 +1,21 @@
-use crate::ptr::{PtrWrapper, UserOutPtr};
+use crate::core::file::fd::FileLike;
+use crate::core::file::pidfd::PidFd;
+use crate::imp::task::resource::usage_to_rusage;
+use crate::ptr::{PtrWrapper, UserOutPtr, nullable};
 use alloc::{sync::Arc, vec::Vec};
 use axerrno::{LinuxError, LinuxResult};
+use axsignal::Signo;
 use bitflags::bitflags;
 use linux_raw_sys::general::{
-    __WALL, __WCLONE, __WNOTHREAD, WCONTINUED, WEXITED, WNOHANG, WNOWAIT, WUNTRACED,
+    __WALL, __WCLONE, __WNOTHREAD, CLD_CONTINUED, CLD_DUMPED, CLD_EXITED, CLD_KILLED, CLD_STOPPED,
+    P_ALL, P_PGID, P_PID, P_PIDFD, SIGCHLD, SIGCONT, WCONTINUED, WEXITED, WNOHANG, WNOWAIT,
+    WUNTRACED, rusage, siginfo,
+};
+use starry_core::process::{JobEvent, exit_record, get_process_data};
+use starry_core::rusage::Usage;
+use starry_core::task::{
+    count_context_switch, current_process, current_process_data, wait_killable,
 };
-use starry_core::task::{current_process, current_process_data};
 use syscall_trace::syscall_trace;
 use undefined_process::Pid;
 use undefined_process::process::Process;
@@ -55,13 +65,138 @@ impl WaitPid {
     }
 }
 
+/// The change of the state of a child which is reported.
+#[derive(Debug, Clone, Copy)]
+enum ChildState {
+    /// Exited or killed, with the status reported by `wait4`.
+    Exited(i32),
+    /// Stopped by the signal.
+    Stopped(Signo),
+    /// Continued by `SIGCONT`.
+    Continued,
+}
+
+impl ChildState {
+    /// The status reported by `wait4`.
+    fn status(&self) -> i32 {
+        match *self {
+            ChildState::Exited(status) => status,
+            ChildState::Stopped(signo) => ((signo as i32) << 8) | 0x7f,
+            ChildState::Continued => 0xffff,
+        }
+    }
+
+    /// The `si_code` and `si_status` reported by `waitid`.
+    fn code_and_status(&self) -> (u32, i32) {
+        match *self {
+            ChildState::Exited(status) => match status & 0x7f {
+                0 => (CLD_EXITED, (status >> 8) & 0xff),
+                signo if status & 0x80 != 0 => (CLD_DUMPED, signo),
+                signo => (CLD_KILLED, signo),
+            },
+            ChildState::Stopped(signo) => (CLD_STOPPED, signo as i32),
+            ChildState::Continued => (CLD_CONTINUED, SIGCONT as i32),
+        }
+    }
+}
+
+/// Whether `child` is a "clone" child, see [`ProcessData::is_clone_child`],
+/// which is recorded when it exits as its data may be released before.
+///
+/// [`ProcessData::is_clone_child`]: starry_core::process::ProcessData::is_clone_child
+fn is_clone_child(child: &Process) -> bool {
+    let pid = child.get_pid();
+    match get_process_data(pid) {
+        Some(process_data) => process_data.is_clone_child(),
+        None => exit_record(pid, true).is_some_and(|record| record.clone_child),
+    }
+}
+
+/// Wait for a child selected by `pid` to exit, stop or continue, as asked by
+/// `options`. The exited child is reaped, and the change is forgotten, unless
+/// `WNOWAIT` is given. Returns `None` if no child has changed yet and
+/// `WNOHANG` is given.
+///
+/// The resource usage of the child, including the one of its own children,
+/// is returned as well, and added to the one of the children of the current
+/// process when the child is reaped.
+fn wait_child(
+    pid: WaitPid,
+    options: &WaitOptions,
+) -> LinuxResult<Option<(Arc<Process>, ChildState, Usage)>> {
+    let process = current_process();
+    let process_data = current_process_data();
+    let keep = options.contains(WaitOptions::WNOWAIT);
+
+    let children = process
+        .get_children()
+        .into_iter()
+        .filter(|child| pid.apply(child))
+        .filter(|child| {
+            options.contains(WaitOptions::WALL)
+                || options.contains(WaitOptions::WCLONE) == is_clone_child(child)
+        })
+        .collect::<Vec<_>>();
+    if children.is_empty() {
+        return Err(LinuxError::ECHILD);
+    }
+
+    loop {
+        for child in &children {
+            if child.is_zombie() {
+                if !options.contains(WaitOptions::WEXITED) {
+                    continue;
+                }
+                let usage = exit_record(child.get_pid(), keep)
+                    .map(|record| record.usage.total())
+                    .unwrap_or_default();
+                if !keep {
+                    process_data.usage.children.add(&usage);
+                    child.release();
+                }
+                let state = ChildState::Exited(child.get_exit_code());
+                return Ok(Some((child.clone(), state, usage)));
+            }
+            let Some(child_data) = get_process_data(child.get_pid()) else {
+                continue;
+            };
+            // `WUNTRACED` is `WSTOPPED`
+            let wanted = |event| match event {
+                JobEvent::Stopped(_) => options.contains(WaitOptions::WUNTRACED),
+                JobEvent::Continued => options.contains(WaitOptions::WCONTINUED),
+            };
+            let state = match child_data.job.wait_event(wanted, keep) {
+                Some(JobEvent::Stopped(signo)) => ChildState::Stopped(signo),
+                Some(JobEvent::Continued) => ChildState::Continued,
+                None => continue,
+            };
+            return Ok(Some((child.clone(), state, child_data.usage.total())));
+        }
+        if options.contains(WaitOptions::WNOHANG) {
+            return Ok(None);
+        }
+        // signal
+        count_context_switch(true);
+        if wait_killable(&process_data.child_exit_wq) {
+            return Err(LinuxError::EINTR);
+        }
+    }
+}
+
 #[syscall_trace]
-pub fn sys_wait4(pid: i32, exit_code_ptr: UserOutPtr<i32>, options: u32) -> LinuxResult<isize> {
-    let options = WaitOptions::from_bits_truncate(options);
+pub fn sys_wait4(
+    pid: i32,
+    exit_code_ptr: UserOutPtr<i32>,
+    options: u32,
+    rusage_ptr: UserOutPtr<rusage>,
+) -> LinuxResult<isize> {
+    let options = WaitOptions::from_bits(options)
+        .filter(|options| !options.intersects(WaitOptions::WEXITED | WaitOptions::WNOWAIT))
+        .ok_or(LinuxError::EINVAL)?
+        | WaitOptions::WEXITED;
     info!("sys_waitpid <= pid: {:?}, options: {:?}", pid, options);
 
     let process = current_process();
-    let process_data = current_process_data();
 
     let pid = if pid == -1 {
         WaitPid::Any
@@ -73,39 +208,72 @@ pub fn sys_wait4(pid: i32, exit_code_ptr: UserOutPtr<i32>, options: u32) -> Linu
         WaitPid::Pgid(-pid as _)
     };
 
-    let children = process
-        .get_children()
-        .into_iter()
-        .filter(|child| pid.apply(child))
-        // .filter(|child| {
-        //     options.contains(WaitOptions::WALL)
-        //         || (options.contains(WaitOptions::WCLONE)
-        //             == get_process_data(child.get_pid()).is_some_and(|x| x.is_clone_child()))
-        // })
-        .collect::<Vec<_>>();
-    if children.is_empty() {
-        return Err(LinuxError::ECHILD);
+    let exit_code = exit_code_ptr.get();
+    let rusage = nullable!(rusage_ptr.get_as_mut_ref())?;
+    let Some((child, state, usage)) = wait_child(pid, &options)? else {
+        return Ok(0);
+    };
+    if let Some(rusage) = rusage {
+        *rusage = usage_to_rusage(&usage);
+    }
+    if let Ok(exit_code) = exit_code {
+        // high 8 bits are exit code, low 8 bits are signal number
+        unsafe {
+            *exit_code = state.status();
+        }
     }
+    Ok(child.get_pid() as _)
+}
 
-    let exit_code = exit_code_ptr.get();
-    loop {
-        if let Some(child) = children.iter().find(|child| child.is_zombie()) {
-            if !options.contains(WaitOptions::WNOWAIT) {
-                child.release();
-            }
-            if let Ok(exit_code) = exit_code {
-                // get_exit_code returns exit status of the child process
-                // high 8 bits are exit code, low 8 bits are signal number
-                unsafe {
-                    *exit_code = child.get_exit_code();
-                }
+/// Like `wait4`, but the child is selected by `idtype` and `id`, and its state
+/// is reported in a `siginfo`.
+#[syscall_trace]
+pub fn sys_waitid(
+    idtype: u32,
+    id: i32,
+    infop: UserOutPtr<siginfo>,
+    options: u32,
+    rusage_ptr: UserOutPtr<rusage>,
+) -> LinuxResult<isize> {
+    let options = WaitOptions::from_bits(options).ok_or(LinuxError::EINVAL)?;
+    info!("sys_waitid <= idtype: {idtype}, id: {id}, options: {options:?}");
+    // `WUNTRACED` is `WSTOPPED`
+    if !options.intersects(WaitOptions::WEXITED | WaitOptions::WUNTRACED | WaitOptions::WCONTINUED)
+    {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let pid = match idtype {
+        P_ALL => WaitPid::Any,
+        P_PID if id > 0 => WaitPid::Pid(id as _),
+        P_PGID if id == 0 => WaitPid::Pgid(current_process().get_group().get_pgid()),
+        P_PGID if id > 0 => WaitPid::Pgid(id as _),
+        P_PIDFD => WaitPid::Pid(PidFd::from_fd(id)?.process().get_pid()),
+        _ => return Err(LinuxError::EINVAL),
+    };
+
+    let infop = nullable!(infop.get_as_mut_ref())?;
+    let rusage = nullable!(rusage_ptr.get_as_mut_ref())?;
+    let child = wait_child(pid, &options)?;
+    if let Some(infop) = infop {
+        *infop = unsafe { core::mem::zeroed() };
+        if let Some((child, state, _)) = &child {
+            let (code, status) = state.code_and_status();
+            // Safety: the `_sigchld` fields are used for SIGCHLD.
+            unsafe {
+                let fields = &mut infop.__bindgen_anon_1.__bindgen_anon_1;
+                fields.si_signo = SIGCHLD as _;
+                fields.si_code = code as _;
+                fields._sifields._sigchld._pid = child.get_pid() as _;
+                // all the processes are of root
+                fields._sifields._sigchld._uid = 0;
+                fields._sifields._sigchld._status = status;
             }
-            return Ok(child.get_pid() as _);
-        } else if options.contains(WaitOptions::WNOHANG) {
-            return Ok(0);
-        } else {
-            // signal
-            process_data.child_exit_wq.wait();
         }
     }
+    if let Some(rusage) = rusage {
+        let usage = child.map(|(_, _, usage)| usage).unwrap_or_default();
+        *rusage = usage_to_rusage(&usage);
+    }
+    Ok(0)
 }
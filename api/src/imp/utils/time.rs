@@ -1,33 +1,88 @@
 use crate::core::time::TimeSpec;
-use crate::ptr::{PtrWrapper, UserOutPtr, UserPtr, nullable};
+use crate::ptr::{PtrWrapper, UserInPtr, UserOutPtr, UserPtr, nullable};
 use arceos_posix_api::{self as api, ctypes::timeval};
 use axerrno::{LinuxError, LinuxResult};
-use axhal::time::{NANOS_PER_SEC, monotonic_time_nanos, nanos_to_ticks};
-use linux_raw_sys::general::timezone;
-use starry_core::{ctypes::Tms, task::time_stat_output};
+use axhal::time::{NANOS_PER_MICROS, NANOS_PER_SEC, monotonic_time_nanos, nanos_to_ticks};
+use linux_raw_sys::general::{
+    __kernel_old_timeval, CLOCK_PROCESS_CPUTIME_ID, CLOCK_REALTIME, CLOCK_REALTIME_COARSE,
+    itimerval, timezone,
+};
+use starry_core::ctypes::{TimerType, Tms};
+use starry_core::itimer::ITimerValue;
+use starry_core::task::current_process_data;
+use starry_core::timer::{realtime_nanos, set_realtime};
 use syscall_trace::syscall_trace;
 
 pub fn sys_clock_gettime(clock_id: i32, tp: UserPtr<api::ctypes::timespec>) -> LinuxResult<isize> {
+    if clock_id == CLOCK_PROCESS_CPUTIME_ID as i32 {
+        let cpu_time_ns = current_process_data().posix_timers.cpu_time_ns();
+        *tp.get_as_mut_ref()? = api::ctypes::timespec {
+            tv_sec: (cpu_time_ns / NANOS_PER_SEC) as _,
+            tv_nsec: (cpu_time_ns % NANOS_PER_SEC) as _,
+        };
+        return Ok(0);
+    }
+    if matches!(clock_id as u32, CLOCK_REALTIME | CLOCK_REALTIME_COARSE) {
+        let now = realtime_nanos();
+        *tp.get_as_mut_ref()? = api::ctypes::timespec {
+            tv_sec: (now / NANOS_PER_SEC) as _,
+            tv_nsec: (now % NANOS_PER_SEC) as _,
+        };
+        return Ok(0);
+    }
     unsafe { Ok(api::sys_clock_gettime(clock_id, tp.get()?) as _) }
 }
 
+/// Set the realtime clock, the only one which can be set.
+#[syscall_trace]
+pub fn sys_clock_settime(clock_id: i32, tp: UserInPtr<TimeSpec>) -> LinuxResult<isize> {
+    if clock_id as u32 != CLOCK_REALTIME {
+        return Err(LinuxError::EINVAL);
+    }
+    let time = tp.get_as_ref()?;
+    if time.seconds < 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    set_realtime(time.to_duration()?.as_nanos() as u64);
+    Ok(0)
+}
+
 #[syscall_trace]
 pub fn sys_get_time_of_day(
     ts: UserOutPtr<timeval>,
     tz: UserOutPtr<timezone>,
 ) -> LinuxResult<isize> {
     nullable!(tz.get())?;
-    unsafe { Ok(api::sys_get_time_of_day(ts.get()?) as _) }
+    let now = realtime_nanos();
+    *ts.get_as_mut_ref()? = timeval {
+        tv_sec: (now / NANOS_PER_SEC) as _,
+        tv_usec: (now % NANOS_PER_SEC / NANOS_PER_MICROS) as _,
+    };
+    Ok(0)
+}
+
+/// Set the realtime clock, the time zone is obsolete and ignored.
+#[syscall_trace]
+pub fn sys_settimeofday(
+    tv: UserInPtr<__kernel_old_timeval>,
+    _tz: UserInPtr<timezone>,
+) -> LinuxResult<isize> {
+    if let Some(tv) = nullable!(tv.get_as_ref())? {
+        set_realtime(timeval_to_nanos(tv)?);
+    }
+    Ok(0)
 }
 
 pub fn sys_times(tms: UserPtr<Tms>) -> LinuxResult<isize> {
-    let (_, utime_us, _, stime_us) = time_stat_output();
+    let usage = &current_process_data().usage;
+    let (own, children) = (usage.own.get(), usage.children.get());
+    let to_us = |ns: u64| (ns / NANOS_PER_MICROS) as usize;
     unsafe {
         *tms.get()? = Tms {
-            tms_utime: utime_us,
-            tms_stime: stime_us,
-            tms_cutime: utime_us,
-            tms_cstime: stime_us,
+            tms_utime: to_us(own.utime_ns),
+            tms_stime: to_us(own.stime_ns),
+            tms_cutime: to_us(children.utime_ns),
+            tms_cstime: to_us(children.stime_ns),
         }
     }
     Ok(nanos_to_ticks(monotonic_time_nanos()) as _)
@@ -46,3 +101,86 @@ pub fn sys_clock_getres(clock_id: i32, resolution: UserOutPtr<TimeSpec>) -> Linu
     }
     Ok(0)
 }
+
+fn timeval_to_nanos(tv: &__kernel_old_timeval) -> LinuxResult<u64> {
+    if tv.tv_sec < 0 || tv.tv_usec < 0 || tv.tv_usec >= 1_000_000 {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(tv.tv_sec as u64 * NANOS_PER_SEC + tv.tv_usec as u64 * NANOS_PER_MICROS)
+}
+
+fn nanos_to_timeval(ns: u64) -> __kernel_old_timeval {
+    // Round up so that an armed timer is never reported as disarmed.
+    let us = ns.div_ceil(NANOS_PER_MICROS);
+    __kernel_old_timeval {
+        tv_sec: (us / 1_000_000) as _,
+        tv_usec: (us % 1_000_000) as _,
+    }
+}
+
+fn itimer_to_user(value: ITimerValue) -> itimerval {
+    itimerval {
+        it_interval: nanos_to_timeval(value.interval_ns),
+        it_value: nanos_to_timeval(value.remained_ns),
+    }
+}
+
+fn parse_timer_type(which: i32) -> LinuxResult<TimerType> {
+    match TimerType::try_from(which) {
+        Ok(TimerType::NONE) | Err(_) => Err(LinuxError::EINVAL),
+        Ok(which) => Ok(which),
+    }
+}
+
+#[syscall_trace]
+pub fn sys_getitimer(which: i32, curr_value: UserOutPtr<itimerval>) -> LinuxResult<isize> {
+    let which = parse_timer_type(which)?;
+    let value = current_process_data().itimers.get(which);
+    *curr_value.get_as_mut_ref()? = itimer_to_user(value);
+    Ok(0)
+}
+
+#[syscall_trace]
+pub fn sys_setitimer(
+    which: i32,
+    new_value: UserInPtr<itimerval>,
+    old_value: UserOutPtr<itimerval>,
+) -> LinuxResult<isize> {
+    let which = parse_timer_type(which)?;
+    let new_value = new_value.get_as_ref()?;
+    let new_value = ITimerValue {
+        interval_ns: timeval_to_nanos(&new_value.it_interval)?,
+        remained_ns: timeval_to_nanos(&new_value.it_value)?,
+    };
+    let process_data = current_process_data();
+    let old = process_data
+        .itimers
+        .set(&process_data.signal, which, new_value);
+    if let Some(old_value) = nullable!(old_value.get_as_mut_ref())? {
+        *old_value = itimer_to_user(old);
+    }
+    Ok(0)
+}
+
+/// Arrange for `SIGALRM` to be delivered in `seconds` seconds, or cancel the
+/// pending alarm if `seconds` is 0.
+///
+/// Returns the number of seconds remaining until the previous alarm.
+#[syscall_trace]
+pub fn sys_alarm(seconds: u32) -> LinuxResult<isize> {
+    let process_data = current_process_data();
+    let old = process_data.itimers.set(
+        &process_data.signal,
+        TimerType::REAL,
+        ITimerValue {
+            interval_ns: 0,
+            remained_ns: seconds as u64 * NANOS_PER_SEC,
+        },
+    );
+    // Round to the nearest second, but never report a pending alarm as 0.
+    let remained = match old.remained_ns {
+        0 => 0,
+        ns => ((ns + NANOS_PER_SEC / 2) / NANOS_PER_SEC).max(1),
+    };
+    Ok(remained as _)
+}
@@ -0,0 +1,120 @@
+use crate::core::time::ITimerSpec;
+use crate::imp::task::signal::parse_signo;
+use crate::ptr::{PtrWrapper, UserInPtr, UserOutPtr, nullable};
+use axerrno::{LinuxError, LinuxResult};
+use linux_raw_sys::general::{
+    CLOCK_BOOTTIME, CLOCK_MONOTONIC, CLOCK_PROCESS_CPUTIME_ID, CLOCK_REALTIME, SIGEV_NONE,
+    SIGEV_SIGNAL, SIGEV_THREAD, SIGEV_THREAD_ID, TIMER_ABSTIME, sigevent,
+};
+use starry_core::posix_timer::{PosixTimer, TimerClock, TimerNotify};
+use starry_core::task::{current_process, current_process_data};
+use syscall_trace::syscall_trace;
+use undefined_process::thread::get_thread;
+
+use alloc::sync::Arc;
+use axsignal::Signo;
+
+fn parse_timer_clock(clock_id: u32) -> LinuxResult<TimerClock> {
+    match clock_id {
+        CLOCK_REALTIME => Ok(TimerClock::Realtime),
+        CLOCK_MONOTONIC | CLOCK_BOOTTIME => Ok(TimerClock::Monotonic),
+        CLOCK_PROCESS_CPUTIME_ID => Ok(TimerClock::ProcessCpu),
+        _ => Err(LinuxError::EINVAL),
+    }
+}
+
+/// Parse the notification method of a timer, returns it with the `sigev_value`.
+fn parse_sigevent(sevp: UserInPtr<sigevent>) -> LinuxResult<(TimerNotify, Option<usize>)> {
+    let Some(sev) = nullable!(sevp.get_as_ref())? else {
+        // The default is SIGEV_SIGNAL with SIGALRM and the timer ID.
+        return Ok((TimerNotify::Signal(Signo::SIGALRM), None));
+    };
+    let sigval = unsafe { sev.sigev_value.sival_ptr } as usize;
+    let notify = match sev.sigev_notify as u32 {
+        SIGEV_NONE => TimerNotify::None,
+        // `SIGEV_THREAD` is implemented by libc on top of `SIGEV_THREAD_ID`,
+        // the kernel treats it as `SIGEV_SIGNAL`.
+        SIGEV_SIGNAL | SIGEV_THREAD => TimerNotify::Signal(parse_signo(sev.sigev_signo as _)?),
+        SIGEV_THREAD_ID => {
+            let tid = unsafe { sev._sigev_un._tid } as _;
+            // The target thread must belong to the calling process.
+            let thread = get_thread(tid).ok_or(LinuxError::EINVAL)?;
+            if thread.get_process().get_pid() != current_process().get_pid() {
+                return Err(LinuxError::EINVAL);
+            }
+            TimerNotify::ThreadSignal(parse_signo(sev.sigev_signo as _)?, tid)
+        }
+        _ => return Err(LinuxError::EINVAL),
+    };
+    Ok((notify, Some(sigval)))
+}
+
+fn get_timer(timer_id: i32) -> LinuxResult<Arc<PosixTimer>> {
+    current_process_data()
+        .posix_timers
+        .get(timer_id)
+        .ok_or(LinuxError::EINVAL)
+}
+
+#[syscall_trace]
+pub fn sys_timer_create(
+    clock_id: u32,
+    sevp: UserInPtr<sigevent>,
+    timer_id: UserOutPtr<i32>,
+) -> LinuxResult<isize> {
+    let clock = parse_timer_clock(clock_id)?;
+    let (notify, sigval) = parse_sigevent(sevp)?;
+    let timer_id = timer_id.get_as_mut_ref()?;
+    let process_data = current_process_data();
+    *timer_id = process_data.posix_timers.create(
+        current_process().get_pid(),
+        &process_data.signal,
+        clock,
+        notify,
+        sigval,
+    );
+    Ok(0)
+}
+
+#[syscall_trace]
+pub fn sys_timer_settime(
+    timer_id: i32,
+    flags: u32,
+    new_value: UserInPtr<ITimerSpec>,
+    old_value: UserOutPtr<ITimerSpec>,
+) -> LinuxResult<isize> {
+    if flags & !TIMER_ABSTIME != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let timer = get_timer(timer_id)?;
+    let new_value = new_value.get_as_ref()?.to_itimer_value()?;
+    let old =
+        current_process_data()
+            .posix_timers
+            .set(&timer, new_value, flags & TIMER_ABSTIME != 0);
+    if let Some(old_value) = nullable!(old_value.get_as_mut_ref())? {
+        *old_value = old.into();
+    }
+    Ok(0)
+}
+
+#[syscall_trace]
+pub fn sys_timer_gettime(timer_id: i32, curr_value: UserOutPtr<ITimerSpec>) -> LinuxResult<isize> {
+    let timer = get_timer(timer_id)?;
+    *curr_value.get_as_mut_ref()? = timer.get().into();
+    Ok(0)
+}
+
+#[syscall_trace]
+pub fn sys_timer_getoverrun(timer_id: i32) -> LinuxResult<isize> {
+    Ok(get_timer(timer_id)?.overrun() as _)
+}
+
+#[syscall_trace]
+pub fn sys_timer_delete(timer_id: i32) -> LinuxResult<isize> {
+    current_process_data()
+        .posix_timers
+        .delete(timer_id)
+        .ok_or(LinuxError::EINVAL)?;
+    Ok(0)
+}
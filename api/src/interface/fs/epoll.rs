@@ -1,10 +1,12 @@
 use crate::core::file::epoll::EpollInstance;
 use crate::core::file::fd::{FdFlags, FileLike, fd_add};
+use crate::imp::task::signal::{read_sigmask, with_sigmask};
 use crate::ptr::{UserInOutPtr, UserInPtr};
 use crate::utils::task::task_yield_interruptable;
 use alloc::sync::Arc;
 use axerrno::{LinuxError, LinuxResult};
 use axhal::time::wall_time;
+use axsignal::SignalSet;
 use core::ffi::c_int;
 use core::time::Duration;
 use linux_raw_sys::general::epoll_event;
@@ -65,3 +67,19 @@ pub fn sys_epoll_wait(
         task_yield_interruptable()?;
     }
 }
+
+/// Like `epoll_wait`, but atomically replaces the signal mask during the wait.
+#[syscall_trace]
+pub fn sys_epoll_pwait(
+    epfd: c_int,
+    events: UserInOutPtr<epoll_event>,
+    maxevents: c_int,
+    timeout: c_int,
+    sigmask: UserInPtr<SignalSet>,
+    sigsetsize: usize,
+) -> LinuxResult<isize> {
+    let sigmask = read_sigmask(sigmask, sigsetsize)?;
+    with_sigmask(sigmask, || {
+        sys_epoll_wait(epfd, events.clone(), maxevents, timeout)
+    })
+}
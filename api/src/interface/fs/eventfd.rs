@@ -0,0 +1,32 @@
+use crate::core::file::eventfd::EventFd;
+use crate::core::file::fd::{FdFlags, fd_add};
+use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
+use axfs_ng::api::FileFlags;
+use linux_raw_sys::general::{EFD_CLOEXEC, EFD_NONBLOCK, EFD_SEMAPHORE};
+use syscall_trace::syscall_trace;
+
+/// Creates an eventfd object, and returns a file descriptor that refers to it.
+#[syscall_trace]
+pub fn sys_eventfd2(initval: u32, flags: u32) -> LinuxResult<isize> {
+    if flags & !(EFD_CLOEXEC | EFD_NONBLOCK | EFD_SEMAPHORE) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let fd_flags = if flags & EFD_CLOEXEC != 0 {
+        FdFlags::CLOSE_ON_EXEC
+    } else {
+        FdFlags::empty()
+    };
+    let eventfd = EventFd::new(
+        initval as _,
+        flags & EFD_SEMAPHORE != 0,
+        FileFlags::from_bits_truncate(flags & EFD_NONBLOCK),
+    );
+    Ok(fd_add(Arc::new(eventfd), fd_flags)? as _)
+}
+
+/// Like `eventfd2`, but without flags.
+#[syscall_trace]
+pub fn sys_eventfd(initval: u32) -> LinuxResult<isize> {
+    sys_eventfd2(initval, 0)
+}
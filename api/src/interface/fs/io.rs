@@ -11,6 +11,7 @@ use axerrno::{LinuxError, LinuxResult};
 use axfs_ng::api::FileFlags;
 use axio::SeekFrom;
 use core::ffi::{c_char, c_int, c_long, c_uint};
+use starry_core::page_cache;
 use syscall_trace::syscall_trace;
 
 #[syscall_trace]
@@ -30,6 +31,38 @@ pub fn sys_ftruncate(fd: c_int, length: c_long) -> LinuxResult<isize> {
     sys_truncate_impl(&file, length as _)
 }
 
+/// Write the file back to the storage, including the changes made through
+/// its shared mappings. Only the data is if `data_only` is set.
+fn sys_fsync_impl(fd: c_int, data_only: bool) -> LinuxResult<isize> {
+    let file = File::from_fd(fd)?;
+    let location = file.inner().location().clone();
+    page_cache::sync(&location)?;
+    location.entry().as_file()?.sync(data_only)?;
+    Ok(0)
+}
+
+#[syscall_trace]
+pub fn sys_fsync(fd: c_int) -> LinuxResult<isize> {
+    sys_fsync_impl(fd, false)
+}
+
+#[syscall_trace]
+pub fn sys_fdatasync(fd: c_int) -> LinuxResult<isize> {
+    sys_fsync_impl(fd, true)
+}
+
+/// Write the changes made through the shared mappings of all files back.
+#[syscall_trace]
+pub fn sys_sync() -> LinuxResult<isize> {
+    if let Err(err) = page_cache::sync_all() {
+        warn!(
+            "[sys_sync] failed to write back the mapped files: {:?}",
+            err
+        );
+    }
+    Ok(0)
+}
+
 #[syscall_trace]
 pub fn sys_lseek(fd: c_int, offset: isize, whence: c_int) -> LinuxResult<isize> {
     let pos = match whence {
@@ -1,8 +1,11 @@
 pub mod epoll;
+pub mod eventfd;
 pub mod fd;
 pub mod io;
 pub mod path;
 pub mod poll;
+pub mod signalfd;
 mod stat;
+pub mod timerfd;
 
 pub use stat::*;
@@ -1,8 +1,10 @@
 use crate::imp::fs::poll::{PollEntry, PollFlags, sys_poll_impl};
+use crate::imp::task::signal::{read_sigmask, with_sigmask};
 use crate::ptr::{PtrWrapper, UserInOutPtr, UserInPtr};
 use alloc::vec::Vec;
 use axerrno::{LinuxError, LinuxResult};
 use axhal::time::{MICROS_PER_SEC, NANOS_PER_MICROS, NANOS_PER_SEC};
+use axsignal::SignalSet;
 use bit_field::BitArray;
 use core::cmp::min;
 use core::ffi::{c_int, c_ulong};
@@ -82,7 +84,8 @@ pub fn sys_ppoll(
     fds: UserInOutPtr<UserPollFd>,
     n_fds: c_ulong,
     timeout: UserInPtr<timespec>,
-    _sigmask: UserInOutPtr<c_ulong>,
+    sigmask: UserInPtr<SignalSet>,
+    sigsetsize: usize,
 ) -> LinuxResult<isize> {
     // get params
     let fds = fds.get_as_array(n_fds as _)?;
@@ -100,12 +103,12 @@ pub fn sys_ppoll(
         timeout_nanos = (t.tv_sec as u64 * NANOS_PER_SEC) + t.tv_nsec as u64;
     }
 
+    let sigmask = read_sigmask(sigmask, sigsetsize)?;
+
     // preform syscall
-    // TODO: implement signal mask
-    // if _sigmask should be modified? use UserInPtr?
-    // pthread_sigmask(SIG_SETMASK, &sigmask, &origmask);
-    let result = sys_poll_impl(&mut entries, timeout_nanos, block)?;
-    // pthread_sigmask(SIG_SETMASK, &origmask, NULL);
+    let result = with_sigmask(sigmask, || {
+        sys_poll_impl(&mut entries, timeout_nanos, block)
+    })?;
 
     // copy results back
     let entries: Vec<UserPollFd> = entries.into_iter().map(|fd| fd.into()).collect();
@@ -113,6 +116,19 @@ pub fn sys_ppoll(
     Ok(result)
 }
 
+/// The last argument of `pselect6`, mirroring the kernel's anonymous struct.
+///
+/// The signal mask can't be passed directly because `pselect6` runs out of
+/// argument registers.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct PselectSigmask {
+    /// Pointer to the signal mask, may be null.
+    pub set: usize,
+    /// Size of the signal mask in bytes.
+    pub size: usize,
+}
+
 const FD_SETSIZE: usize = 1024;
 const FD_SET_LEN: usize = FD_SETSIZE / 8 / size_of::<usize>();
 
@@ -153,7 +169,7 @@ pub fn sys_pselect(
     write_fds: UserInOutPtr<c_ulong>,
     except_fds: UserInOutPtr<c_ulong>,
     timeout: UserInPtr<timespec>,
-    _sigmask: UserInOutPtr<c_ulong>,
+    sigmask: UserInPtr<PselectSigmask>,
 ) -> LinuxResult<isize> {
     let mut block = false;
     let mut timeout_nanos: u64 = 0;
@@ -166,16 +182,20 @@ pub fn sys_pselect(
         }
         timeout_nanos = (t.tv_sec as u64 * NANOS_PER_SEC) + t.tv_nsec as u64;
     }
-    // TODO: implement signal mask
-    sys_select_(
-        n_fds,
-        read_fds.clone(),
-        write_fds.clone(),
-        except_fds.clone(),
-        timeout_nanos,
-        block,
-    )
-    // TODO: restore signal mask
+    let sigmask = match sigmask.nullable(UserInPtr::get_as_ref)? {
+        Some(sigmask) => read_sigmask(sigmask.set.into(), sigmask.size)?,
+        None => None,
+    };
+    with_sigmask(sigmask, || {
+        sys_select_(
+            n_fds,
+            read_fds.clone(),
+            write_fds.clone(),
+            except_fds.clone(),
+            timeout_nanos,
+            block,
+        )
+    })
 }
 
 fn sys_select_(
@@ -232,9 +252,7 @@ fn sys_select_(
         .filter(|entry| !entry.events.is_empty())
         .collect();
 
-    // TODO: implement signal mask
     sys_poll_impl(&mut entries, timeout_nanos, block)?;
-    // pthread_sigmask(SIG_SETMASK, &origmask, NULL);
 
     // copy results back
     let mut count = 0;
@@ -0,0 +1,46 @@
+use crate::core::file::fd::{FdFlags, FileLike, fd_add};
+use crate::core::file::signalfd::SignalFd;
+use crate::imp::task::signal::check_sigset_size;
+use crate::ptr::UserInPtr;
+use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
+use axfs_ng::api::FileFlags;
+use axsignal::SignalSet;
+use core::ffi::c_int;
+use linux_raw_sys::general::{O_CLOEXEC, O_NONBLOCK};
+use syscall_trace::syscall_trace;
+
+/// Creates a file descriptor that can be used to accept signals in `mask`, or
+/// updates the mask of an existing one if `fd` is not -1.
+#[syscall_trace]
+pub fn sys_signalfd4(
+    fd: c_int,
+    mask: UserInPtr<SignalSet>,
+    sizemask: usize,
+    flags: u32,
+) -> LinuxResult<isize> {
+    // SFD_CLOEXEC and SFD_NONBLOCK share values with O_CLOEXEC and O_NONBLOCK.
+    if flags & !(O_CLOEXEC | O_NONBLOCK) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    check_sigset_size(sizemask)?;
+    let mask = *mask.get_as_ref()?;
+
+    if fd != -1 {
+        SignalFd::from_fd(fd)?.set_mask(mask);
+        return Ok(fd as _);
+    }
+    let fd_flags = if flags & O_CLOEXEC != 0 {
+        FdFlags::CLOSE_ON_EXEC
+    } else {
+        FdFlags::empty()
+    };
+    let signalfd = SignalFd::new(mask, FileFlags::from_bits_truncate(flags & O_NONBLOCK));
+    Ok(fd_add(Arc::new(signalfd), fd_flags)? as _)
+}
+
+/// Like `signalfd4`, but without flags.
+#[syscall_trace]
+pub fn sys_signalfd(fd: c_int, mask: UserInPtr<SignalSet>, sizemask: usize) -> LinuxResult<isize> {
+    sys_signalfd4(fd, mask, sizemask, 0)
+}
@@ -0,0 +1,66 @@
+use crate::core::file::fd::{FdFlags, FileLike, fd_add};
+use crate::core::file::timerfd::TimerFd;
+use crate::core::time::ITimerSpec;
+use crate::ptr::{UserInPtr, UserOutPtr, nullable};
+use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
+use axfs_ng::api::FileFlags;
+use core::ffi::c_int;
+use linux_raw_sys::general::{
+    CLOCK_BOOTTIME, CLOCK_BOOTTIME_ALARM, CLOCK_MONOTONIC, CLOCK_REALTIME, CLOCK_REALTIME_ALARM,
+    TFD_CLOEXEC, TFD_NONBLOCK, TFD_TIMER_ABSTIME, TFD_TIMER_CANCEL_ON_SET,
+};
+use starry_core::posix_timer::TimerClock;
+use syscall_trace::syscall_trace;
+
+/// Creates a new timer object, and returns a file descriptor that refers to it.
+#[syscall_trace]
+pub fn sys_timerfd_create(clock_id: u32, flags: u32) -> LinuxResult<isize> {
+    if flags & !(TFD_CLOEXEC | TFD_NONBLOCK) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let clock = match clock_id {
+        CLOCK_REALTIME | CLOCK_REALTIME_ALARM => TimerClock::Realtime,
+        CLOCK_MONOTONIC | CLOCK_BOOTTIME | CLOCK_BOOTTIME_ALARM => TimerClock::Monotonic,
+        _ => return Err(LinuxError::EINVAL),
+    };
+    let fd_flags = if flags & TFD_CLOEXEC != 0 {
+        FdFlags::CLOSE_ON_EXEC
+    } else {
+        FdFlags::empty()
+    };
+    let timerfd = TimerFd::new(clock, FileFlags::from_bits_truncate(flags & TFD_NONBLOCK));
+    Ok(fd_add(Arc::new(timerfd), fd_flags)? as _)
+}
+
+/// Arms or disarms the timer referred to by the file descriptor.
+#[syscall_trace]
+pub fn sys_timerfd_settime(
+    fd: c_int,
+    flags: u32,
+    new_value: UserInPtr<ITimerSpec>,
+    old_value: UserOutPtr<ITimerSpec>,
+) -> LinuxResult<isize> {
+    if flags & !(TFD_TIMER_ABSTIME | TFD_TIMER_CANCEL_ON_SET) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let timerfd = TimerFd::from_fd(fd)?;
+    let new_value = new_value.get_as_ref()?.to_itimer_value()?;
+    let old = timerfd.set(
+        new_value,
+        flags & TFD_TIMER_ABSTIME != 0,
+        flags & TFD_TIMER_CANCEL_ON_SET != 0,
+    );
+    if let Some(old_value) = nullable!(old_value.get_as_mut_ref())? {
+        *old_value = old.into();
+    }
+    Ok(0)
+}
+
+/// Returns the current setting of the timer referred to by the file descriptor.
+#[syscall_trace]
+pub fn sys_timerfd_gettime(fd: c_int, curr_value: UserOutPtr<ITimerSpec>) -> LinuxResult<isize> {
+    let timerfd = TimerFd::from_fd(fd)?;
+    *curr_value.get_as_mut_ref()? = timerfd.get().into();
+    Ok(0)
+}
@@ -72,7 +72,8 @@ pub fn sys_shmat(shm_id: c_int, shm_addr: c_ulong, shm_flag: c_int) -> LinuxResu
     let shared_memory = SHARED_MEMORY_MANAGER.get(key).ok_or(LinuxError::EINVAL)?;
     let size = shared_memory.page_count * PAGE_SIZE_4K;
     let process_data = current_process_data();
-    let mut addr_space = process_data.addr_space.lock();
+    let addr_space = process_data.addr_space();
+    let mut addr_space = addr_space.lock();
     let addr = if shm_addr == 0 {
         addr_space.find_free_area(
             addr_space.base(),
@@ -153,7 +154,8 @@ pub fn sys_shmdt(shm_addr: c_ulong) -> LinuxResult<isize> {
     let mut shared_memory = process_data.shared_memory.lock();
     let virt_addr = VirtAddr::from(shm_addr as usize);
     let shm_to_detach = shared_memory.remove(&virt_addr).ok_or(LinuxError::EINVAL)?;
-    let mut addr_space = process_data.addr_space.lock();
+    let addr_space = process_data.addr_space();
+    let mut addr_space = addr_space.lock();
     let size = shm_to_detach.page_count * PAGE_SIZE_4K;
     addr_space.unmap(virt_addr, size)?;
     Ok(0)
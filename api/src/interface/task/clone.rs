@@ -16,11 +16,13 @@
 //! - x86_64: `NONE`
 //! - loongarch: `NONE`
 use crate::imp::task::*;
-use crate::ptr::{PtrWrapper, UserOutPtr};
+use crate::ptr::{PtrWrapper, UserInPtr, UserOutPtr};
 use axerrno::{LinuxError, LinuxResult};
 use axsignal::Signo;
 use core::ffi::{c_int, c_ulong};
-use linux_raw_sys::general::CSIGNAL;
+use linux_raw_sys::general::{
+    CLONE_ARGS_SIZE_VER0, CLONE_CLEAR_SIGHAND, CLONE_INTO_CGROUP, CSIGNAL, clone_args,
+};
 use syscall_trace::syscall_trace;
 use undefined_process::Pid;
 
@@ -72,9 +74,35 @@ fn sys_clone_(
     // get flags
     let flags = flags as u32; // lower 32 bits of clone_flags
     let exit_signal = flags & CSIGNAL;
-    let clone_flags = flags & !CSIGNAL;
+    let clone_flags = CloneFlags::from_bits_truncate(flags & !CSIGNAL);
+
+    // With CLONE_PIDFD, the pidfd is stored where the parent TID would be,
+    // so it can not be combined with CLONE_PARENT_SETTID.
+    if clone_flags.contains(CloneFlags::PIDFD | CloneFlags::PARENT_SETTID) {
+        return Err(LinuxError::EINVAL);
+    }
+    do_clone(
+        clone_flags,
+        exit_signal,
+        new_sp as _,
+        tls as _,
+        addr_parent_tid.clone(),
+        addr_child_tid,
+        addr_parent_tid,
+    )
+}
+
+/// Check the arguments shared by `clone` and `clone3`, and do the clone.
+fn do_clone(
+    clone_flags: CloneFlags,
+    exit_signal: u32,
+    new_sp: usize,
+    tls: usize,
+    addr_parent_tid: UserOutPtr<c_int>,
+    addr_child_tid: UserOutPtr<c_int>,
+    addr_pidfd: UserOutPtr<c_int>,
+) -> LinuxResult<isize> {
     let exit_signal = Signo::from_repr(exit_signal as u8);
-    let clone_flags = CloneFlags::from_bits_truncate(clone_flags);
 
     // param check
     // If CLONE_THREAD or CLONE_PARENT was specified in the flags,
@@ -93,12 +121,17 @@ fn sys_clone_(
     {
         return Err(LinuxError::EINVAL);
     }
+    // A pidfd can only refer to a process.
+    if clone_flags.contains(CloneFlags::PIDFD | CloneFlags::THREAD) {
+        return Err(LinuxError::EINVAL);
+    }
     // TODO: signals
     let result = sys_clone_impl(
         clone_flags,
-        new_sp as _,
-        tls as _,
+        new_sp,
+        tls,
         addr_child_tid.address().into(),
+        addr_pidfd,
         exit_signal,
     );
 
@@ -116,9 +149,72 @@ fn sys_clone_(
     result
 }
 
+/// Like `clone`, but the arguments are passed in a `struct clone_args` of
+/// `size` bytes, which may be smaller or larger than the one known here.
+#[syscall_trace]
+pub fn sys_clone3(args: UserInPtr<clone_args>, size: usize) -> LinuxResult<isize> {
+    if size < CLONE_ARGS_SIZE_VER0 as usize {
+        return Err(LinuxError::EINVAL);
+    }
+    let bytes = unsafe { core::slice::from_raw_parts(args.get_as_bytes(size)?.cast::<u8>(), size) };
+    // Unknown trailing fields must be zero.
+    let known_size = size_of::<clone_args>().min(size);
+    if bytes[known_size..].iter().any(|&b| b != 0) {
+        return Err(LinuxError::E2BIG);
+    }
+    let mut args: clone_args = unsafe { core::mem::zeroed() };
+    unsafe {
+        core::ptr::copy_nonoverlapping(
+            bytes.as_ptr(),
+            (&mut args as *mut clone_args).cast::<u8>(),
+            known_size,
+        );
+    }
+
+    const CLONE3_ONLY_FLAGS: u64 = CLONE_CLEAR_SIGHAND as u64 | CLONE_INTO_CGROUP as u64;
+    let clone_flags = CloneFlags::from_bits(args.flags as u32)
+        .filter(|_| args.flags & !(u32::MAX as u64 | CLONE3_ONLY_FLAGS) == 0)
+        .ok_or(LinuxError::EINVAL)?;
+    // The exit signal has its own field, and is no longer part of the flags.
+    if args.flags & CSIGNAL as u64 != 0 || args.exit_signal & !(CSIGNAL as u64) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    // The stack is given by its lowest address and its size.
+    if (args.stack == 0) != (args.stack_size == 0) {
+        return Err(LinuxError::EINVAL);
+    }
+    if args.flags & CLONE_CLEAR_SIGHAND as u64 != 0 && clone_flags.contains(CloneFlags::SIGHAND) {
+        return Err(LinuxError::EINVAL);
+    }
+    if args.set_tid_size > 0 {
+        // Choosing the PID of the child is not supported.
+        warn!("[sys_clone3] set_tid is not supported");
+        return Err(LinuxError::EPERM);
+    }
+    if args.flags & CLONE_INTO_CGROUP as u64 != 0 {
+        // There is only the root cgroup, which every process is in.
+        warn!("[sys_clone3] ignore cgroup fd {}", args.cgroup);
+    }
+    // CLONE_CLEAR_SIGHAND needs nothing more: without CLONE_SIGHAND, the
+    // child starts with default signal dispositions.
+    let stack_top = args
+        .stack
+        .checked_add(args.stack_size)
+        .ok_or(LinuxError::EINVAL)?;
+
+    do_clone(
+        clone_flags,
+        args.exit_signal as _,
+        stack_top as _,
+        args.tls as _,
+        (args.parent_tid as usize).into(),
+        (args.child_tid as usize).into(),
+        (args.pidfd as usize).into(),
+    )
+}
+
 #[syscall_trace]
 pub fn sys_fork() -> LinuxResult<isize> {
-    // fork is a special case of clone
-    // TODO: exit_signal = SIGCHLD
-    sys_clone_impl(CloneFlags::empty(), 0, 0, 0, None)
+    // fork is a special case of clone, which notifies the parent with SIGCHLD
+    sys_clone_impl(CloneFlags::empty(), 0, 0, 0, 0.into(), Some(Signo::SIGCHLD))
 }
@@ -1,12 +1,12 @@
 use crate::imp::task::sys_execve_impl;
 use crate::ptr::{UserConstPtr, UserInPtr};
-use crate::utils::path::resolve_path_at_cwd;
+use crate::utils::path::{Resolve, ResolveFlags, resolve_path_at, resolve_path_at_cwd};
 use alloc::string::{String, ToString};
 use alloc::vec;
 use alloc::vec::Vec;
-use axerrno::LinuxResult;
-use axhal::arch::TrapFrame;
-use core::ffi::c_char;
+use axerrno::{LinuxError, LinuxResult};
+use core::ffi::{c_char, c_int};
+use linux_raw_sys::general::{AT_EMPTY_PATH, AT_SYMLINK_NOFOLLOW};
 use syscall_trace::syscall_trace;
 
 fn get_string_array(array: UserConstPtr<usize>) -> LinuxResult<Vec<String>> {
@@ -22,7 +22,6 @@ fn get_string_array(array: UserConstPtr<usize>) -> LinuxResult<Vec<String>> {
 
 #[syscall_trace]
 pub fn sys_execve(
-    tf: &mut TrapFrame,
     path: UserInPtr<c_char>,
     argv: UserInPtr<usize>,
     envp: UserInPtr<usize>,
@@ -31,17 +30,37 @@ pub fn sys_execve(
     let args = get_string_array(argv.clone())?;
     let envs = get_string_array(envp.clone())?;
 
-    // TODO: enhance it
-    if path.ends_with(".sh") {
-        const BUSYBOX: &str = "/musl/busybox";
-        info!("[execve] shebang detected, calling sh...");
-        let mut new_args = vec![BUSYBOX.to_string(), "sh".to_string()];
-        new_args.extend(args);
-        sys_execve_impl(tf, BUSYBOX.to_string(), new_args, envs)
-    } else {
-        let abs_path = resolve_path_at_cwd(Some(path))?.absolute_path()?;
-        let mut new_args = vec![abs_path.to_string()];
-        new_args.extend(args[1..].iter().cloned());
-        sys_execve_impl(tf, abs_path.to_string(), new_args, envs)
+    // scripts are run by the interpreter on their `#!` line
+    let location = resolve_path_at_cwd(Some(path))?;
+    let abs_path = location.absolute_path()?;
+    let mut new_args = vec![abs_path.to_string()];
+    new_args.extend(args.into_iter().skip(1));
+    sys_execve_impl(location, new_args, envs)
+}
+
+#[syscall_trace]
+pub fn sys_execveat(
+    dir_fd: c_int,
+    path: UserInPtr<c_char>,
+    argv: UserInPtr<usize>,
+    envp: UserInPtr<usize>,
+    flags: u32,
+) -> LinuxResult<isize> {
+    if flags & !(AT_EMPTY_PATH | AT_SYMLINK_NOFOLLOW) != 0 {
+        return Err(LinuxError::EINVAL);
     }
+    let path = path.get_as_str()?;
+    let args = get_string_array(argv.clone())?;
+    let envs = get_string_array(envp.clone())?;
+
+    // with `AT_EMPTY_PATH`, `dir_fd` is the program itself, e.g. `fexecve`
+    let location =
+        match resolve_path_at(dir_fd, Some(path), ResolveFlags::from_bits_truncate(flags))? {
+            Resolve::Location(location) => location,
+            Resolve::FileLike(file_like) => file_like.location().ok_or(LinuxError::EACCES)?,
+        };
+    let abs_path = location.absolute_path()?;
+    let mut new_args = vec![abs_path.to_string()];
+    new_args.extend(args.into_iter().skip(1));
+    sys_execve_impl(location, new_args, envs)
 }
@@ -1,8 +1,10 @@
 mod clone;
 mod execve;
 mod exit;
+mod pidfd;
 pub mod resource;
 
 pub use self::clone::*;
 pub use self::execve::*;
 pub use self::exit::*;
+pub use self::pidfd::*;
@@ -0,0 +1,62 @@
+use crate::core::file::fd::{FD_TABLE, FdFlags, FileLike, fd_add};
+use crate::core::file::pidfd::{PidFd, pidfd_create};
+use crate::interface::user::identity::sys_getuid;
+use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
+use axfs_ng::api::FileFlags;
+use core::ffi::c_int;
+use linux_raw_sys::general::O_NONBLOCK;
+use starry_core::process::get_thread_data;
+use starry_core::task::current_process;
+use syscall_trace::syscall_trace;
+use undefined_process::Pid;
+use undefined_process::process::{Process, get_process};
+
+/// Obtain a file descriptor that refers to a process.
+#[syscall_trace]
+pub fn sys_pidfd_open(pid: Pid, flags: u32) -> LinuxResult<isize> {
+    // PIDFD_NONBLOCK shares its value with O_NONBLOCK.
+    if flags & !O_NONBLOCK != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    // `get_process` only finds thread group leaders.
+    let process = get_process(pid).ok_or(LinuxError::ESRCH)?;
+    if process.is_zombie() {
+        return Err(LinuxError::ESRCH);
+    }
+    Ok(pidfd_create(process, FileFlags::from_bits_truncate(flags))? as _)
+}
+
+/// Obtain a duplicate of another process's file descriptor.
+#[syscall_trace]
+pub fn sys_pidfd_getfd(pidfd: c_int, target_fd: c_int, flags: u32) -> LinuxResult<isize> {
+    if flags != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let pidfd = PidFd::from_fd(pidfd)?;
+    let process = pidfd.process();
+    // like `ptrace_may_access` on Linux, the caller must be run by the same
+    // user or be the parent
+    let is_parent = process
+        .get_parent()
+        .is_some_and(|parent| Arc::ptr_eq(&parent, &current_process()));
+    if !is_parent && process_uid(process)? != sys_getuid()? {
+        return Err(LinuxError::EPERM);
+    }
+    // All threads of a process share the fd table unless they were cloned
+    // without CLONE_FILES, so look it up through any live thread.
+    let thread_data = process
+        .get_threads()
+        .iter()
+        .find_map(|thread| get_thread_data(thread.get_tid()))
+        .ok_or(LinuxError::ESRCH)?;
+    let file_like = FD_TABLE.deref_from(&thread_data.namespace).get(target_fd)?;
+    Ok(fd_add(file_like, FdFlags::CLOSE_ON_EXEC)? as _)
+}
+
+/// The real UID of `process`.
+fn process_uid(_process: &Process) -> LinuxResult<isize> {
+    // TODO: the processes have no credentials of their own, all are run by the
+    // real user
+    sys_getuid()
+}
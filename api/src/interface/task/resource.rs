@@ -1,7 +1,10 @@
-use crate::imp::task::resource::{sys_getrlimit_impl, sys_setrlimit_impl};
+use crate::imp::task::resource::{
+    sys_getrlimit_impl, sys_getrusage_impl, sys_setrlimit_impl, usage_to_rusage,
+};
 use crate::ptr::{PtrWrapper, UserInPtr, UserOutPtr};
 use axerrno::{LinuxError, LinuxResult};
 use core::ffi::c_int;
+use linux_raw_sys::general::rusage;
 use starry_core::resource::{ResourceLimit, ResourceLimitType};
 use syscall_trace::syscall_trace;
 
@@ -47,3 +50,10 @@ pub fn sys_getrlimit(
     }
     Ok(0)
 }
+
+#[syscall_trace]
+pub fn sys_getrusage(who: c_int, usage: UserOutPtr<rusage>) -> LinuxResult<isize> {
+    let value = usage_to_rusage(&sys_getrusage_impl(who)?);
+    *usage.get_as_mut_ref()? = value;
+    Ok(0)
+}
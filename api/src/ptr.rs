@@ -6,6 +6,7 @@ use core::fmt::Debug;
 use core::{alloc::Layout, ffi::c_char, mem, slice, str};
 use memory_addr::{MemoryAddr, PAGE_SIZE_4K, VirtAddr, VirtAddrRange};
 use starry_core::mm::access_user_memory;
+use starry_core::resource::ResourceLimitType;
 use starry_core::task::current_process_data;
 
 fn check_region(start: VirtAddr, layout: Layout, access_flags: MappingFlags) -> LinuxResult<()> {
@@ -19,18 +20,29 @@ fn check_region(start: VirtAddr, layout: Layout, access_flags: MappingFlags) ->
         // overflow
         return Err(LinuxError::EFAULT);
     }
-    let mut aspace = task.addr_space.lock();
-
-    if !aspace.check_region_access(
-        VirtAddrRange::from_start_size(start, layout.size()),
-        access_flags,
-    ) {
+    let (stack_limit, as_limit) = {
+        let limits = task.resource_limits.lock();
+        (
+            limits.get_soft(&ResourceLimitType::STACK),
+            limits.get_soft(&ResourceLimitType::AS),
+        )
+    };
+    let aspace = task.addr_space();
+    let mut aspace = aspace.lock();
+
+    // the stack grows down to the memory the kernel accesses as well
+    let range = VirtAddrRange::from_start_size(start, layout.size());
+    if !aspace.check_region_access(range, access_flags)
+        && !(aspace.grow_stack(start, stack_limit, as_limit)
+            && aspace.check_region_access(range, access_flags))
+    {
         return Err(LinuxError::EFAULT);
     }
 
     let page_start = start.align_down_4k();
     let page_end = (start + layout.size()).align_up_4k();
     aspace.populate_area(page_start, page_end - page_start, access_flags)?;
+    task.usage.own.update_maxrss(aspace.resident_size());
 
     Ok(())
 }
@@ -65,7 +77,8 @@ fn check_null_terminated<T: Eq + Default>(
                 // querying the page table since the page might has not been
                 // allocated yet.
                 let task = current_process_data();
-                let aspace = task.addr_space.lock();
+                let aspace = task.addr_space();
+                let aspace = aspace.lock();
                 if page.checked_add(PAGE_SIZE_4K).is_none() {
                     // overflow
                     return Err(LinuxError::EFAULT);
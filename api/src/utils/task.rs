@@ -3,8 +3,10 @@ use axerrno::{LinuxError, LinuxResult};
 use axhal::arch::TrapFrame;
 use core::time::Duration;
 use percpu::def_percpu;
+use starry_core::task::{count_context_switch, count_polling_switch, current_thread_data};
 
 pub fn task_yield() {
+    count_polling_switch();
     axtask::yield_now();
 }
 
@@ -31,6 +33,7 @@ pub fn set_trap_frame(tf: *mut TrapFrame) {
 /// Provides a signal-interruptible yield function for tasks.
 pub fn task_yield_interruptable() -> LinuxResult {
     // debug!("yield_interruptable");
+    count_polling_switch();
     axtask::yield_now();
     // debug!("yield resumed");
     // TODO: check signals, if any are pending, return LinuxError::EINTR
@@ -38,7 +41,12 @@ pub fn task_yield_interruptable() -> LinuxResult {
     if let Some(tf) = tf {
         // If we have a trap frame, we can check for signals
         // This is where we would handle the signal logic
-        if check_signals(tf, None) {
+        // If the mask was temporarily replaced (e.g. by `ppoll`), the signal frame
+        // must save the original one.
+        let thr_data = current_thread_data();
+        let restore_blocked = *thr_data.saved_sigmask.lock();
+        if check_signals(tf, restore_blocked) {
+            thr_data.saved_sigmask.lock().take();
             error!("syscall interrupted by signal");
             return Err(LinuxError::EINTR);
         }
@@ -46,10 +54,12 @@ pub fn task_yield_interruptable() -> LinuxResult {
     Ok(())
 }
 pub fn task_sleep(duration: Duration) {
+    count_context_switch(true);
     axtask::sleep(duration);
 }
 
 pub fn task_sleep_interruptable(duration: Duration) -> LinuxResult {
+    count_context_switch(true);
     axtask::sleep(duration);
     // TODO: check signals, if any are pending, return LinuxError::EINTR
     Ok(())
@@ -0,0 +1,36 @@
+#include <linux/futex.h>
+#include <pthread.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/syscall.h>
+#include <unistd.h>
+
+static int word;
+
+static void *park(void *arg) {
+  (void)arg;
+  // no timeout, only `SIGKILL` from `execve` ends it
+  while (1) {
+    syscall(SYS_futex, &word, FUTEX_WAIT, 0, NULL, NULL, 0);
+  }
+  return NULL;
+}
+
+// `execve` kills the other threads, even the ones blocked on a futex
+static void test_exec_futex() {
+  pthread_t thread;
+  pthread_create(&thread, NULL, park, NULL);
+  // let it block
+  usleep(100000);
+  execl("/exec_c", "exec_c", "test_exec_futex", NULL);
+  perror("execl");
+}
+
+int main(int argc, char *argv[]) {
+  if (argc > 1) {
+    printf("%s ok\n", argv[1]);
+    return 0;
+  }
+  test_exec_futex();
+  return 1;
+}
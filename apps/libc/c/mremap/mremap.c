@@ -0,0 +1,168 @@
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#ifndef MREMAP_DONTUNMAP
+#define MREMAP_DONTUNMAP 4
+#endif
+
+#define PAGE 4096
+
+static void fill(char *p, size_t size, char seed) {
+  for (size_t i = 0; i < size; i++) {
+    p[i] = (char)(seed + i % PAGE % 251);
+  }
+}
+
+static int check(const char *p, size_t size, char seed) {
+  for (size_t i = 0; i < size; i++) {
+    if (p[i] != (char)(seed + i % PAGE % 251)) {
+      return 0;
+    }
+  }
+  return 1;
+}
+
+// whether nothing is mapped at `p`
+static int unmapped(void *p) {
+  unsigned char vec;
+  return mincore(p, PAGE, &vec) < 0 && errno == ENOMEM;
+}
+
+// map `pages` pages with a page kept free after them, which is then taken by
+// another mapping, so that they cannot grow in place
+static char *map_blocked(int pages, int flags) {
+  char *p = mmap(NULL, (pages + 1) * PAGE, PROT_READ | PROT_WRITE,
+                 flags | MAP_ANONYMOUS, -1, 0);
+  assert(p != MAP_FAILED);
+  assert(munmap(p + pages * PAGE, PAGE) == 0);
+  char *blocker = mmap(p + pages * PAGE, PAGE, PROT_READ,
+                       MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED_NOREPLACE, -1, 0);
+  assert(blocker == p + pages * PAGE);
+  return p;
+}
+
+void test_grow_in_place() {
+  char *p = mmap(NULL, 4 * PAGE, PROT_READ | PROT_WRITE,
+                 MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+  assert(p != MAP_FAILED);
+  assert(munmap(p + 2 * PAGE, 2 * PAGE) == 0);
+  fill(p, 2 * PAGE, 1);
+
+  char *q = mremap(p, 2 * PAGE, 4 * PAGE, 0);
+  assert(q == p);
+  assert(check(q, 2 * PAGE, 1));
+  fill(q + 2 * PAGE, 2 * PAGE, 2);
+
+  // shrinking stays in place as well
+  q = mremap(p, 4 * PAGE, PAGE, 0);
+  assert(q == p);
+  assert(check(q, PAGE, 1));
+  assert(unmapped(p + PAGE));
+  assert(munmap(p, PAGE) == 0);
+  puts("test_grow_in_place ok");
+}
+
+void test_maymove() {
+  char *p = map_blocked(2, MAP_PRIVATE);
+  fill(p, 2 * PAGE, 3);
+
+  assert(mremap(p, 2 * PAGE, 8 * PAGE, 0) == MAP_FAILED && errno == ENOMEM);
+  assert(check(p, 2 * PAGE, 3));
+
+  char *q = mremap(p, 2 * PAGE, 8 * PAGE, MREMAP_MAYMOVE);
+  assert(q != MAP_FAILED && q != p);
+  assert(check(q, 2 * PAGE, 3));
+  assert(unmapped(p));
+  fill(q + 2 * PAGE, 6 * PAGE, 4);
+  assert(check(q + 2 * PAGE, 6 * PAGE, 4));
+  assert(munmap(q, 8 * PAGE) == 0);
+  assert(munmap(p + 2 * PAGE, PAGE) == 0);
+  puts("test_maymove ok");
+}
+
+void test_fixed() {
+  char *p = mmap(NULL, 2 * PAGE, PROT_READ | PROT_WRITE,
+                 MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+  char *dst = mmap(NULL, 4 * PAGE, PROT_READ | PROT_WRITE,
+                   MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+  assert(p != MAP_FAILED && dst != MAP_FAILED);
+  fill(p, 2 * PAGE, 5);
+  fill(dst, 4 * PAGE, 6);
+
+  // MREMAP_FIXED needs MREMAP_MAYMOVE, and ranges which do not overlap
+  assert(mremap(p, 2 * PAGE, 2 * PAGE, MREMAP_FIXED, dst) == MAP_FAILED &&
+         errno == EINVAL);
+  assert(mremap(p, 2 * PAGE, 2 * PAGE, MREMAP_MAYMOVE | MREMAP_FIXED,
+                p + PAGE) == MAP_FAILED &&
+         errno == EINVAL);
+
+  // what was mapped at the new address is replaced
+  char *q = mremap(p, 2 * PAGE, 2 * PAGE, MREMAP_MAYMOVE | MREMAP_FIXED, dst);
+  assert(q == dst);
+  assert(check(q, 2 * PAGE, 5));
+  assert(check(dst + 2 * PAGE, 2 * PAGE, 6));
+  assert(unmapped(p));
+  assert(munmap(dst, 4 * PAGE) == 0);
+  puts("test_fixed ok");
+}
+
+void test_dontunmap() {
+  char *p = mmap(NULL, 2 * PAGE, PROT_READ | PROT_WRITE,
+                 MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+  assert(p != MAP_FAILED);
+  fill(p, 2 * PAGE, 7);
+
+  // the size may not change
+  assert(mremap(p, 2 * PAGE, 4 * PAGE, MREMAP_MAYMOVE | MREMAP_DONTUNMAP,
+                NULL) == MAP_FAILED &&
+         errno == EINVAL);
+
+  char *q =
+      mremap(p, 2 * PAGE, 2 * PAGE, MREMAP_MAYMOVE | MREMAP_DONTUNMAP, NULL);
+  assert(q != MAP_FAILED && q != p);
+  assert(check(q, 2 * PAGE, 7));
+  // the old range is still mapped, but empty
+  for (int i = 0; i < 2 * PAGE; i++) {
+    assert(p[i] == 0);
+  }
+  assert(munmap(p, 2 * PAGE) == 0);
+  assert(munmap(q, 2 * PAGE) == 0);
+  puts("test_dontunmap ok");
+}
+
+void test_shared() {
+  char *p = map_blocked(2, MAP_SHARED);
+  fill(p, 2 * PAGE, 8);
+
+  char *q = mremap(p, 2 * PAGE, 4 * PAGE, MREMAP_MAYMOVE);
+  assert(q != MAP_FAILED && q != p);
+  assert(check(q, 2 * PAGE, 8));
+
+  // still shared with the children, only within the size it was mapped with
+  pid_t pid = fork();
+  if (pid == 0) {
+    fill(q, 2 * PAGE, 9);
+    _exit(0);
+  }
+  int status;
+  assert(waitpid(pid, &status, 0) == pid && status == 0);
+  assert(check(q, 2 * PAGE, 9));
+  assert(munmap(q, 4 * PAGE) == 0);
+  assert(munmap(p + 2 * PAGE, PAGE) == 0);
+  puts("test_shared ok");
+}
+
+int main() {
+  test_grow_in_place();
+  test_maymove();
+  test_fixed();
+  test_dontunmap();
+  test_shared();
+  return 0;
+}
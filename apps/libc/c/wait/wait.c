@@ -0,0 +1,49 @@
+#include <assert.h>
+#include <errno.h>
+#include <stdio.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+// a child made by `fork` is no "clone" child, so it is waited for without
+// `__WALL`
+static void test_fork_wait() {
+  pid_t pid = fork();
+  if (pid == 0) {
+    _exit(3);
+  }
+  int status;
+  assert(waitpid(pid, &status, 0) == pid);
+  assert(WIFEXITED(status) && WEXITSTATUS(status) == 3);
+
+  pid = fork();
+  if (pid == 0) {
+    _exit(4);
+  }
+  assert(wait(&status) == pid);
+  assert(WIFEXITED(status) && WEXITSTATUS(status) == 4);
+  assert(wait(&status) == -1 && errno == ECHILD);
+  puts("test_fork_wait ok");
+}
+
+#ifdef SYS_fork
+// the `fork` syscall itself, which musl does not call on every architecture
+static void test_sys_fork_wait() {
+  pid_t pid = syscall(SYS_fork);
+  if (pid == 0) {
+    _exit(5);
+  }
+  int status;
+  assert(waitpid(pid, &status, 0) == pid);
+  assert(WIFEXITED(status) && WEXITSTATUS(status) == 5);
+  puts("test_sys_fork_wait ok");
+}
+#endif
+
+int main() {
+  test_fork_wait();
+#ifdef SYS_fork
+  test_sys_fork_wait();
+#endif
+  return 0;
+}
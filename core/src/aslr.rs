@@ -0,0 +1,88 @@
+//! Address space layout randomization, see `randomize_va_space` in
+//! <https://docs.kernel.org/admin-guide/sysctl/kernel.html>.
+
+use axerrno::{LinuxError, LinuxResult};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use memory_addr::PAGE_SIZE_4K;
+
+use crate::random::random_u64;
+
+/// The `personality(2)` flag which disables the randomization for a process.
+pub const ADDR_NO_RANDOMIZE: u32 = 0x0040000;
+
+/// The range the heap is moved in, the same as the one of `brk` on Linux.
+const HEAP_RND_RANGE: usize = 0x200_0000;
+
+/// The bounds of `mmap_rnd_bits`, which are limited by the space between the
+/// base of the interpreter and the heap.
+pub const MMAP_RND_BITS_MIN: usize = 8;
+pub const MMAP_RND_BITS_MAX: usize = 16;
+
+/// 0: no randomization, 1: randomize the stack, the mmap base and PIE
+/// binaries, 2: randomize the heap as well.
+static RANDOMIZE_VA_SPACE: AtomicUsize = AtomicUsize::new(2);
+/// The number of random bits of the page offsets.
+static MMAP_RND_BITS: AtomicUsize = AtomicUsize::new(MMAP_RND_BITS_MAX);
+
+pub fn randomize_va_space() -> usize {
+    RANDOMIZE_VA_SPACE.load(Ordering::Acquire)
+}
+
+pub fn set_randomize_va_space(value: usize) -> LinuxResult<()> {
+    if value > 2 {
+        return Err(LinuxError::EINVAL);
+    }
+    RANDOMIZE_VA_SPACE.store(value, Ordering::Release);
+    Ok(())
+}
+
+pub fn mmap_rnd_bits() -> usize {
+    MMAP_RND_BITS.load(Ordering::Acquire)
+}
+
+pub fn set_mmap_rnd_bits(bits: usize) -> LinuxResult<()> {
+    if !(MMAP_RND_BITS_MIN..=MMAP_RND_BITS_MAX).contains(&bits) {
+        return Err(LinuxError::EINVAL);
+    }
+    MMAP_RND_BITS.store(bits, Ordering::Release);
+    Ok(())
+}
+
+/// A random offset of less than `count` pages.
+fn random_pages(count: usize) -> usize {
+    (random_u64() as usize % count) * PAGE_SIZE_4K
+}
+
+/// The offsets an address space is laid out with.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LayoutOffsets {
+    /// Subtracted from the top of the stack.
+    pub stack: usize,
+    /// Added to the address `mmap` starts searching from.
+    pub mmap: usize,
+    /// Added to the load address of position-independent binaries.
+    pub elf: usize,
+    /// Added to the end of the program, where the heap starts.
+    pub heap: usize,
+}
+
+impl LayoutOffsets {
+    /// Random offsets for a new program, all of them 0 if the randomization
+    /// is disabled, either globally or by the `personality` of the process.
+    pub fn new(personality: u32) -> Self {
+        let level = randomize_va_space();
+        if level == 0 || personality & ADDR_NO_RANDOMIZE != 0 {
+            return Self::default();
+        }
+        let count = 1 << mmap_rnd_bits();
+        Self {
+            stack: random_pages(count),
+            mmap: random_pages(count),
+            elf: random_pages(count),
+            heap: match level {
+                2 => random_pages(HEAP_RND_RANGE / PAGE_SIZE_4K),
+                _ => 0,
+            },
+        }
+    }
+}
@@ -0,0 +1,1348 @@
+//! The address space of a user process, which also keeps track of the
+//! mappings made in it, and of the areas whose pages are read from a file on
+//! demand.
+
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use axerrno::{AxResult, LinuxError, LinuxResult};
+use axhal::paging::{MappingFlags, PageSize};
+use axmm::{AddrSpace, kernel_aspace};
+use core::ops::Bound;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use memory_addr::{MemoryAddr, PAGE_SIZE_4K, PhysAddr, VirtAddr, VirtAddrRange, align_up};
+
+use crate::aslr::LayoutOffsets;
+use crate::page_cache::CachedFile;
+
+/// The gap kept free below a stack, so that it does not grow right into the
+/// mapping below it, like `stack_guard_gap` of Linux.
+const STACK_GUARD_GAP: usize = 256 * PAGE_SIZE_4K;
+
+/// The room kept free above the bottom of the heap for it to grow into, the
+/// mappings `mmap` is not given an address for are placed above it.
+const HEAP_GAP: usize = 0x4000_0000;
+
+/// The cached resident size which has to be counted again, see
+/// [`UserAddrSpace::resident_size`].
+const RSS_UNKNOWN: usize = usize::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PageState {
+    /// Not accessed yet, reserved by a lazily allocated area.
+    Unloaded,
+    /// Mapped to the page cached for the file, shared with the other
+    /// mappings of the file. It is read-only, so that the first write to a
+    /// page of a shared area is known.
+    Shared,
+    /// Mapped writable to the page cached for the file, by a shared area.
+    Dirty,
+    /// Mapped to a private copy of the file contents.
+    Private,
+}
+
+/// An area whose pages are read from a file on the first access.
+#[derive(Clone)]
+struct FileArea {
+    start: VirtAddr,
+    flags: MappingFlags,
+    file: Arc<CachedFile>,
+    /// The index of the page of the file mapped at `start`.
+    first_page: usize,
+    /// The memory from here on is zero-filled instead of read from the file,
+    /// e.g. the part of the `.bss` section sharing a page with `.data`.
+    zero_from: VirtAddr,
+    /// Whether the writes go to the file, i.e. `MAP_SHARED`.
+    shared: bool,
+    pages: Vec<PageState>,
+}
+
+impl FileArea {
+    fn end(&self) -> VirtAddr {
+        self.start + self.pages.len() * PAGE_SIZE_4K
+    }
+
+    /// Split the area at the page-aligned address `at`, returns the part
+    /// from `at` on.
+    fn split_off(&mut self, at: VirtAddr) -> FileArea {
+        let index = (at - self.start) / PAGE_SIZE_4K;
+        FileArea {
+            start: at,
+            flags: self.flags,
+            file: self.file.clone(),
+            first_page: self.first_page + index,
+            zero_from: self.zero_from,
+            shared: self.shared,
+            pages: self.pages.split_off(index),
+        }
+    }
+
+    /// Map the page `index` of the area, in place of the reserved one, for
+    /// writing if `write` is set.
+    ///
+    /// The pages of shared areas, and clean pages of private areas which are
+    /// never written, are shared with the other mappings of the file. The
+    /// others are private copies.
+    fn load_page(&mut self, aspace: &mut AddrSpace, index: usize, write: bool) -> LinuxResult<()> {
+        let vaddr = self.start + index * PAGE_SIZE_4K;
+        let file_page = self.first_page + index;
+        aspace.unmap(vaddr, PAGE_SIZE_4K)?;
+        if self.shared {
+            let paddr = self.file.page(file_page)?;
+            let writable = write && self.flags.contains(MappingFlags::WRITE);
+            let flags = match writable {
+                true => self.flags,
+                false => self.flags - MappingFlags::WRITE,
+            };
+            aspace.map_linear(vaddr, paddr, PAGE_SIZE_4K, flags, PageSize::Size4K)?;
+            self.pages[index] = if writable {
+                self.file.map_writable(file_page);
+                PageState::Dirty
+            } else {
+                PageState::Shared
+            };
+        } else if !self.flags.contains(MappingFlags::WRITE)
+            && vaddr + PAGE_SIZE_4K <= self.zero_from
+        {
+            let paddr = self.file.page(file_page)?;
+            aspace.map_linear(vaddr, paddr, PAGE_SIZE_4K, self.flags, PageSize::Size4K)?;
+            self.pages[index] = PageState::Shared;
+        } else {
+            let mut data = vec![0; PAGE_SIZE_4K];
+            if vaddr < self.zero_from {
+                let len = (self.zero_from - vaddr).min(PAGE_SIZE_4K);
+                self.file.read_page(file_page, &mut data[..len])?;
+            }
+            aspace.map_alloc(vaddr, PAGE_SIZE_4K, self.flags, true, PageSize::Size4K)?;
+            aspace.write(vaddr, PageSize::Size4K, &data)?;
+            self.pages[index] = PageState::Private;
+        }
+        Ok(())
+    }
+
+    /// Make the dirty page `index` read-only again, so that the next write
+    /// to it is known, e.g. before it is written back.
+    fn clean_page(&mut self, aspace: &mut AddrSpace, index: usize) -> LinuxResult<()> {
+        let vaddr = self.start + index * PAGE_SIZE_4K;
+        aspace.protect(vaddr, PAGE_SIZE_4K, self.flags - MappingFlags::WRITE)?;
+        self.file.unmap_writable(self.first_page + index);
+        self.pages[index] = PageState::Shared;
+        Ok(())
+    }
+
+    /// Unmap the page `index`, so that it is loaded again on the next access.
+    fn drop_page(&mut self, aspace: &mut AddrSpace, index: usize) -> LinuxResult<()> {
+        let state = self.pages[index];
+        if state == PageState::Unloaded {
+            return Ok(());
+        }
+        if state == PageState::Dirty {
+            // it stays dirty in the cache until it is written back
+            self.file.unmap_writable(self.first_page + index);
+        }
+        let vaddr = self.start + index * PAGE_SIZE_4K;
+        aspace.unmap(vaddr, PAGE_SIZE_4K)?;
+        aspace.map_alloc(vaddr, PAGE_SIZE_4K, self.flags, false, PageSize::Size4K)?;
+        self.pages[index] = PageState::Unloaded;
+        Ok(())
+    }
+
+    /// Write the dirty pages back to the file, when the area is unmapped.
+    fn release(&mut self) -> LinuxResult<()> {
+        if !self.shared {
+            return Ok(());
+        }
+        for (index, state) in self.pages.iter_mut().enumerate() {
+            if *state == PageState::Dirty {
+                self.file.unmap_writable(self.first_page + index);
+                *state = PageState::Shared;
+            }
+        }
+        self.file
+            .write_back(self.first_page..self.first_page + self.pages.len())
+    }
+}
+
+/// What the pages of a mapping are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backing {
+    /// Private memory allocated on the first access, e.g. the heap.
+    Private,
+    /// Physical memory mapped as it is from this address, e.g. a device or a
+    /// shared memory segment.
+    Linear(PhysAddr),
+    /// Pages of a file, or of anonymous shared memory, kept track of by a
+    /// [`FileArea`].
+    File,
+}
+
+/// A mapping made in the address space, which [`AddrSpace`] does not tell
+/// about.
+#[derive(Debug, Clone, Copy)]
+struct Mapping {
+    end: VirtAddr,
+    flags: MappingFlags,
+    page_size: PageSize,
+    backing: Backing,
+    /// Whether it is left out of the address space of the children, i.e.
+    /// `MADV_DONTFORK`.
+    dont_fork: bool,
+    /// Whether it is locked in memory, i.e. `mlock`.
+    locked: bool,
+    /// Whether it grows down as the memory below it is accessed, i.e. a
+    /// stack.
+    grows_down: bool,
+}
+
+impl Mapping {
+    /// Split the mapping starting at `start` at `at`, returns the part from
+    /// `at` on.
+    fn split_off(&mut self, start: VirtAddr, at: VirtAddr) -> Mapping {
+        let backing = match self.backing {
+            Backing::Linear(paddr) => Backing::Linear(paddr + (at - start)),
+            backing => backing,
+        };
+        let upper = Mapping { backing, ..*self };
+        self.end = at;
+        upper
+    }
+}
+
+/// A user address space.
+///
+/// It wraps an [`AddrSpace`], whose page table is only changed through the
+/// operations here, so that the mappings and the areas backed by files are
+/// always known.
+pub struct UserAddrSpace {
+    aspace: AddrSpace,
+    /// The mappings made through this, by their start address.
+    mappings: BTreeMap<VirtAddr, Mapping>,
+    /// Whether the mappings made from now on are locked, i.e. `MCL_FUTURE`,
+    /// and only as they are accessed, i.e. `MCL_ONFAULT`.
+    lock_future: Option<bool>,
+    /// The areas backed by files, by their start address.
+    file_areas: BTreeMap<VirtAddr, FileArea>,
+    /// The offsets the program is laid out with, chosen before it is loaded.
+    layout: LayoutOffsets,
+    /// The start of the heap, after the segments of the program.
+    heap_bottom: VirtAddr,
+    /// The program break, the end of the heap.
+    brk: VirtAddr,
+    /// The resident size, kept up to date by the page faults and counted
+    /// again after the other changes, or `RSS_UNKNOWN`.
+    rss: AtomicUsize,
+}
+
+impl UserAddrSpace {
+    pub fn new_empty(base: VirtAddr, size: usize) -> AxResult<Self> {
+        Ok(Self {
+            aspace: AddrSpace::new_empty(base, size)?,
+            mappings: BTreeMap::new(),
+            lock_future: None,
+            file_areas: BTreeMap::new(),
+            layout: LayoutOffsets::default(),
+            heap_bottom: base,
+            brk: base,
+            rss: AtomicUsize::new(0),
+        })
+    }
+
+    /// The start of the user space.
+    pub fn base(&self) -> VirtAddr {
+        self.aspace.base()
+    }
+
+    /// The end of the user space.
+    pub fn end(&self) -> VirtAddr {
+        self.aspace.end()
+    }
+
+    /// The physical address of the root of the page table, to switch to it.
+    pub fn page_table_root(&self) -> PhysAddr {
+        self.aspace.page_table_root()
+    }
+
+    /// Find a free area of `size` bytes in `limit`, from `hint` on.
+    pub fn find_free_area(
+        &self,
+        hint: VirtAddr,
+        size: usize,
+        limit: VirtAddrRange,
+        align: PageSize,
+    ) -> Option<VirtAddr> {
+        self.aspace.find_free_area(hint, size, limit, align)
+    }
+
+    /// Whether the range is mapped as a whole, with `access_flags` allowed.
+    pub fn check_region_access(&self, range: VirtAddrRange, access_flags: MappingFlags) -> bool {
+        self.aspace.check_region_access(range, access_flags)
+    }
+
+    /// Write `data` to the memory at `start`, whose pages are mapped in
+    /// already, e.g. the initial stack.
+    pub fn write(&mut self, start: VirtAddr, align: PageSize, data: &[u8]) -> AxResult {
+        self.aspace.write(start, align, data)
+    }
+
+    /// Copy the kernel mappings into the page table, see
+    /// [`crate::mm::copy_from_kernel`]. They are not mappings of the process,
+    /// and are left out of its memory.
+    pub fn copy_mappings_from(&mut self, kernel: &AddrSpace) -> AxResult {
+        self.aspace.copy_mappings_from(kernel)
+    }
+
+    pub fn layout(&self) -> &LayoutOffsets {
+        &self.layout
+    }
+
+    /// Set the offsets to lay out the program with, before it is loaded.
+    pub fn set_layout(&mut self, layout: LayoutOffsets) {
+        self.layout = layout;
+    }
+
+    /// The top of the user stack.
+    pub fn stack_top(&self) -> VirtAddr {
+        VirtAddr::from_usize(axconfig::plat::USER_STACK_TOP - self.layout.stack)
+    }
+
+    pub fn heap_bottom(&self) -> VirtAddr {
+        self.heap_bottom
+    }
+
+    /// Place the empty heap at `bottom`, when the program is loaded.
+    pub fn set_heap_bottom(&mut self, bottom: VirtAddr) {
+        self.heap_bottom = bottom;
+        self.brk = bottom;
+    }
+
+    pub fn brk(&self) -> VirtAddr {
+        self.brk
+    }
+
+    /// Move the program break to `brk`, the pages of the heap are allocated
+    /// on the first access and released when it shrinks.
+    ///
+    /// The heap may not be larger than `data_limit` bytes, i.e.
+    /// `RLIMIT_DATA`, nor run into another mapping, nor grow the address
+    /// space beyond `as_limit` bytes, i.e. `RLIMIT_AS`.
+    pub fn set_brk(&mut self, brk: VirtAddr, data_limit: u64, as_limit: u64) -> LinuxResult<()> {
+        if brk < self.heap_bottom || (brk - self.heap_bottom) as u64 > data_limit {
+            return Err(LinuxError::ENOMEM);
+        }
+        let old_end = self.brk.align_up_4k();
+        let new_end = brk.align_up_4k();
+        if new_end > old_end {
+            let size = new_end - old_end;
+            if !self.may_expand(size, true, as_limit, data_limit)
+                || new_end > self.aspace.end()
+                || self.aspace.find_free_area(
+                    old_end,
+                    size,
+                    VirtAddrRange::new(old_end, new_end),
+                    PageSize::Size4K,
+                ) != Some(old_end)
+            {
+                return Err(LinuxError::ENOMEM);
+            }
+            self.map_alloc(
+                old_end,
+                size,
+                MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER,
+                false,
+                PageSize::Size4K,
+            )?;
+        } else if new_end < old_end {
+            self.unmap(new_end, old_end - new_end)?;
+        }
+        self.brk = brk;
+        Ok(())
+    }
+
+    /// The address `mmap` starts searching for a free area from, above the
+    /// room kept for the heap, so that the heap can still grow with `brk`.
+    pub fn mmap_base(&self) -> VirtAddr {
+        self.heap_bottom + HEAP_GAP + self.layout.mmap
+    }
+
+    /// The size of all the mappings, counted against `RLIMIT_AS`.
+    pub fn mapped_size(&self) -> usize {
+        self.mappings
+            .iter()
+            .map(|(&start, mapping)| mapping.end - start)
+            .sum()
+    }
+
+    /// Whether the mapping starting at `start` is private writable memory
+    /// other than a stack, counted against `RLIMIT_DATA` like the `data_vm`
+    /// of Linux.
+    fn is_data(&self, start: VirtAddr, mapping: &Mapping) -> bool {
+        let private = match mapping.backing {
+            Backing::Private => true,
+            Backing::File => self.file_areas.get(&start).is_some_and(|area| !area.shared),
+            Backing::Linear(_) => false,
+        };
+        private && mapping.flags.contains(MappingFlags::WRITE) && !mapping.grows_down
+    }
+
+    /// The size of the private writable mappings other than the stacks,
+    /// counted against `RLIMIT_DATA`.
+    pub fn data_size(&self) -> usize {
+        self.mappings
+            .iter()
+            .filter(|(&start, mapping)| self.is_data(start, mapping))
+            .map(|(&start, mapping)| mapping.end - start)
+            .sum()
+    }
+
+    /// Whether the mapping containing `vaddr` is counted against
+    /// `RLIMIT_DATA`.
+    pub fn is_data_at(&self, vaddr: VirtAddr) -> bool {
+        self.mappings
+            .range(..=vaddr)
+            .next_back()
+            .is_some_and(|(&start, mapping)| mapping.end > vaddr && self.is_data(start, mapping))
+    }
+
+    /// Whether `size` more bytes can be mapped without going beyond
+    /// `as_limit`, i.e. `RLIMIT_AS`, nor beyond `data_limit`, i.e.
+    /// `RLIMIT_DATA`, if they are counted against it, like `may_expand_vm`
+    /// of Linux.
+    pub fn may_expand(&self, size: usize, data: bool, as_limit: u64, data_limit: u64) -> bool {
+        (self.mapped_size() + size) as u64 <= as_limit
+            && (!data || (self.data_size() + size) as u64 <= data_limit)
+    }
+
+    fn add_mapping(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        flags: MappingFlags,
+        page_size: PageSize,
+        backing: Backing,
+    ) {
+        self.forget_rss();
+        let mapping = Mapping {
+            end: start + size,
+            flags,
+            page_size,
+            backing,
+            dont_fork: false,
+            locked: self.lock_future.is_some(),
+            grows_down: false,
+        };
+        self.mappings.insert(start, mapping);
+    }
+
+    /// Map `size` bytes at `start` to private memory, allocated on the first
+    /// access unless `populate` is set.
+    pub fn map_alloc(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        flags: MappingFlags,
+        populate: bool,
+        page_size: PageSize,
+    ) -> AxResult {
+        self.aspace
+            .map_alloc(start, size, flags, populate, page_size)?;
+        self.add_mapping(start, size, flags, page_size, Backing::Private);
+        Ok(())
+    }
+
+    /// Map `size` bytes at `start` as a stack, which grows down as the memory
+    /// below it is accessed, e.g. `MAP_GROWSDOWN`.
+    pub fn map_stack(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        flags: MappingFlags,
+        populate: bool,
+    ) -> AxResult {
+        self.map_alloc(start, size, flags, populate, PageSize::Size4K)?;
+        if let Some(mapping) = self.mappings.get_mut(&start) {
+            mapping.grows_down = true;
+        }
+        Ok(())
+    }
+
+    /// Grow the stack right above `vaddr` down to it, returns whether it
+    /// grew.
+    ///
+    /// The stack may not be larger than `limit` bytes, i.e. `RLIMIT_STACK`,
+    /// nor grow the address space beyond `as_limit` bytes, i.e. `RLIMIT_AS`,
+    /// and the guard gap has to stay free below it.
+    pub fn grow_stack(&mut self, vaddr: VirtAddr, limit: u64, as_limit: u64) -> bool {
+        let Some((&start, &mapping)) = self
+            .mappings
+            .range((Bound::Excluded(vaddr), Bound::Unbounded))
+            .next()
+        else {
+            return false;
+        };
+        let new_start = vaddr.align_down_4k();
+        if !mapping.grows_down
+            || (mapping.end - new_start) as u64 > limit
+            || new_start < self.aspace.base() + STACK_GUARD_GAP
+            || !self.may_expand(start - new_start, false, as_limit, u64::MAX)
+        {
+            return false;
+        }
+        if let Some((_, below)) = self.mappings.range(..start).next_back()
+            && below.end + STACK_GUARD_GAP > new_start
+        {
+            return false;
+        }
+        if self
+            .aspace
+            .map_alloc(
+                new_start,
+                start - new_start,
+                mapping.flags,
+                false,
+                PageSize::Size4K,
+            )
+            .is_err()
+        {
+            return false;
+        }
+        self.mappings.remove(&start);
+        self.mappings.insert(new_start, mapping);
+        true
+    }
+
+    /// The start of the stack `vaddr` is in, e.g. for `PROT_GROWSDOWN`.
+    pub fn stack_start(&self, vaddr: VirtAddr) -> Option<VirtAddr> {
+        let (&start, mapping) = self.mappings.range(..=vaddr).next_back()?;
+        if vaddr >= mapping.end || !mapping.grows_down {
+            return None;
+        }
+        // it may be split into several mappings, e.g. by `mprotect`
+        let mut start = start;
+        while let Some((&below_start, below)) = self.mappings.range(..start).next_back()
+            && below.end == start
+            && below.grows_down
+        {
+            start = below_start;
+        }
+        Some(start)
+    }
+
+    /// Map `size` bytes at `start` to memory shared with the children, i.e.
+    /// `MAP_SHARED | MAP_ANONYMOUS`.
+    ///
+    /// It is a shared mapping of an anonymous file, so that its pages are
+    /// shared and moved by `mremap` like the ones of a file.
+    pub fn map_shared(&mut self, start: VirtAddr, size: usize, flags: MappingFlags) -> AxResult {
+        let end = start + size;
+        self.map_file(start, size, flags, CachedFile::anonymous(), 0, end, true)
+    }
+
+    /// Map `size` bytes at `start` to the physical memory at `paddr`.
+    pub fn map_linear(
+        &mut self,
+        start: VirtAddr,
+        paddr: PhysAddr,
+        size: usize,
+        flags: MappingFlags,
+        page_size: PageSize,
+    ) -> AxResult {
+        self.aspace
+            .map_linear(start, paddr, size, flags, page_size)?;
+        self.add_mapping(start, size, flags, page_size, Backing::Linear(paddr));
+        Ok(())
+    }
+
+    /// Map `size` bytes at `start` to the file from the page `first_page` of
+    /// it on, which are read on the first access. The memory from `zero_from`
+    /// on is zero-filled instead.
+    ///
+    /// If `shared` is set, the writes go to the file, otherwise to private
+    /// copies of its pages.
+    #[allow(clippy::too_many_arguments)]
+    pub fn map_file(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        flags: MappingFlags,
+        file: Arc<CachedFile>,
+        first_page: usize,
+        zero_from: VirtAddr,
+        shared: bool,
+    ) -> AxResult {
+        // reserve the area, so that it is known to be in use
+        self.aspace
+            .map_alloc(start, size, flags, false, PageSize::Size4K)?;
+        self.add_mapping(start, size, flags, PageSize::Size4K, Backing::File);
+        self.file_areas.insert(
+            start,
+            FileArea {
+                start,
+                flags,
+                file,
+                first_page,
+                zero_from,
+                shared,
+                pages: vec![PageState::Unloaded; size / PAGE_SIZE_4K],
+            },
+        );
+        Ok(())
+    }
+
+    /// Split the mappings and the file areas at `start` and `end`, so that
+    /// every one is either inside of the range or outside of it.
+    fn split_at(&mut self, start: VirtAddr, end: VirtAddr) {
+        for at in [start, end] {
+            if let Some((&mapping_start, mapping)) = self.mappings.range_mut(..at).next_back()
+                && mapping.end > at
+            {
+                let upper = mapping.split_off(mapping_start, at);
+                self.mappings.insert(at, upper);
+            }
+            if let Some((_, area)) = self.file_areas.range_mut(..at).next_back()
+                && area.end() > at
+            {
+                let upper = area.split_off(at);
+                self.file_areas.insert(at, upper);
+            }
+        }
+    }
+
+    pub fn unmap(&mut self, start: VirtAddr, size: usize) -> LinuxResult<()> {
+        self.forget_rss();
+        self.aspace.unmap(start, size)?;
+        let end = start + size;
+        self.split_at(start, end);
+        self.mappings
+            .retain(|&mapping_start, _| mapping_start < start || mapping_start >= end);
+        let unmapped: Vec<VirtAddr> = self
+            .file_areas
+            .range(start..end)
+            .map(|(&area_start, _)| area_start)
+            .collect();
+        for area_start in unmapped {
+            if let Some(mut area) = self.file_areas.remove(&area_start) {
+                area.release().unwrap_or_else(|err| {
+                    warn!("Failed to write back {:#x}: {:?}", area_start, err)
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn protect(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        new_flags: MappingFlags,
+    ) -> LinuxResult<()> {
+        self.aspace.protect(start, size, new_flags)?;
+        let end = start + size;
+        self.split_at(start, end);
+        for (_, mapping) in self.mappings.range_mut(start..end) {
+            mapping.flags = new_flags;
+        }
+        let writable = new_flags.contains(MappingFlags::WRITE);
+        for (_, area) in self.file_areas.range_mut(start..end) {
+            area.flags = new_flags;
+            for index in 0..area.pages.len() {
+                match area.pages[index] {
+                    // the first write is still to be known
+                    PageState::Shared if area.shared && writable => {
+                        let vaddr = area.start + index * PAGE_SIZE_4K;
+                        self.aspace.protect(
+                            vaddr,
+                            PAGE_SIZE_4K,
+                            new_flags - MappingFlags::WRITE,
+                        )?;
+                    }
+                    // the cached pages must not be written, make private copies
+                    PageState::Shared if writable => {
+                        area.load_page(&mut self.aspace, index, false)?;
+                    }
+                    PageState::Dirty if !writable => {
+                        area.clean_page(&mut self.aspace, index)?;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Resize the mapping of `old_size` bytes at `old_start` to `new_size`,
+    /// returns where it is afterwards.
+    ///
+    /// It grows in place if the memory after it is free. Otherwise it is
+    /// moved to `new_start` if given, or to a free area if `may_move`. With
+    /// `keep_old`, it is always moved and the old range stays mapped, but
+    /// empty, i.e. `MREMAP_DONTUNMAP`.
+    pub fn remap(
+        &mut self,
+        old_start: VirtAddr,
+        old_size: usize,
+        new_size: usize,
+        may_move: bool,
+        new_start: Option<VirtAddr>,
+        keep_old: bool,
+    ) -> LinuxResult<VirtAddr> {
+        self.forget_rss();
+        let Some((&start, &mapping)) = self.mappings.range(..=old_start).next_back() else {
+            return Err(LinuxError::EFAULT);
+        };
+        let page_size: usize = mapping.page_size.into();
+        let mut old_size = align_up(old_size, page_size);
+        let new_size = align_up(new_size, page_size);
+        let old_end = old_start + old_size;
+        if old_end > mapping.end {
+            return Err(LinuxError::EFAULT);
+        }
+        if !old_start.is_aligned(page_size) {
+            return Err(LinuxError::EINVAL);
+        }
+        // there is nothing mapped for the memory after a physical range
+        if matches!(mapping.backing, Backing::Linear(_)) && new_size > old_size {
+            return Err(LinuxError::EINVAL);
+        }
+
+        if new_start.is_none() && !keep_old {
+            if new_size <= old_size {
+                if new_size < old_size {
+                    self.unmap(old_start + new_size, old_size - new_size)?;
+                }
+                return Ok(old_start);
+            }
+            let new_end = old_start + new_size;
+            if old_end == mapping.end
+                && new_end <= self.aspace.end()
+                && self.aspace.find_free_area(
+                    old_end,
+                    new_size - old_size,
+                    VirtAddrRange::new(old_end, new_end),
+                    mapping.page_size,
+                ) == Some(old_end)
+            {
+                self.grow(start, new_end)?;
+                return Ok(old_start);
+            }
+            if !may_move {
+                return Err(LinuxError::ENOMEM);
+            }
+        }
+        if new_size < old_size {
+            self.unmap(old_start + new_size, old_size - new_size)?;
+            old_size = new_size;
+        }
+        self.split_at(old_start, old_start + old_size);
+        let new_start = match new_start {
+            Some(new_start) => {
+                self.unmap(new_start, new_size)?;
+                new_start
+            }
+            None => self
+                .aspace
+                .find_free_area(
+                    self.mmap_base().align_up(page_size),
+                    new_size,
+                    VirtAddrRange::new(self.aspace.base(), self.aspace.end()),
+                    mapping.page_size,
+                )
+                .ok_or(LinuxError::ENOMEM)?,
+        };
+
+        let flags = mapping.flags;
+        let mut emptied = None;
+        match mapping.backing {
+            Backing::Private => {
+                self.aspace
+                    .map_alloc(new_start, new_size, flags, false, mapping.page_size)?;
+                self.move_pages(old_start, new_start, old_size, mapping.page_size)?;
+            }
+            Backing::Linear(paddr) => {
+                self.aspace
+                    .map_linear(new_start, paddr, new_size, flags, mapping.page_size)?;
+            }
+            Backing::File => emptied = Some(self.move_file_area(old_start, new_start, new_size)?),
+        }
+        self.aspace.unmap(old_start, old_size)?;
+
+        if keep_old {
+            match mapping.backing {
+                Backing::Linear(paddr) => {
+                    self.aspace
+                        .map_linear(old_start, paddr, old_size, flags, mapping.page_size)?;
+                }
+                Backing::File => {
+                    self.aspace
+                        .map_alloc(old_start, old_size, flags, false, PageSize::Size4K)?;
+                    if let Some(area) = emptied {
+                        self.file_areas.insert(old_start, area);
+                    }
+                }
+                _ => {
+                    self.aspace
+                        .map_alloc(old_start, old_size, flags, false, mapping.page_size)?;
+                }
+            }
+        } else {
+            self.mappings.remove(&old_start);
+        }
+        self.add_mapping(
+            new_start,
+            new_size,
+            flags,
+            mapping.page_size,
+            mapping.backing,
+        );
+        Ok(new_start)
+    }
+
+    /// Grow the mapping at `start` in place up to `new_end`, the memory after
+    /// it is known to be free.
+    fn grow(&mut self, start: VirtAddr, new_end: VirtAddr) -> LinuxResult<()> {
+        let mapping = self.mappings.get_mut(&start).ok_or(LinuxError::EFAULT)?;
+        let old_end = mapping.end;
+        let size = new_end - old_end;
+        match mapping.backing {
+            Backing::Private => {
+                self.aspace
+                    .map_alloc(old_end, size, mapping.flags, false, mapping.page_size)?;
+            }
+            Backing::Linear(_) => return Err(LinuxError::EINVAL),
+            Backing::File => {
+                let area = self.file_areas.get_mut(&start).ok_or(LinuxError::EFAULT)?;
+                self.aspace
+                    .map_alloc(old_end, size, area.flags, false, PageSize::Size4K)?;
+                area.pages
+                    .resize((new_end - start) / PAGE_SIZE_4K, PageState::Unloaded);
+                // the file goes on in the new pages
+                if area.zero_from >= old_end {
+                    area.zero_from = area.zero_from.max(new_end);
+                }
+            }
+        }
+        mapping.end = new_end;
+        Ok(())
+    }
+
+    /// Move the page table entries of the `size` bytes at `old_start` to
+    /// `new_start`, which are both reserved by areas allocating their pages
+    /// on the first access, without copying the pages.
+    ///
+    /// The pages are then freed by the area at `new_start` when it is
+    /// unmapped, while the one at `old_start` finds none of them left.
+    fn move_pages(
+        &mut self,
+        old_start: VirtAddr,
+        new_start: VirtAddr,
+        size: usize,
+        page_size: PageSize,
+    ) -> LinuxResult<()> {
+        let page_table = self.aspace.page_table_mut();
+        for offset in (0..size).step_by(page_size.into()) {
+            // not accessed yet
+            let Ok((paddr, flags, _)) = page_table.query(old_start + offset) else {
+                continue;
+            };
+            let (_, _, tlb) = page_table
+                .unmap(old_start + offset)
+                .map_err(|_| LinuxError::EFAULT)?;
+            tlb.flush();
+            page_table
+                .map(new_start + offset, paddr, page_size, flags)
+                .map_err(|_| LinuxError::ENOMEM)?
+                .ignore();
+        }
+        Ok(())
+    }
+
+    /// Move the file area at `old_start` to `new_start`, resized to
+    /// `new_size`. The cached pages are mapped there as they are, and the
+    /// private copies are moved, see [`UserAddrSpace::move_pages`].
+    ///
+    /// Returns the area emptied of its pages, for the old range to be mapped
+    /// to again.
+    fn move_file_area(
+        &mut self,
+        old_start: VirtAddr,
+        new_start: VirtAddr,
+        new_size: usize,
+    ) -> LinuxResult<FileArea> {
+        let mut area = self
+            .file_areas
+            .remove(&old_start)
+            .ok_or(LinuxError::EFAULT)?;
+        let emptied = FileArea {
+            pages: vec![PageState::Unloaded; area.pages.len()],
+            ..area.clone()
+        };
+        self.aspace
+            .map_alloc(new_start, new_size, area.flags, false, PageSize::Size4K)?;
+        for (index, &state) in area.pages.iter().enumerate() {
+            let old = old_start + index * PAGE_SIZE_4K;
+            let new = new_start + index * PAGE_SIZE_4K;
+            match state {
+                PageState::Unloaded => {}
+                PageState::Shared | PageState::Dirty => {
+                    let flags = match state {
+                        PageState::Dirty => area.flags,
+                        _ => area.flags - MappingFlags::WRITE,
+                    };
+                    let paddr = area.file.page(area.first_page + index)?;
+                    self.aspace.unmap(new, PAGE_SIZE_4K)?;
+                    self.aspace
+                        .map_linear(new, paddr, PAGE_SIZE_4K, flags, PageSize::Size4K)?;
+                }
+                PageState::Private => {
+                    self.aspace.unmap(new, PAGE_SIZE_4K)?;
+                    self.aspace.map_alloc(
+                        new,
+                        PAGE_SIZE_4K,
+                        area.flags,
+                        false,
+                        PageSize::Size4K,
+                    )?;
+                    self.move_pages(old, new, PAGE_SIZE_4K, PageSize::Size4K)?;
+                }
+            }
+        }
+        let end = area.end();
+        area.zero_from = if area.zero_from >= end {
+            // the file goes on in the pages it grows by
+            (new_start + (area.zero_from - area.start)).max(new_start + new_size)
+        } else {
+            new_start + (area.zero_from.max(area.start) - area.start)
+        };
+        area.start = new_start;
+        area.pages
+            .resize(new_size / PAGE_SIZE_4K, PageState::Unloaded);
+        self.file_areas.insert(new_start, area);
+        Ok(emptied)
+    }
+
+    /// Drop the pages in the range, e.g. on `MADV_DONTNEED`. The next access
+    /// gets zero-filled memory, or the contents of the file again, while the
+    /// memory shared with others stays as it is.
+    pub fn discard(&mut self, start: VirtAddr, size: usize) -> LinuxResult<()> {
+        self.forget_rss();
+        let end = start + size;
+        if self
+            .mappings
+            .range(..end)
+            .any(|(_, mapping)| mapping.end > start && mapping.locked)
+        {
+            return Err(LinuxError::EINVAL);
+        }
+        let private: Vec<(VirtAddr, Mapping)> = self
+            .mappings
+            .range(..end)
+            .filter(|(_, mapping)| mapping.end > start && mapping.backing == Backing::Private)
+            .map(|(&mapping_start, &mapping)| (mapping_start, mapping))
+            .collect();
+        for (mapping_start, mapping) in private {
+            // only whole pages, huge pages are not split
+            let range_start = start.max(mapping_start).align_up(mapping.page_size);
+            let range_end = end.min(mapping.end).align_down(mapping.page_size);
+            if range_start < range_end {
+                let size = range_end - range_start;
+                self.aspace.unmap(range_start, size)?;
+                self.aspace.map_alloc(
+                    range_start,
+                    size,
+                    mapping.flags,
+                    false,
+                    mapping.page_size,
+                )?;
+            }
+        }
+        for (_, area) in self.file_areas.range_mut(..end) {
+            if area.end() <= start {
+                continue;
+            }
+            let first = (start.max(area.start) - area.start) / PAGE_SIZE_4K;
+            let last = (end.min(area.end()).align_up_4k() - area.start) / PAGE_SIZE_4K;
+            for index in first..last {
+                area.drop_page(&mut self.aspace, index)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Free the private pages in the range, e.g. on `MADV_FREE`.
+    ///
+    /// Linux frees them when the memory is needed, unless they are written
+    /// to before. Nothing is reclaimed later here, so they are freed at once,
+    /// as if the memory were needed right away.
+    pub fn free(&mut self, start: VirtAddr, size: usize) -> LinuxResult<()> {
+        let end = start + size;
+        if self
+            .mappings
+            .range(..end)
+            .any(|(_, mapping)| mapping.end > start && mapping.backing != Backing::Private)
+        {
+            return Err(LinuxError::EINVAL);
+        }
+        self.discard(start, size)
+    }
+
+    /// Read the pages of the files mapped in the range into the page cache,
+    /// e.g. on `MADV_WILLNEED`.
+    pub fn prefetch(&self, start: VirtAddr, size: usize) -> LinuxResult<()> {
+        let end = start + size;
+        for (_, area) in self.file_areas.range(..end) {
+            if area.end() <= start {
+                continue;
+            }
+            let first = (start.max(area.start) - area.start) / PAGE_SIZE_4K;
+            let last = (end.min(area.end()).align_up_4k() - area.start) / PAGE_SIZE_4K;
+            for index in first..last {
+                area.file.prefetch(area.first_page + index)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Set whether the mappings in the range are left out of the address
+    /// space of the children, i.e. `MADV_DONTFORK` and `MADV_DOFORK`.
+    pub fn set_dont_fork(&mut self, start: VirtAddr, size: usize, dont_fork: bool) {
+        let end = start + size;
+        self.split_at(start, end);
+        for (_, mapping) in self.mappings.range_mut(start..end) {
+            mapping.dont_fork = dont_fork;
+        }
+    }
+
+    /// The size of the locked mappings, i.e. `VmLck`.
+    pub fn locked_size(&self) -> usize {
+        self.mappings
+            .iter()
+            .filter(|(_, mapping)| mapping.locked)
+            .map(|(&start, mapping)| mapping.end - start)
+            .sum()
+    }
+
+    /// Whether the mappings made from now on are locked, and only as they are
+    /// accessed if it is `Some(true)`.
+    pub fn lock_future(&self) -> Option<bool> {
+        self.lock_future
+    }
+
+    /// Lock the mappings in the range in memory, e.g. on `mlock`. Their pages
+    /// are loaded right away, unless `on_fault`.
+    ///
+    /// Pages are never reclaimed anyway, but the locked memory may not be
+    /// more than `limit` bytes, i.e. `RLIMIT_MEMLOCK`.
+    pub fn lock(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        on_fault: bool,
+        limit: u64,
+    ) -> LinuxResult<()> {
+        let end = start + size;
+        let newly_locked: usize = self
+            .mappings
+            .range(..end)
+            .filter(|(_, mapping)| mapping.end > start && !mapping.locked)
+            .map(|(&mapping_start, mapping)| end.min(mapping.end) - start.max(mapping_start))
+            .sum();
+        if (self.locked_size() + newly_locked) as u64 > limit {
+            return Err(LinuxError::ENOMEM);
+        }
+        self.split_at(start, end);
+        for (_, mapping) in self.mappings.range_mut(start..end) {
+            mapping.locked = true;
+        }
+        if !on_fault {
+            self.populate_area(start, size, MappingFlags::empty())?;
+        }
+        Ok(())
+    }
+
+    /// Unlock the mappings in the range, e.g. on `munlock`.
+    pub fn unlock(&mut self, start: VirtAddr, size: usize) {
+        let end = start + size;
+        self.split_at(start, end);
+        for (_, mapping) in self.mappings.range_mut(start..end) {
+            mapping.locked = false;
+        }
+    }
+
+    /// Lock all the mappings if `current`, and the ones made from now on if
+    /// `future`, e.g. on `mlockall`. See [`UserAddrSpace::lock`].
+    pub fn lock_all(
+        &mut self,
+        current: bool,
+        future: bool,
+        on_fault: bool,
+        limit: u64,
+    ) -> LinuxResult<()> {
+        if current {
+            let mappings: Vec<(VirtAddr, usize)> = self
+                .mappings
+                .iter()
+                .map(|(&start, mapping)| (start, mapping.end - start))
+                .collect();
+            let total: usize = mappings.iter().map(|&(_, size)| size).sum();
+            if total as u64 > limit {
+                return Err(LinuxError::ENOMEM);
+            }
+            for (start, size) in mappings {
+                self.lock(start, size, on_fault, limit)?;
+            }
+        }
+        if future {
+            self.lock_future = Some(on_fault);
+        }
+        Ok(())
+    }
+
+    /// Unlock all the mappings, and the ones made from now on, e.g. on
+    /// `munlockall`.
+    pub fn unlock_all(&mut self) {
+        for mapping in self.mappings.values_mut() {
+            mapping.locked = false;
+        }
+        self.lock_future = None;
+    }
+
+    /// Whether the page at `vaddr` is in memory, i.e. it was accessed, or it
+    /// is cached for the file it is mapped to.
+    pub fn is_resident(&self, vaddr: VirtAddr) -> bool {
+        if let Some((_, area)) = self.file_areas.range(..=vaddr).next_back()
+            && vaddr < area.end()
+        {
+            let index = (vaddr.align_down_4k() - area.start) / PAGE_SIZE_4K;
+            if area.pages[index] == PageState::Unloaded {
+                return area.file.is_cached(area.first_page + index);
+            }
+        }
+        self.aspace.page_table().query(vaddr).is_ok()
+    }
+
+    /// Whether a fault at `vaddr` reads a page of a file which is not in the
+    /// cache yet, i.e. is a major one.
+    pub fn is_major_fault(&self, vaddr: VirtAddr) -> bool {
+        self.file_areas
+            .range(..=vaddr)
+            .next_back()
+            .is_some_and(|(_, area)| {
+                let index = (vaddr.align_down_4k() - area.start) / PAGE_SIZE_4K;
+                vaddr < area.end()
+                    && area.pages[index] == PageState::Unloaded
+                    && !area.file.is_anonymous()
+                    && !area.file.is_cached(area.first_page + index)
+            })
+    }
+
+    /// The size of the pages mapped in, i.e. the resident set, leaving out
+    /// the physical memory mapped as it is.
+    ///
+    /// It is cached, since it is checked on every page fault for `ru_maxrss`.
+    pub fn resident_size(&self) -> usize {
+        let rss = self.rss.load(Ordering::Relaxed);
+        if rss != RSS_UNKNOWN {
+            return rss;
+        }
+        let rss = self.resident_in(self.aspace.base(), self.aspace.end());
+        self.rss.store(rss, Ordering::Relaxed);
+        rss
+    }
+
+    /// The size of the pages mapped in which overlap the range, see
+    /// [`UserAddrSpace::resident_size`].
+    fn resident_in(&self, start: VirtAddr, end: VirtAddr) -> usize {
+        self.mappings
+            .range(..end)
+            .filter(|(_, mapping)| {
+                mapping.end > start && !matches!(mapping.backing, Backing::Linear(_))
+            })
+            .map(|(&mapping_start, mapping)| {
+                let page_size: usize = mapping.page_size.into();
+                let range_start = start.max(mapping_start).align_down(page_size);
+                (range_start.as_usize()..end.min(mapping.end).as_usize())
+                    .step_by(page_size)
+                    .filter(|&vaddr| self.aspace.page_table().query(vaddr.into()).is_ok())
+                    .count()
+                    * page_size
+            })
+            .sum()
+    }
+
+    /// Count the resident size again on the next
+    /// [`UserAddrSpace::resident_size`], after the pages may have changed.
+    fn forget_rss(&self) {
+        self.rss.store(RSS_UNKNOWN, Ordering::Relaxed);
+    }
+
+    /// Add the pages mapped in the range by `f` to the cached resident size,
+    /// instead of counting all of them again.
+    fn track_rss<T>(
+        &mut self,
+        start: VirtAddr,
+        end: VirtAddr,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        let before = self.resident_in(start, end);
+        let ret = f(self);
+        let after = self.resident_in(start, end);
+        let _ = self
+            .rss
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |rss| {
+                (rss != RSS_UNKNOWN).then(|| rss + after - before)
+            });
+        ret
+    }
+
+    /// Write the pages written through the shared mappings in the range back
+    /// to their files, e.g. on `msync`.
+    pub fn sync(&mut self, start: VirtAddr, size: usize) -> LinuxResult<()> {
+        let end = start + size;
+        for (_, area) in self.file_areas.range_mut(..end) {
+            if !area.shared || area.end() <= start {
+                continue;
+            }
+            let first = (start.max(area.start) - area.start) / PAGE_SIZE_4K;
+            let last = (end.min(area.end()).align_up_4k() - area.start) / PAGE_SIZE_4K;
+            // the pages written from now on are dirty again
+            for index in first..last {
+                if area.pages[index] == PageState::Dirty {
+                    area.clean_page(&mut self.aspace, index)?;
+                }
+            }
+            area.file
+                .write_back(area.first_page + first..area.first_page + last)?;
+        }
+        Ok(())
+    }
+
+    /// Handle a page fault, returns whether it is handled.
+    pub fn handle_page_fault(&mut self, vaddr: VirtAddr, access_flags: MappingFlags) -> bool {
+        self.track_rss(vaddr, vaddr + 1, |aspace| {
+            aspace.fault_in(vaddr, access_flags)
+        })
+    }
+
+    /// Map in the page at `vaddr` on a fault, see
+    /// [`UserAddrSpace::handle_page_fault`].
+    fn fault_in(&mut self, vaddr: VirtAddr, access_flags: MappingFlags) -> bool {
+        if let Some((_, area)) = self.file_areas.range_mut(..=vaddr).next_back()
+            && vaddr < area.end()
+        {
+            let index = (vaddr.align_down_4k() - area.start) / PAGE_SIZE_4K;
+            let write = access_flags.contains(MappingFlags::WRITE);
+            let load = match area.pages[index] {
+                PageState::Unloaded => true,
+                // the first write to a page of a shared area
+                PageState::Shared => area.shared && write,
+                _ => false,
+            };
+            if load {
+                if !area.flags.contains(access_flags) {
+                    return false;
+                }
+                return area
+                    .load_page(&mut self.aspace, index, write)
+                    .inspect_err(|err| warn!("Failed to load page at {:#x}: {:?}", vaddr, err))
+                    .is_ok();
+            }
+        }
+        self.aspace.handle_page_fault(vaddr, access_flags)
+    }
+
+    /// Populate the pages in the range, e.g. before the kernel accesses them.
+    pub fn populate_area(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        access_flags: MappingFlags,
+    ) -> LinuxResult<()> {
+        self.track_rss(start, start + size, |aspace| {
+            aspace.populate_pages(start, size, access_flags)
+        })
+    }
+
+    /// Populate the pages in the range, see [`UserAddrSpace::populate_area`].
+    fn populate_pages(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        access_flags: MappingFlags,
+    ) -> LinuxResult<()> {
+        let end = start + size;
+        let write = access_flags.contains(MappingFlags::WRITE);
+        for (_, area) in self.file_areas.range_mut(..end) {
+            if area.end() <= start {
+                continue;
+            }
+            let first = (start.max(area.start) - area.start) / PAGE_SIZE_4K;
+            let last = (end.min(area.end()).align_up_4k() - area.start) / PAGE_SIZE_4K;
+            for index in first..last {
+                let load = match area.pages[index] {
+                    PageState::Unloaded => true,
+                    PageState::Shared => area.shared && write,
+                    _ => false,
+                };
+                if load {
+                    area.load_page(&mut self.aspace, index, write)?;
+                }
+            }
+        }
+        self.aspace.populate_area(start, size, access_flags)?;
+        Ok(())
+    }
+
+    /// Clone the address space for a new process, e.g. on `fork`.
+    pub fn try_clone(&mut self) -> LinuxResult<Self> {
+        let aspace = self.aspace.try_clone()?;
+        // the dirty pages are mapped writable by the new one as well
+        for area in self.file_areas.values() {
+            for (index, &state) in area.pages.iter().enumerate() {
+                if state == PageState::Dirty {
+                    area.file.map_writable(area.first_page + index);
+                }
+            }
+        }
+        // the locks are not inherited
+        let mut mappings = self.mappings.clone();
+        for mapping in mappings.values_mut() {
+            mapping.locked = false;
+        }
+        let mut new = Self {
+            aspace,
+            mappings,
+            lock_future: None,
+            file_areas: self.file_areas.clone(),
+            layout: self.layout,
+            heap_bottom: self.heap_bottom,
+            brk: self.brk,
+            rss: AtomicUsize::new(RSS_UNKNOWN),
+        };
+        let dont_fork: Vec<(VirtAddr, usize)> = self
+            .mappings
+            .iter()
+            .filter(|(_, mapping)| mapping.dont_fork)
+            .map(|(&start, mapping)| (start, mapping.end - start))
+            .collect();
+        for (start, size) in dont_fork {
+            new.unmap(start, size)?;
+        }
+        Ok(new)
+    }
+}
+
+impl Drop for UserAddrSpace {
+    fn drop(&mut self) {
+        for area in self.file_areas.values_mut() {
+            area.release()
+                .unwrap_or_else(|err| warn!("Failed to write back {:#x}: {:?}", area.start, err));
+        }
+        if !cfg!(target_arch = "aarch64") && !cfg!(target_arch = "loongarch64") {
+            // the kernel mappings copied into it, see
+            // [`crate::mm::copy_from_kernel`], must not be freed with it
+            let kernel = kernel_aspace().lock();
+            self.aspace
+                .clear_mappings(VirtAddrRange::from_start_size(kernel.base(), kernel.size()));
+        }
+    }
+}
@@ -0,0 +1,253 @@
+//! Interpreters registered for file formats, recognized by magic bytes or by
+//! file name extension, see
+//! <https://docs.kernel.org/admin-guide/binfmt-misc.html>.
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use axerrno::{LinuxError, LinuxResult};
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+
+/// The size of the file header that magic bytes are matched against.
+const BINPRM_BUF_SIZE: usize = 256;
+
+/// How a file is recognized by an entry.
+#[derive(Debug)]
+pub enum BinfmtMatch {
+    /// The bytes at `offset` equal `magic`, after both are masked by `mask`.
+    Magic {
+        offset: usize,
+        magic: Vec<u8>,
+        mask: Option<Vec<u8>>,
+    },
+    /// The file name ends with `.` and the extension.
+    Extension(String),
+}
+
+/// An interpreter registered for a file format.
+#[derive(Debug)]
+pub struct BinfmtEntry {
+    name: String,
+    matcher: BinfmtMatch,
+    interpreter: String,
+    flags: String,
+    enabled: AtomicBool,
+}
+
+impl BinfmtEntry {
+    /// Parse a rule written to the `register` file, which is
+    /// `:name:type:offset:magic:mask:interpreter:flags` with `:` being any
+    /// delimiter.
+    pub fn parse(rule: &str) -> LinuxResult<Self> {
+        let rule = rule.trim_end_matches('\n');
+        let mut chars = rule.chars();
+        let delimiter = chars.next().ok_or(LinuxError::EINVAL)?;
+        let fields: Vec<&str> = chars.as_str().split(delimiter).collect();
+        let [name, kind, offset, magic, mask, interpreter, flags] = fields[..] else {
+            return Err(LinuxError::EINVAL);
+        };
+
+        if name.is_empty()
+            || name.contains('/')
+            || matches!(name, "." | ".." | "register" | "status")
+        {
+            return Err(LinuxError::EINVAL);
+        }
+        if interpreter.is_empty() {
+            return Err(LinuxError::EINVAL);
+        }
+        let matcher = match kind {
+            "M" => {
+                let offset = match offset {
+                    "" => 0,
+                    offset => offset.parse().map_err(|_| LinuxError::EINVAL)?,
+                };
+                let magic = unescape(magic)?;
+                let mask = match mask {
+                    "" => None,
+                    mask => Some(unescape(mask)?),
+                };
+                if magic.is_empty()
+                    || offset + magic.len() > BINPRM_BUF_SIZE
+                    || mask.as_ref().is_some_and(|mask| mask.len() != magic.len())
+                {
+                    return Err(LinuxError::EINVAL);
+                }
+                BinfmtMatch::Magic {
+                    offset,
+                    magic,
+                    mask,
+                }
+            }
+            // the offset and the mask are ignored
+            "E" => {
+                if magic.is_empty() || magic.contains('/') {
+                    return Err(LinuxError::EINVAL);
+                }
+                BinfmtMatch::Extension(magic.to_string())
+            }
+            _ => return Err(LinuxError::EINVAL),
+        };
+        Ok(Self {
+            name: name.to_string(),
+            matcher,
+            interpreter: interpreter.to_string(),
+            flags: flags.to_string(),
+            enabled: AtomicBool::new(true),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn interpreter(&self) -> &str {
+        &self.interpreter
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Acquire)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Release);
+    }
+
+    /// Whether the file at `path` with contents `file_data` is of this format.
+    fn matches(&self, path: &str, file_data: &[u8]) -> bool {
+        match &self.matcher {
+            BinfmtMatch::Magic {
+                offset,
+                magic,
+                mask,
+            } => {
+                let Some(header) = file_data.get(*offset..*offset + magic.len()) else {
+                    return false;
+                };
+                match mask {
+                    Some(mask) => header
+                        .iter()
+                        .zip(magic)
+                        .zip(mask)
+                        .all(|((byte, magic), mask)| byte & mask == magic & mask),
+                    None => header == magic,
+                }
+            }
+            BinfmtMatch::Extension(extension) => {
+                let file_name = path.rsplit('/').next().unwrap_or(path);
+                file_name
+                    .rsplit_once('.')
+                    .is_some_and(|(_, ext)| ext == extension)
+            }
+        }
+    }
+
+    /// The contents of the file of this entry.
+    pub fn status(&self) -> String {
+        let mut status = String::new();
+        status += if self.is_enabled() {
+            "enabled\n"
+        } else {
+            "disabled\n"
+        };
+        let _ = writeln!(status, "interpreter {}", self.interpreter);
+        let _ = writeln!(status, "flags: {}", self.flags);
+        match &self.matcher {
+            BinfmtMatch::Magic {
+                offset,
+                magic,
+                mask,
+            } => {
+                let _ = writeln!(status, "offset {offset}");
+                let _ = writeln!(status, "magic {}", hex(magic));
+                if let Some(mask) = mask {
+                    let _ = writeln!(status, "mask {}", hex(mask));
+                }
+            }
+            BinfmtMatch::Extension(extension) => {
+                let _ = writeln!(status, "extension .{extension}");
+            }
+        }
+        status
+    }
+}
+
+/// Decode the `\xHH` escapes in a magic or a mask.
+fn unescape(field: &str) -> LinuxResult<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut rest = field.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'\\' && tail.first() == Some(&b'x') {
+            let hex = tail.get(1..3).ok_or(LinuxError::EINVAL)?;
+            let hex = core::str::from_utf8(hex).map_err(|_| LinuxError::EINVAL)?;
+            bytes.push(u8::from_str_radix(hex, 16).map_err(|_| LinuxError::EINVAL)?);
+            rest = &tail[3..];
+        } else if byte == b'\\' && tail.first() == Some(&b'\\') {
+            bytes.push(b'\\');
+            rest = &tail[1..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    Ok(bytes)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+static ENTRIES: Mutex<BTreeMap<String, Arc<BinfmtEntry>>> = Mutex::new(BTreeMap::new());
+
+/// Whether the registered interpreters are used at all.
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Acquire)
+}
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Release);
+}
+
+/// Register an interpreter by a rule written to the `register` file.
+pub fn register(rule: &str) -> LinuxResult<Arc<BinfmtEntry>> {
+    let entry = Arc::new(BinfmtEntry::parse(rule)?);
+    let mut entries = ENTRIES.lock();
+    if entries.contains_key(&entry.name) {
+        return Err(LinuxError::EEXIST);
+    }
+    entries.insert(entry.name.clone(), entry.clone());
+    Ok(entry)
+}
+
+pub fn get_entry(name: &str) -> Option<Arc<BinfmtEntry>> {
+    ENTRIES.lock().get(name).cloned()
+}
+
+pub fn remove_entry(name: &str) -> Option<Arc<BinfmtEntry>> {
+    ENTRIES.lock().remove(name)
+}
+
+pub fn clear_entries() {
+    ENTRIES.lock().clear();
+}
+
+pub fn entry_names() -> Vec<String> {
+    ENTRIES.lock().keys().cloned().collect()
+}
+
+/// Find the enabled entry for the file at `path` with contents `file_data`.
+pub fn lookup(path: &str, file_data: &[u8]) -> Option<Arc<BinfmtEntry>> {
+    if !is_enabled() {
+        return None;
+    }
+    ENTRIES
+        .lock()
+        .values()
+        .find(|entry| entry.is_enabled() && entry.matches(path, file_data))
+        .cloned()
+}
@@ -1,5 +1,6 @@
 //! clone 任务时指定的参数。
 
+use axhal::time::monotonic_time_nanos;
 use bitflags::*;
 
 bitflags! {
@@ -87,6 +88,7 @@ numeric_enum_macro::numeric_enum! {
     #[repr(i32)]
     #[allow(non_camel_case_types)]
     #[derive(Eq, PartialEq, Debug, Clone, Copy)]
+    /// The interval timer types, see `setitimer(2)`.
     pub enum TimerType {
     /// 表示目前没有任何计时器(不在linux规范中，是os自己规定的)
     NONE = -1,
@@ -112,9 +114,8 @@ pub struct TimeStat {
     stime_ns: usize,
     user_timestamp: usize,
     kernel_timestamp: usize,
-    timer_type: TimerType,
-    timer_interval_ns: usize,
-    timer_remained_ns: usize,
+    /// Whether the task is running in user mode.
+    in_user: bool,
 }
 
 impl Default for TimeStat {
@@ -125,14 +126,13 @@ impl Default for TimeStat {
 
 impl TimeStat {
     pub fn new() -> Self {
+        let now = monotonic_time_nanos() as usize;
         Self {
             utime_ns: 0,
             stime_ns: 0,
-            user_timestamp: 0,
-            kernel_timestamp: 0,
-            timer_type: TimerType::NONE,
-            timer_interval_ns: 0,
-            timer_remained_ns: 0,
+            user_timestamp: now,
+            kernel_timestamp: now,
+            in_user: false,
         }
     }
 
@@ -145,26 +145,35 @@ impl TimeStat {
         self.stime_ns = 0;
         self.user_timestamp = 0;
         self.kernel_timestamp = current_timestamp;
+        self.in_user = false;
     }
 
-    pub fn switch_into_kernel_mode(&mut self, current_timestamp: usize) {
+    /// Returns the user time spent since the last switch into user mode, 0 if
+    /// already in kernel mode.
+    pub fn switch_into_kernel_mode(&mut self, current_timestamp: usize) -> usize {
+        if !self.in_user {
+            return 0;
+        }
+        self.in_user = false;
         let now_time_ns = current_timestamp;
-        let delta = now_time_ns - self.kernel_timestamp;
+        let delta = now_time_ns - self.user_timestamp;
         self.utime_ns += delta;
         self.kernel_timestamp = now_time_ns;
-        if self.timer_type != TimerType::NONE {
-            self.update_timer(delta);
-        };
+        delta
     }
 
-    pub fn switch_into_user_mode(&mut self, current_timestamp: usize) {
+    /// Returns the kernel time spent since the last switch into kernel mode, 0
+    /// if already in user mode.
+    pub fn switch_into_user_mode(&mut self, current_timestamp: usize) -> usize {
+        if self.in_user {
+            return 0;
+        }
+        self.in_user = true;
         let now_time_ns = current_timestamp;
         let delta = now_time_ns - self.kernel_timestamp;
         self.stime_ns += delta;
         self.user_timestamp = now_time_ns;
-        if self.timer_type == TimerType::REAL || self.timer_type == TimerType::PROF {
-            self.update_timer(delta);
-        }
+        delta
     }
 
     pub fn switch_from_old_task(&mut self, current_timestamp: usize) {
@@ -172,38 +181,9 @@ impl TimeStat {
         let delta = now_time_ns - self.kernel_timestamp;
         self.stime_ns += delta;
         self.kernel_timestamp = now_time_ns;
-        if self.timer_type == TimerType::REAL || self.timer_type == TimerType::PROF {
-            self.update_timer(delta);
-        }
     }
 
     pub fn switch_to_new_task(&mut self, current_timestamp: usize) {
-        let now_time_ns = current_timestamp;
-        let delta = now_time_ns - self.kernel_timestamp;
-        self.kernel_timestamp = now_time_ns;
-        if self.timer_type == TimerType::REAL {
-            self.update_timer(delta);
-        }
-    }
-
-    pub fn set_timer(
-        &mut self,
-        timer_interval_ns: usize,
-        timer_remained_ns: usize,
-        timer_type: usize,
-    ) -> bool {
-        self.timer_type = timer_type.into();
-        self.timer_interval_ns = timer_interval_ns;
-        self.timer_remained_ns = timer_remained_ns;
-        self.timer_type != TimerType::NONE
-    }
-
-    pub fn update_timer(&mut self, delta: usize) {
-        if self.timer_remained_ns == 0 {
-            return;
-        }
-        if self.timer_remained_ns > delta {
-            self.timer_remained_ns -= delta;
-        }
+        self.kernel_timestamp = current_timestamp;
     }
 }
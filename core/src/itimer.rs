@@ -0,0 +1,109 @@
+//! Per-process interval timers, see `setitimer(2)`.
+
+use alloc::sync::Arc;
+use axhal::time::monotonic_time_nanos;
+use axsignal::api::ProcessSignalManager;
+use axsignal::{SignalInfo, Signo};
+use axsync::RawMutex;
+use linux_raw_sys::general::SI_KERNEL;
+use spin::Mutex;
+
+use crate::ctypes::TimerType;
+use crate::task::WaitQueueWrapper;
+use crate::timer::AlarmTimer;
+
+/// The state of an interval timer, in nanoseconds.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ITimerValue {
+    /// The reload value, 0 for a one-shot timer.
+    pub interval_ns: u64,
+    /// The time left until the next expiration, 0 if the timer is disarmed.
+    pub remained_ns: u64,
+}
+
+impl ITimerValue {
+    /// Charge `delta` nanoseconds to the timer, returns whether it expired.
+    pub(crate) fn charge(&mut self, delta: u64) -> bool {
+        if self.remained_ns == 0 {
+            return false;
+        }
+        if self.remained_ns > delta {
+            self.remained_ns -= delta;
+            return false;
+        }
+        self.remained_ns = match self.interval_ns {
+            0 => 0,
+            interval => interval - (delta - self.remained_ns) % interval,
+        };
+        true
+    }
+}
+
+pub(crate) type SignalManager = ProcessSignalManager<RawMutex, WaitQueueWrapper>;
+
+/// The three interval timers of a process.
+#[derive(Default)]
+pub struct ITimers {
+    /// `ITIMER_REAL` counts down in wall-clock time.
+    real: AlarmTimer,
+    virt: Mutex<ITimerValue>,
+    prof: Mutex<ITimerValue>,
+}
+
+impl ITimers {
+    /// Get the current value of the timer of the given type.
+    pub fn get(&self, which: TimerType) -> ITimerValue {
+        match which {
+            TimerType::REAL => self.real.get(),
+            TimerType::VIRTUAL => *self.virt.lock(),
+            TimerType::PROF => *self.prof.lock(),
+            TimerType::NONE => ITimerValue::default(),
+        }
+    }
+
+    /// Arm or disarm the timer of the given type, returns the old value.
+    ///
+    /// `signal` is the signal manager of the process owning these timers.
+    pub fn set(
+        &self,
+        signal: &Arc<SignalManager>,
+        which: TimerType,
+        new: ITimerValue,
+    ) -> ITimerValue {
+        let old = self.get(which);
+        match which {
+            TimerType::REAL => {
+                let deadline = match new.remained_ns {
+                    0 => 0,
+                    remained => monotonic_time_nanos() + remained,
+                };
+                let signal = Arc::downgrade(signal);
+                self.real.set(deadline, new.interval_ns, move |_| {
+                    if let Some(signal) = signal.upgrade() {
+                        signal.send_signal(SignalInfo::new(Signo::SIGALRM, SI_KERNEL));
+                    }
+                });
+            }
+            TimerType::VIRTUAL => *self.virt.lock() = new,
+            TimerType::PROF => *self.prof.lock() = new,
+            TimerType::NONE => {}
+        }
+        old
+    }
+
+    /// Charge the CPU time spent by a thread of the process, and return the
+    /// signal to send if `ITIMER_VIRTUAL` or `ITIMER_PROF` expired.
+    ///
+    /// If both expired on the same tick, only `SIGVTALRM` is reported.
+    pub(crate) fn charge(&self, user_ns: u64, kernel_ns: u64) -> Option<Signo> {
+        let prof = self.prof.lock().charge(user_ns + kernel_ns);
+        let virt = user_ns != 0 && self.virt.lock().charge(user_ns);
+        if virt {
+            Some(Signo::SIGVTALRM)
+        } else if prof {
+            Some(Signo::SIGPROF)
+        } else {
+            None
+        }
+    }
+}
@@ -4,9 +4,19 @@ extern crate alloc;
 #[macro_use]
 extern crate axlog;
 
+pub mod aslr;
+pub mod aspace;
+pub mod binfmt_misc;
 pub mod ctypes;
+pub mod itimer;
 pub mod mm;
+pub mod page_cache;
+pub mod posix_timer;
 pub mod process;
+pub mod random;
 pub mod resource;
+pub mod rusage;
 pub mod shared_memory;
 pub mod task;
+pub mod timer;
+pub mod vdso;
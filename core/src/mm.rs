@@ -1,18 +1,26 @@
 use core::ffi::CStr;
 
 use alloc::string::ToString;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
 use alloc::{string::String, vec};
 use axerrno::{AxError, AxResult, LinuxError, LinuxResult};
 use axfs_ng::api::FS_CONTEXT;
 use axhal::mem::virt_to_phys;
 use axhal::paging::{MappingFlags, PageSize};
-use axmm::{AddrSpace, kernel_aspace};
-use kernel_elf_parser::{AuxvEntry, ELFParser, app_stack_region};
+use axmm::kernel_aspace;
+use kernel_elf_parser::{AuxvEntry, AuxvType, ELFParser, app_stack_region};
 use memory_addr::{MemoryAddr, PAGE_SIZE_4K, VirtAddr};
+use undefined_vfs::types::NodeType;
 use xmas_elf::{ElfFile, program::SegmentData};
 
-pub fn new_user_aspace_empty() -> AxResult<AddrSpace> {
-    AddrSpace::new_empty(
+use crate::aspace::UserAddrSpace;
+use crate::binfmt_misc;
+use crate::page_cache::{self, CachedFile};
+use crate::vdso::VDSO_BASE;
+
+pub fn new_user_aspace_empty() -> AxResult<UserAddrSpace> {
+    UserAddrSpace::new_empty(
         VirtAddr::from_usize(axconfig::plat::USER_SPACE_BASE),
         axconfig::plat::USER_SPACE_SIZE,
     )
@@ -20,7 +28,7 @@ pub fn new_user_aspace_empty() -> AxResult<AddrSpace> {
 
 /// If the target architecture requires it, the kernel portion of the address
 /// space will be copied to the user address space.
-pub fn copy_from_kernel(aspace: &mut AddrSpace) -> AxResult {
+pub fn copy_from_kernel(aspace: &mut UserAddrSpace) -> AxResult {
     #[cfg(not(any(target_arch = "aarch64", target_arch = "loongarch64")))]
     {
         // ARMv8 (aarch64) and LoongArch64 use separate page tables for user space
@@ -32,7 +40,7 @@ pub fn copy_from_kernel(aspace: &mut AddrSpace) -> AxResult {
 }
 
 /// Map the signal trampoline to the user address space.
-pub fn map_trampoline(aspace: &mut AddrSpace) -> AxResult {
+pub fn map_trampoline(aspace: &mut UserAddrSpace) -> AxResult {
     let signal_trampoline_paddr = virt_to_phys(axsignal::arch::signal_trampoline_address().into());
     aspace.map_linear(
         axconfig::plat::SIGNAL_TRAMPOLINE.into(),
@@ -46,22 +54,38 @@ pub fn map_trampoline(aspace: &mut AddrSpace) -> AxResult {
 
 /// Map the elf file to the user address space.
 ///
+/// The segments are mapped to the file, and their pages are read on the first
+/// access.
+///
 /// # Arguments
 /// - `uspace`: The address space of the user app.
-/// - `elf`: The elf file.
+/// - `elf`: The elf file, parsed from its beginning.
+/// - `file`: The elf file to read the segments from.
 ///
 /// # Returns
 /// - The entry point of the user app.
-fn map_elf(uspace: &mut AddrSpace, elf: &ElfFile) -> AxResult<(VirtAddr, [AuxvEntry; 17])> {
+/// - The auxiliary vector.
+/// - The end of the segments, which the heap follows.
+fn map_elf(
+    uspace: &mut UserAddrSpace,
+    elf: &ElfFile,
+    file: &Arc<CachedFile>,
+) -> AxResult<(VirtAddr, [AuxvEntry; 17], VirtAddr)> {
     let uspace_base = uspace.base().as_usize();
+    // only position-independent binaries can be moved
+    let elf_offset = match elf.header.pt2.type_().as_type() {
+        xmas_elf::header::Type::SharedObject => uspace.layout().elf,
+        _ => 0,
+    };
     let elf_parser = ELFParser::new(
         elf,
-        axconfig::plat::USER_INTERP_BASE,
-        Some(uspace_base as isize),
+        axconfig::plat::USER_INTERP_BASE + elf_offset,
+        Some((uspace_base + elf_offset) as isize),
         uspace_base,
     )
     .map_err(|_| AxError::InvalidData)?;
 
+    let mut elf_end = VirtAddr::from_usize(uspace_base);
     for segment in elf_parser.ph_load() {
         debug!(
             "Mapping ELF segment: [{:#x?}, {:#x?}) flags: {:#x?}",
@@ -71,27 +95,50 @@ fn map_elf(uspace: &mut AddrSpace, elf: &ElfFile) -> AxResult<(VirtAddr, [AuxvEn
         );
         let seg_pad = segment.vaddr.align_offset_4k();
         assert_eq!(seg_pad, segment.offset % PAGE_SIZE_4K);
+        if segment.offset + segment.filesz as usize > file.size() {
+            return Err(AxError::InvalidData);
+        }
 
-        let seg_align_size =
-            (segment.memsz as usize + seg_pad + PAGE_SIZE_4K - 1) & !(PAGE_SIZE_4K - 1);
-        uspace.map_alloc(
-            segment.vaddr.align_down_4k(),
-            seg_align_size,
-            segment.flags,
-            true,
-            PageSize::Size4K,
-        )?;
-        let seg_data = elf
-            .input
-            .get(segment.offset..segment.offset + segment.filesz as usize)
-            .ok_or(AxError::InvalidData)?;
-        uspace.write(segment.vaddr, PageSize::Size4K, seg_data)?;
+        let seg_start = segment.vaddr.align_down_4k();
+        let seg_end = (segment.vaddr + segment.memsz as usize).align_up_4k();
+        elf_end = elf_end.max(seg_end);
+        let file_end = match segment.filesz {
+            0 => seg_start,
+            filesz => (segment.vaddr + filesz as usize).align_up_4k(),
+        };
+        if file_end > seg_start {
+            // the rest of the last page is only zeroed if it is `.bss`
+            let zero_from = if segment.memsz > segment.filesz {
+                segment.vaddr + segment.filesz as usize
+            } else {
+                file_end
+            };
+            uspace.map_file(
+                seg_start,
+                file_end - seg_start,
+                segment.flags,
+                file.clone(),
+                (segment.offset - seg_pad) / PAGE_SIZE_4K,
+                zero_from,
+                false,
+            )?;
+        }
+        if seg_end > file_end {
+            uspace.map_alloc(
+                file_end,
+                seg_end - file_end,
+                segment.flags,
+                false,
+                PageSize::Size4K,
+            )?;
+        }
         // TDOO: flush the I-cache
     }
 
     Ok((
         elf_parser.entry().into(),
         elf_parser.auxv_vector(PAGE_SIZE_4K),
+        elf_end,
     ))
 }
 
@@ -106,40 +153,162 @@ fn map_elf(uspace: &mut AddrSpace, elf: &ElfFile) -> AxResult<(VirtAddr, [AuxvEn
 /// - The entry point of the user app.
 /// - The stack pointer of the user app.
 pub fn load_user_app(
-    uspace: &mut AddrSpace,
+    uspace: &mut UserAddrSpace,
     args: &[String],
     envs: &[String],
 ) -> LinuxResult<(VirtAddr, VirtAddr)> {
     if args.is_empty() {
         return Err(LinuxError::EINVAL);
     }
-    let context = FS_CONTEXT.lock();
-    let path = args[0].as_str();
-    let file_data = context.read(path).inspect_err(|_| {
-        error!("Load user app could not read file: {}", path);
+    let file = open_app(&args[0])?;
+    load_app(uspace, &file, args, envs, 0)
+}
+
+fn open_app(path: &str) -> LinuxResult<Arc<CachedFile>> {
+    let location = FS_CONTEXT.lock().resolve(path).inspect_err(|_| {
+        error!("Load user app could not open file: {}", path);
     })?;
-    drop(context);
-    let elf = if let Ok(elf) = ElfFile::new(&file_data) {
-        elf
-    } else {
-        // Not a valid ELF file, maybe a script file.
-        if file_data.starts_with(b"#!") {
-            // A script file, try to read the interpreter path.
-            let first_line_end = file_data
-                .iter()
-                .position(|&c| c == b'\n')
-                .unwrap_or(file_data.len());
-            let first_line = &file_data[2..first_line_end];
-            let interp_path = core::str::from_utf8(first_line)
-                .map_err(|_| LinuxError::EINVAL)?
-                .trim();
-            let mut new_args = vec![interp_path.to_string()];
-            new_args.extend_from_slice(args);
-            return load_user_app(uspace, &new_args, envs);
-        } else {
-            error!("Load user app invalid ELF file: {}", path);
+    if location.metadata()?.node_type != NodeType::RegularFile {
+        return Err(LinuxError::EACCES);
+    }
+    page_cache::open(location)
+}
+
+/// Read the beginning of the file, up to the end of the ELF program headers
+/// and the path of the interpreter if it is an ELF file.
+fn read_header(file: &CachedFile) -> LinuxResult<Vec<u8>> {
+    let read_prefix = |len: usize| -> LinuxResult<Vec<u8>> {
+        let mut data = vec![0; len.min(file.size())];
+        let read_len = file.read_at(&mut data, 0)?;
+        data.truncate(read_len);
+        Ok(data)
+    };
+
+    let mut data = read_prefix(PAGE_SIZE_4K)?;
+    let Ok(header) = xmas_elf::header::parse_header(&data) else {
+        return Ok(data);
+    };
+    let ph_end = header.pt2.ph_offset() as usize
+        + header.pt2.ph_count() as usize * header.pt2.ph_entry_size() as usize;
+    if ph_end > data.len() {
+        data = read_prefix(ph_end)?;
+        if ph_end > data.len() {
+            return Err(LinuxError::ENOEXEC);
+        }
+    }
+
+    let interp_end = ElfFile::new(&data).ok().and_then(|elf| {
+        elf.program_iter()
+            .find(|ph| ph.get_type() == Ok(xmas_elf::program::Type::Interp))
+            .map(|ph| (ph.offset() + ph.file_size()) as usize)
+    });
+    if let Some(interp_end) = interp_end
+        && interp_end > data.len()
+    {
+        data = read_prefix(interp_end)?;
+        if interp_end > data.len() {
             return Err(LinuxError::ENOEXEC);
         }
+    }
+    Ok(data)
+}
+
+/// The maximum depth of interpreters, e.g. a script run by an interpreter
+/// which is a script itself. The dynamic linker counts as one as well.
+const MAX_INTERP_DEPTH: usize = 5;
+
+/// The maximum length of the `#!` line of a script.
+const MAX_SHEBANG_LEN: usize = 256;
+
+/// Parse the `#!` line of a script, returns the interpreter and its optional
+/// argument.
+///
+/// As on Linux, everything after the interpreter is a single argument, and
+/// the line is cut at `MAX_SHEBANG_LEN` bytes.
+fn parse_shebang(file_data: &[u8]) -> LinuxResult<Option<(String, Option<String>)>> {
+    let Some(line) = file_data.strip_prefix(b"#!") else {
+        return Ok(None);
+    };
+    let line = &line[..line.len().min(MAX_SHEBANG_LEN - 2)];
+    let line = match line.iter().position(|&c| c == b'\n') {
+        Some(end) => &line[..end],
+        None => line,
+    };
+    let line = core::str::from_utf8(line).map_err(|_| LinuxError::ENOEXEC)?;
+    let line = line.trim_matches([' ', '\t']);
+    let (interp, arg) = match line.split_once([' ', '\t']) {
+        Some((interp, arg)) => (interp, Some(arg.trim_matches([' ', '\t']))),
+        None => (line, None),
+    };
+    if interp.is_empty() {
+        return Err(LinuxError::ENOEXEC);
+    }
+    Ok(Some((
+        interp.to_string(),
+        arg.filter(|arg| !arg.is_empty()).map(ToString::to_string),
+    )))
+}
+
+/// Load the interpreter `interp_args[0]` of an app.
+fn load_interp(
+    uspace: &mut UserAddrSpace,
+    interp_args: &[String],
+    envs: &[String],
+    depth: usize,
+) -> LinuxResult<(VirtAddr, VirtAddr)> {
+    if depth >= MAX_INTERP_DEPTH {
+        return Err(LinuxError::ELOOP);
+    }
+    let file = open_app(&interp_args[0])?;
+    load_app(uspace, &file, interp_args, envs, depth + 1)
+}
+
+/// Load the user app in `file` to the user address space, e.g. the one opened
+/// by `execveat`.
+///
+/// An interpreter of the app is still opened by its path, and `args[0]` is
+/// passed to it as the path of the app.
+pub fn load_user_app_file(
+    uspace: &mut UserAddrSpace,
+    file: &Arc<CachedFile>,
+    args: &[String],
+    envs: &[String],
+) -> LinuxResult<(VirtAddr, VirtAddr)> {
+    if args.is_empty() {
+        return Err(LinuxError::EINVAL);
+    }
+    load_app(uspace, file, args, envs, 0)
+}
+
+fn load_app(
+    uspace: &mut UserAddrSpace,
+    file: &Arc<CachedFile>,
+    args: &[String],
+    envs: &[String],
+    depth: usize,
+) -> LinuxResult<(VirtAddr, VirtAddr)> {
+    let path = args[0].as_str();
+    let header = read_header(file)?;
+
+    // An interpreter registered in binfmt_misc runs the app with its path.
+    if let Some(entry) = binfmt_misc::lookup(path, &header) {
+        let mut new_args = vec![entry.interpreter().to_string()];
+        new_args.extend_from_slice(args);
+        return load_interp(uspace, &new_args, envs, depth);
+    }
+
+    let elf = if let Ok(elf) = ElfFile::new(&header) {
+        elf
+    } else if let Some((interp, arg)) = parse_shebang(&header)? {
+        // A script file, run by the interpreter with the optional argument
+        // and the path of the script.
+        let mut new_args = vec![interp];
+        new_args.extend(arg);
+        new_args.extend_from_slice(args);
+        return load_interp(uspace, &new_args, envs, depth);
+    } else {
+        error!("Load user app invalid ELF file: {}", path);
+        return Err(LinuxError::ENOEXEC);
     };
 
     if let Some(interp) = elf
@@ -160,15 +329,19 @@ pub fn load_user_app(
         // Set the first argument to the path of the user app.
         let mut new_args = vec![interp_path];
         new_args.extend_from_slice(args);
-        return load_user_app(uspace, &new_args, envs);
+        return load_interp(uspace, &new_args, envs, depth);
     }
 
-    let (entry, mut auxv) = map_elf(uspace, &elf)?;
+    let (entry, auxv, elf_end) = map_elf(uspace, &elf, file)?;
+    uspace.set_heap_bottom(elf_end + uspace.layout().heap);
+    // the vDSO is found by its ELF header
+    let mut auxv = Vec::from(auxv);
+    auxv.insert(0, AuxvEntry::new(AuxvType::SYSINFO_EHDR, VDSO_BASE));
     // The user stack is divided into two parts:
     // `ustack_start` -> `ustack_pointer`: It is the stack space that users actually read and write.
     // `ustack_pointer` -> `ustack_end`: It is the space that contains the arguments, environment variables and auxv passed to the app.
     //  When the app starts running, the stack pointer points to `ustack_pointer`.
-    let ustack_end = VirtAddr::from_usize(axconfig::plat::USER_STACK_TOP);
+    let ustack_end = uspace.stack_top();
     let ustack_size = axconfig::plat::USER_STACK_SIZE;
     let ustack_start = ustack_end - ustack_size;
     debug!(
@@ -177,22 +350,12 @@ pub fn load_user_app(
     );
 
     let stack_data = app_stack_region(args, envs, &mut auxv, ustack_start, ustack_size);
-    uspace.map_alloc(
+    // it grows further down as it is used, up to `RLIMIT_STACK`
+    uspace.map_stack(
         ustack_start,
         ustack_size,
         MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER,
         true,
-        PageSize::Size4K,
-    )?;
-
-    let heap_start = VirtAddr::from_usize(axconfig::plat::USER_HEAP_BASE);
-    let heap_size = axconfig::plat::USER_HEAP_SIZE;
-    uspace.map_alloc(
-        heap_start,
-        heap_size,
-        MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER,
-        true,
-        PageSize::Size4K,
     )?;
 
     let user_sp = ustack_end - stack_data.len();
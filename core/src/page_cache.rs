@@ -0,0 +1,510 @@
+//! Pages of files cached in memory, one cache per inode shared by `read`,
+//! `write` and the mappings of the file, so that they all see the same data
+//! and the processes using a file share the same physical pages.
+//!
+//! Anonymous shared memory is cached the same way, only without a file.
+//!
+//! Writes only go to the cached pages, which stay dirty until they are
+//! written back to the file on `fsync`, `sync` and when the file is neither
+//! open nor mapped anymore. The pages of shared mappings are written to
+//! directly as well, and also written back on `msync` and `munmap`.
+//!
+//! The clean pages which are not mapped are evicted once more than
+//! `MAX_UNMAPPED_PAGES` of a file are cached, the least recently used first,
+//! so that reading a large file does not fill the memory. The pages which were
+//! mapped stay cached as long as the file, since their mappings are not
+//! counted.
+
+use alloc::collections::btree_map::Entry;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use axalloc::global_allocator;
+use axerrno::{LinuxError, LinuxResult};
+use axfs_ng::api::{File, FileFlags};
+use axhal::mem::virt_to_phys;
+use axsync::{Mutex, RawMutex};
+use core::ops::Range;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::time::Duration;
+use memory_addr::{PAGE_SIZE_4K, PhysAddr, VirtAddr};
+use undefined_vfs::mount::Location;
+use undefined_vfs::types::Metadata;
+
+/// The number of pages of a file which are not mapped, kept cached before
+/// the least recently used clean ones are evicted.
+const MAX_UNMAPPED_PAGES: usize = 1024;
+
+/// A page of a file, zero-filled beyond the end of the file.
+struct CachedPage {
+    /// Virtual kernel address of the page
+    addr: usize,
+    /// When it was last used, see [`Pages::clock`].
+    last_use: u64,
+    /// Whether it was mapped, so that it may not be evicted.
+    mapped: bool,
+}
+
+impl CachedPage {
+    fn alloc() -> LinuxResult<Self> {
+        let addr = global_allocator()
+            .alloc_pages(1, PAGE_SIZE_4K)
+            .map_err(|_| LinuxError::ENOMEM)?;
+        let page = Self {
+            addr,
+            last_use: 0,
+            mapped: false,
+        };
+        page.data_mut().fill(0);
+        Ok(page)
+    }
+
+    fn data(&self) -> &[u8] {
+        // SAFETY: the page is allocated by us and lives as long as `self`.
+        unsafe { core::slice::from_raw_parts(self.addr as *const u8, PAGE_SIZE_4K) }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    fn data_mut(&self) -> &mut [u8] {
+        // SAFETY: the page is only written to under the lock of the pages,
+        // or through shared mappings which write to it concurrently anyway.
+        unsafe { core::slice::from_raw_parts_mut(self.addr as *mut u8, PAGE_SIZE_4K) }
+    }
+}
+
+impl Drop for CachedPage {
+    fn drop(&mut self) {
+        global_allocator().dealloc_pages(self.addr, 1);
+    }
+}
+
+/// The cached pages of a file, and the order they were used in.
+#[derive(Default)]
+struct Pages {
+    pages: BTreeMap<usize, CachedPage>,
+    /// The pages which are not mapped, by when they were last used.
+    unmapped: BTreeMap<u64, usize>,
+    /// Counts the uses of the pages.
+    clock: u64,
+}
+
+/// The file the cached pages are read from and written back to.
+struct Source {
+    location: Location<RawMutex>,
+    file: Mutex<File<RawMutex>>,
+}
+
+/// A file whose pages are read into memory on demand.
+pub struct CachedFile {
+    /// The file, none for anonymous shared memory, whose pages are
+    /// zero-filled and never written back.
+    source: Option<Source>,
+    size: AtomicUsize,
+    /// The modification time of the file when the pages were read, or last
+    /// written to by us.
+    modify_time: spin::Mutex<Duration>,
+    pages: Mutex<Pages>,
+    /// The pages to write back, by the number of shared mappings they are
+    /// mapped writable by. They stay dirty as long as they are mapped so, the
+    /// ones written by `write` only until they are written back.
+    dirty: spin::Mutex<BTreeMap<usize, usize>>,
+}
+
+impl CachedFile {
+    /// Cache the pages of anonymous shared memory, i.e. `MAP_SHARED |
+    /// MAP_ANONYMOUS`, so that they are mapped, shared and moved like the
+    /// ones of a file.
+    pub fn anonymous() -> Arc<Self> {
+        Arc::new(Self {
+            source: None,
+            size: AtomicUsize::new(0),
+            modify_time: spin::Mutex::new(Duration::ZERO),
+            pages: Mutex::new(Pages::default()),
+            dirty: spin::Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    /// Whether the pages are anonymous shared memory rather than a file.
+    pub fn is_anonymous(&self) -> bool {
+        self.source.is_none()
+    }
+
+    /// The current size of the file.
+    pub fn size(&self) -> usize {
+        self.size.load(Ordering::Acquire)
+    }
+
+    /// Read the file at `offset` into `buf`, bypassing the cached pages.
+    fn read_file(&self, buf: &mut [u8], offset: usize) -> LinuxResult<usize> {
+        let Some(source) = &self.source else {
+            return Ok(0);
+        };
+        let mut file = source.file.lock();
+        let mut read_len = 0;
+        while read_len < buf.len() {
+            match file.read_at(&mut buf[read_len..], (offset + read_len) as _)? {
+                0 => break,
+                len => read_len += len,
+            }
+        }
+        Ok(read_len)
+    }
+
+    /// Get the cached page `index`, which is read from the file if it is not
+    /// cached yet, and count it as used.
+    fn load<'a>(&self, pages: &'a mut Pages, index: usize) -> LinuxResult<&'a mut CachedPage> {
+        let page = match pages.pages.entry(index) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let page = CachedPage::alloc()?;
+                let offset = index * PAGE_SIZE_4K;
+                let size = self.size();
+                if offset < size {
+                    let len = PAGE_SIZE_4K.min(size - offset);
+                    self.read_file(&mut page.data_mut()[..len], offset)?;
+                }
+                entry.insert(page)
+            }
+        };
+        pages.clock += 1;
+        if !page.mapped {
+            pages.unmapped.remove(&page.last_use);
+            pages.unmapped.insert(pages.clock, index);
+        }
+        page.last_use = pages.clock;
+        Ok(page)
+    }
+
+    /// Evict the least recently used pages which are neither mapped nor
+    /// dirty, as long as more than `MAX_UNMAPPED_PAGES` are not mapped.
+    fn shrink(&self, pages: &mut Pages) {
+        // anonymous pages are only ever mapped, and cannot be read again
+        if self.source.is_none() {
+            return;
+        }
+        let excess = pages.unmapped.len().saturating_sub(MAX_UNMAPPED_PAGES);
+        if excess == 0 {
+            return;
+        }
+        let dirty = self.dirty.lock();
+        let evicted: Vec<(u64, usize)> = pages
+            .unmapped
+            .iter()
+            .filter(|(_, index)| !dirty.contains_key(index))
+            .take(excess)
+            .map(|(&last_use, &index)| (last_use, index))
+            .collect();
+        drop(dirty);
+        for (last_use, index) in evicted {
+            pages.unmapped.remove(&last_use);
+            pages.pages.remove(&index);
+        }
+    }
+
+    /// Read the file at `offset` into `buf` through the cached pages.
+    ///
+    /// Returns the number of bytes read, which is only less than the length
+    /// of `buf` at the end of the file.
+    pub fn read_at(&self, buf: &mut [u8], offset: usize) -> LinuxResult<usize> {
+        let size = self.size();
+        if offset >= size {
+            return Ok(0);
+        }
+        let len = buf.len().min(size - offset);
+        let mut pages = self.pages.lock();
+        let mut read_len = 0;
+        while read_len < len {
+            let pos = offset + read_len;
+            let page_offset = pos % PAGE_SIZE_4K;
+            let chunk = (PAGE_SIZE_4K - page_offset).min(len - read_len);
+            let page = self.load(&mut pages, pos / PAGE_SIZE_4K)?;
+            buf[read_len..read_len + chunk]
+                .copy_from_slice(&page.data()[page_offset..page_offset + chunk]);
+            read_len += chunk;
+        }
+        self.shrink(&mut pages);
+        Ok(len)
+    }
+
+    /// Write `data` to the cached pages at `offset`. The file is extended at
+    /// once if it ends before, but the data is only written back later.
+    pub fn write_at(&self, data: &[u8], offset: usize) -> LinuxResult<()> {
+        let mut pages = self.pages.lock();
+        self.write_pages(&mut pages, data, offset)?;
+        self.shrink(&mut pages);
+        Ok(())
+    }
+
+    /// Write `data` to the cached pages at the end of the file, returns the
+    /// offset it is written at.
+    pub fn append(&self, data: &[u8]) -> LinuxResult<usize> {
+        let mut pages = self.pages.lock();
+        let offset = self.size();
+        self.write_pages(&mut pages, data, offset)?;
+        self.shrink(&mut pages);
+        Ok(offset)
+    }
+
+    /// Write `data` at `offset` with the pages locked, so that concurrent
+    /// writes and reads see either all of it or nothing.
+    fn write_pages(&self, pages: &mut Pages, data: &[u8], offset: usize) -> LinuxResult<()> {
+        let end = offset + data.len();
+        if end > self.size() {
+            if let Some(source) = &self.source {
+                source.location.entry().as_file()?.resize(end as u64)?;
+            }
+            self.size.store(end, Ordering::Release);
+            self.touch()?;
+        }
+        let mut written = 0;
+        while written < data.len() {
+            let pos = offset + written;
+            let page_offset = pos % PAGE_SIZE_4K;
+            let chunk = (PAGE_SIZE_4K - page_offset).min(data.len() - written);
+            let index = pos / PAGE_SIZE_4K;
+            let page = self.load(pages, index)?;
+            page.data_mut()[page_offset..page_offset + chunk]
+                .copy_from_slice(&data[written..written + chunk]);
+            self.dirty.lock().entry(index).or_default();
+            written += chunk;
+        }
+        Ok(())
+    }
+
+    /// Truncate or extend the file to `len`, the cached data beyond it reads
+    /// as zeros if the file grows again and is not written back.
+    ///
+    /// The pages are kept, since they may still be mapped.
+    pub fn resize(&self, len: usize) -> LinuxResult<()> {
+        let pages = self.pages.lock();
+        if let Some(source) = &self.source {
+            source.location.entry().as_file()?.resize(len as u64)?;
+        }
+        for (&index, page) in pages.pages.range(len / PAGE_SIZE_4K..) {
+            let start = len.saturating_sub(index * PAGE_SIZE_4K);
+            page.data_mut()[start..].fill(0);
+        }
+        self.dirty
+            .lock()
+            .retain(|&index, writers| *writers > 0 || index * PAGE_SIZE_4K < len);
+        self.size.store(len, Ordering::Release);
+        self.touch()
+    }
+
+    /// Read the clean cached pages again, since the file was modified other
+    /// than through the cache, e.g. truncated by `O_TRUNC`. It now has `size`
+    /// bytes and was modified at `modify_time`.
+    ///
+    /// The dirty pages are kept, since what was written to them and not
+    /// written back yet is newer, except beyond the end of the file. Of the
+    /// clean ones, the mapped ones are read in place, the others dropped.
+    fn invalidate(&self, size: usize, modify_time: Duration) -> LinuxResult<()> {
+        let mut pages = self.pages.lock();
+        self.size.store(size, Ordering::Release);
+        let dirty: BTreeSet<usize> = {
+            let mut dirty = self.dirty.lock();
+            dirty.retain(|&index, writers| *writers > 0 || index * PAGE_SIZE_4K < size);
+            dirty.keys().copied().collect()
+        };
+        let Pages {
+            pages, unmapped, ..
+        } = &mut *pages;
+        pages.retain(|index, page| {
+            let keep = page.mapped || dirty.contains(index);
+            if !keep {
+                unmapped.remove(&page.last_use);
+            }
+            keep
+        });
+        for (&index, page) in pages.iter() {
+            let offset = index * PAGE_SIZE_4K;
+            let data = page.data_mut();
+            if dirty.contains(&index) {
+                data[size.saturating_sub(offset).min(PAGE_SIZE_4K)..].fill(0);
+                continue;
+            }
+            data.fill(0);
+            if offset < size {
+                let len = PAGE_SIZE_4K.min(size - offset);
+                self.read_file(&mut data[..len], offset)?;
+            }
+        }
+        *self.modify_time.lock() = modify_time;
+        Ok(())
+    }
+
+    /// Remember the modification time of the file written to by us, so that
+    /// the pages are known to be still up to date with it.
+    fn touch(&self) -> LinuxResult<()> {
+        if let Some(source) = &self.source {
+            *self.modify_time.lock() = source.location.metadata()?.modify_time;
+        }
+        Ok(())
+    }
+
+    /// Get the physical address of the page `index` of the file to map it,
+    /// which is read from the file if it is not cached yet.
+    pub fn page(&self, index: usize) -> LinuxResult<PhysAddr> {
+        let mut pages = self.pages.lock();
+        let page = self.load(&mut pages, index)?;
+        page.mapped = true;
+        let (addr, last_use) = (page.addr, page.last_use);
+        pages.unmapped.remove(&last_use);
+        Ok(virt_to_phys(VirtAddr::from(addr)))
+    }
+
+    /// Read the page `index` of the file into the cache, e.g. on
+    /// `MADV_WILLNEED`.
+    pub fn prefetch(&self, index: usize) -> LinuxResult<()> {
+        let mut pages = self.pages.lock();
+        self.load(&mut pages, index)?;
+        self.shrink(&mut pages);
+        Ok(())
+    }
+
+    /// Whether the page `index` of the file is in the cache.
+    pub fn is_cached(&self, index: usize) -> bool {
+        self.pages.lock().pages.contains_key(&index)
+    }
+
+    /// Record that the page `index` is mapped writable by a shared mapping,
+    /// and may be written to from now on.
+    pub fn map_writable(&self, index: usize) {
+        *self.dirty.lock().entry(index).or_default() += 1;
+    }
+
+    /// Record that the page `index` is no longer mapped writable by one of
+    /// the mappings, it is still written back once more.
+    pub fn unmap_writable(&self, index: usize) {
+        if let Some(writers) = self.dirty.lock().get_mut(&index) {
+            *writers = writers.saturating_sub(1);
+        }
+    }
+
+    /// Write the dirty pages in the range of page indices back to the file.
+    ///
+    /// The pages stay locked meanwhile, so that none is written to by `write`
+    /// between being written back and found clean.
+    pub fn write_back(&self, range: Range<usize>) -> LinuxResult<()> {
+        let Some(source) = &self.source else {
+            self.dirty.lock().retain(|_, writers| *writers > 0);
+            return Ok(());
+        };
+        let pages = self.pages.lock();
+        let indices: Vec<usize> = self
+            .dirty
+            .lock()
+            .range(range)
+            .map(|(&index, _)| index)
+            .collect();
+        if indices.is_empty() {
+            return Ok(());
+        }
+        let entry = source.location.entry();
+        let node = entry.as_file()?;
+        for index in indices {
+            let offset = index * PAGE_SIZE_4K;
+            let size = self.size();
+            if offset < size {
+                let data = &pages.pages[&index].data()[..PAGE_SIZE_4K.min(size - offset)];
+                node.write_at(data, offset as u64)?;
+            }
+            let mut dirty = self.dirty.lock();
+            if dirty.get(&index) == Some(&0) {
+                dirty.remove(&index);
+            }
+        }
+        // the pages are still up to date with the file written by us
+        self.touch()
+    }
+
+    /// Write all the dirty pages back to the file, e.g. on `fsync`.
+    pub fn sync(&self) -> LinuxResult<()> {
+        self.write_back(0..usize::MAX)
+    }
+
+    /// Copy the page `index` of the file to `buf`.
+    pub fn read_page(&self, index: usize, buf: &mut [u8]) -> LinuxResult<()> {
+        let mut pages = self.pages.lock();
+        let data = self.load(&mut pages, index)?.data();
+        let len = buf.len().min(PAGE_SIZE_4K);
+        buf[..len].copy_from_slice(&data[..len]);
+        self.shrink(&mut pages);
+        Ok(())
+    }
+}
+
+impl Drop for CachedFile {
+    fn drop(&mut self) {
+        if let Err(err) = self.sync() {
+            warn!("Failed to write back a cached file: {:?}", err);
+        }
+    }
+}
+
+/// The cached files by device and inode number, alive as long as they are
+/// open or mapped somewhere.
+static FILES: spin::Mutex<BTreeMap<(u64, u64), Weak<CachedFile>>> =
+    spin::Mutex::new(BTreeMap::new());
+
+/// Get the cached file at `location`.
+///
+/// The cached pages are read again if the file was modified since they were
+/// read, other than through the cache.
+pub fn open(location: Location<RawMutex>) -> LinuxResult<Arc<CachedFile>> {
+    let metadata = location.metadata()?;
+    let key = (metadata.device, metadata.inode);
+    let mut files = FILES.lock();
+    if let Some(file) = files.get(&key).and_then(Weak::upgrade) {
+        if file.size() != metadata.size as usize || *file.modify_time.lock() != metadata.modify_time
+        {
+            file.invalidate(metadata.size as usize, metadata.modify_time)?;
+        }
+        return Ok(file);
+    }
+    let file = Arc::new(CachedFile {
+        source: Some(Source {
+            location: location.clone(),
+            file: Mutex::new(File::new(location, FileFlags::READ)),
+        }),
+        size: AtomicUsize::new(metadata.size as usize),
+        modify_time: spin::Mutex::new(metadata.modify_time),
+        pages: Mutex::new(Pages::default()),
+        dirty: spin::Mutex::new(BTreeMap::new()),
+    });
+    files.retain(|_, file| file.strong_count() > 0);
+    files.insert(key, Arc::downgrade(&file));
+    Ok(file)
+}
+
+/// Forget the cached file whose last link was removed, by its metadata from
+/// before, so that a new file reusing its inode number does not get its
+/// pages. Those who have it open or mapped keep using it.
+pub fn unlinked(metadata: &Metadata) {
+    if metadata.n_link <= 1 {
+        FILES.lock().remove(&(metadata.device, metadata.inode));
+    }
+}
+
+/// Write the dirty pages of the file at `location` back to it, if it is
+/// cached.
+pub fn sync(location: &Location<RawMutex>) -> LinuxResult<()> {
+    let metadata = location.metadata()?;
+    let file = FILES
+        .lock()
+        .get(&(metadata.device, metadata.inode))
+        .and_then(Weak::upgrade);
+    match file {
+        Some(file) => file.sync(),
+        None => Ok(()),
+    }
+}
+
+/// Write the pages of all the cached files back, e.g. on `sync`.
+pub fn sync_all() -> LinuxResult<()> {
+    let files: Vec<_> = FILES.lock().values().filter_map(Weak::upgrade).collect();
+    for file in files {
+        file.sync()?;
+    }
+    Ok(())
+}
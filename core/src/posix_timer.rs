@@ -0,0 +1,350 @@
+//! POSIX per-process timers, see `timer_create(2)`.
+
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use axhal::time::monotonic_time_nanos;
+use axsignal::{SignalInfo, SignalSet, Signo};
+use core::sync::atomic::{AtomicU64, Ordering};
+use linux_raw_sys::general::{__sifields__bindgen_ty_2, SI_TIMER, sigval};
+use spin::Mutex;
+use undefined_process::Pid;
+use undefined_process::process::get_process;
+
+use crate::itimer::{ITimerValue, SignalManager};
+use crate::process::{ThreadData, get_thread_data};
+use crate::timer::AlarmTimer;
+
+/// The clock a POSIX timer counts on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerClock {
+    /// `CLOCK_REALTIME`, which only differs from the monotonic clock for
+    /// absolute expiration times, moved along when the clock is set.
+    Realtime,
+    /// `CLOCK_MONOTONIC` and `CLOCK_BOOTTIME`.
+    Monotonic,
+    /// `CLOCK_PROCESS_CPUTIME_ID`, the CPU time consumed by all threads.
+    ProcessCpu,
+}
+
+/// How the expiration of a POSIX timer is notified, see `sigevent(7)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerNotify {
+    /// `SIGEV_NONE`
+    None,
+    /// `SIGEV_SIGNAL`, the signal is sent to the process.
+    Signal(Signo),
+    /// `SIGEV_THREAD_ID`, the signal is sent to the given thread.
+    ThreadSignal(Signo, Pid),
+}
+
+#[derive(Default)]
+struct Overrun {
+    /// Whether the signal of the timer is queued and not delivered yet.
+    queued: bool,
+    /// The overrun count of the queued signal, i.e. the expirations not
+    /// notified since it was queued.
+    pending: u64,
+    /// The overrun count of the last signal delivered.
+    last: u64,
+    /// Counts the signals queued, so that a signal delivered is known to be
+    /// the one still queued, see `si_sys_private`.
+    generation: i32,
+}
+
+pub struct PosixTimer {
+    id: i32,
+    /// The owning process.
+    pid: Pid,
+    clock: TimerClock,
+    notify: TimerNotify,
+    /// The `sigev_value` passed to the signal handler.
+    sigval: usize,
+    /// The signal manager of the owning process.
+    signal: Weak<SignalManager>,
+    /// The timer on wall clocks.
+    alarm: AlarmTimer,
+    /// The timer on the process CPU clock.
+    cpu: Mutex<ITimerValue>,
+    overrun: Mutex<Overrun>,
+}
+
+impl PosixTimer {
+    pub fn clock(&self) -> TimerClock {
+        self.clock
+    }
+
+    /// Get the reload value and the time left until the next expiration.
+    pub fn get(&self) -> ITimerValue {
+        match self.clock {
+            TimerClock::ProcessCpu => *self.cpu.lock(),
+            _ => self.alarm.get(),
+        }
+    }
+
+    /// The overrun count of the last signal delivered.
+    pub fn overrun(&self) -> i32 {
+        self.overrun.lock().last.min(i32::MAX as u64) as i32
+    }
+
+    /// Notify an expiration, `missed` periods after the expected time.
+    ///
+    /// Only one signal is queued at a time, the expirations until it is
+    /// delivered are counted as its overrun.
+    fn expire(&self, missed: u64) {
+        let signo = match self.notify {
+            TimerNotify::None => return,
+            TimerNotify::Signal(signo) | TimerNotify::ThreadSignal(signo, _) => signo,
+        };
+        let thread_data = match self.notify {
+            TimerNotify::ThreadSignal(_, tid) => match get_thread_data(tid) {
+                Some(thread_data) => Some(thread_data),
+                None => return,
+            },
+            _ => None,
+        };
+        let Some(signal) = self.signal.upgrade() else {
+            return;
+        };
+
+        let mut overrun = self.overrun.lock();
+        let sig = if overrun.queued
+            && let Some(mut sig) = self.unqueue(thread_data.as_deref(), signo)
+        {
+            overrun.pending += missed + 1;
+            self.fill(&mut sig, &overrun);
+            sig
+        } else {
+            // not queued, or it is being delivered right now
+            overrun.queued = true;
+            overrun.pending = missed;
+            overrun.generation = overrun.generation.wrapping_add(1);
+            let mut sig = SignalInfo::new(signo, SI_TIMER as u32);
+            self.fill(&mut sig, &overrun);
+            sig
+        };
+        drop(overrun);
+        match thread_data {
+            Some(thread_data) => thread_data.signal.send_signal(sig),
+            None => signal.send_signal(sig),
+        };
+    }
+
+    fn fill(&self, sig: &mut SignalInfo, overrun: &Overrun) {
+        unsafe {
+            sig.0.__bindgen_anon_1.__bindgen_anon_1._sifields._timer = __sifields__bindgen_ty_2 {
+                _tid: self.id,
+                _overrun: overrun.pending.min(i32::MAX as u64) as i32,
+                _sigval: sigval {
+                    sival_ptr: self.sigval as _,
+                },
+                _sys_private: overrun.generation,
+            };
+        }
+    }
+
+    /// Take the queued signal of the timer out of the pending signals, to
+    /// queue it again with a new overrun count.
+    ///
+    /// `thread_data` is the thread it is sent to, or none for the process,
+    /// whose signals are taken through any of its threads. The other signals
+    /// taken meanwhile are queued again for that thread.
+    fn unqueue(&self, thread_data: Option<&ThreadData>, signo: Signo) -> Option<SignalInfo> {
+        let owned;
+        let thread_data = match thread_data {
+            Some(thread_data) => thread_data,
+            None => {
+                let thread = get_process(self.pid)?.get_threads().into_iter().next()?;
+                owned = get_thread_data(thread.get_tid())?;
+                &owned
+            }
+        };
+        let mut set = SignalSet::default();
+        set.add(signo);
+        let mut others = Vec::new();
+        let mut found = None;
+        while let Some(sig) = thread_data.signal.dequeue_signal(&set) {
+            if timer_id(&sig) == Some(self.id) {
+                found = Some(sig);
+                break;
+            }
+            others.push(sig);
+        }
+        for sig in others {
+            thread_data.signal.send_signal(sig);
+        }
+        found
+    }
+}
+
+/// The ID of the timer which sent `sig`, if it is sent by a timer.
+fn timer_id(sig: &SignalInfo) -> Option<i32> {
+    if sig.code() as i32 != SI_TIMER {
+        return None;
+    }
+    // Safety: the timer fields are the ones used by signals of timers.
+    Some(unsafe {
+        sig.0
+            .__bindgen_anon_1
+            .__bindgen_anon_1
+            ._sifields
+            ._timer
+            ._tid
+    })
+}
+
+/// The POSIX timers of a process.
+#[derive(Default)]
+pub struct PosixTimers {
+    timers: Mutex<BTreeMap<i32, Arc<PosixTimer>>>,
+    /// The CPU time consumed by all threads of the process.
+    cpu_time_ns: AtomicU64,
+}
+
+impl PosixTimers {
+    /// Create a disarmed timer, returns its ID.
+    ///
+    /// If `sigval` is `None`, the timer ID is passed to the signal handler.
+    pub fn create(
+        &self,
+        pid: Pid,
+        signal: &Arc<SignalManager>,
+        clock: TimerClock,
+        notify: TimerNotify,
+        sigval: Option<usize>,
+    ) -> i32 {
+        let mut timers = self.timers.lock();
+        let id = (0..)
+            .find(|id| !timers.contains_key(id))
+            .expect("no free timer ID");
+        timers.insert(
+            id,
+            Arc::new(PosixTimer {
+                id,
+                pid,
+                clock,
+                notify,
+                sigval: sigval.unwrap_or(id as usize),
+                signal: Arc::downgrade(signal),
+                alarm: AlarmTimer::default(),
+                cpu: Mutex::new(ITimerValue::default()),
+                overrun: Mutex::new(Overrun::default()),
+            }),
+        );
+        id
+    }
+
+    pub fn get(&self, id: i32) -> Option<Arc<PosixTimer>> {
+        self.timers.lock().get(&id).cloned()
+    }
+
+    /// Record that `sig` is delivered, if it is the signal queued by one of
+    /// the timers, so that the timer queues a new one on its next expiration.
+    pub fn delivered(&self, sig: &SignalInfo) {
+        let Some(timer) = timer_id(sig).and_then(|id| self.get(id)) else {
+            return;
+        };
+        // Safety: checked to be the signal of a timer above.
+        let fields = unsafe { sig.0.__bindgen_anon_1.__bindgen_anon_1._sifields._timer };
+        let mut overrun = timer.overrun.lock();
+        if overrun.queued && fields._sys_private == overrun.generation {
+            overrun.queued = false;
+            overrun.last = fields._overrun as u64;
+            overrun.pending = 0;
+        }
+    }
+
+    /// Delete a timer, which also disarms it.
+    pub fn delete(&self, id: i32) -> Option<Arc<PosixTimer>> {
+        let timer = self.timers.lock().remove(&id)?;
+        timer.alarm.set(0, 0, |_| {});
+        *timer.cpu.lock() = ITimerValue::default();
+        Some(timer)
+    }
+
+    /// Delete all timers, e.g. on `execve`.
+    pub fn clear(&self) {
+        let ids: Vec<_> = self.timers.lock().keys().copied().collect();
+        for id in ids {
+            self.delete(id);
+        }
+    }
+
+    /// The CPU time consumed by all threads of the process.
+    pub fn cpu_time_ns(&self) -> u64 {
+        self.cpu_time_ns.load(Ordering::Relaxed)
+    }
+
+    /// Arm or disarm a timer, returns the old value.
+    ///
+    /// If `absolute` is set, `new.remained_ns` is the expiration time on the
+    /// clock of the timer instead of a relative one.
+    pub fn set(&self, timer: &Arc<PosixTimer>, new: ITimerValue, absolute: bool) -> ITimerValue {
+        let old = timer.get();
+        {
+            // a signal still queued is delivered with the old count
+            let mut overrun = timer.overrun.lock();
+            overrun.pending = 0;
+            overrun.last = 0;
+        }
+        let weak = Arc::downgrade(timer);
+        let on_expire = move |missed| {
+            if let Some(timer) = weak.upgrade() {
+                timer.expire(missed);
+            }
+        };
+        if absolute && timer.clock == TimerClock::Realtime {
+            // follows the realtime clock when it is set
+            timer
+                .alarm
+                .set_realtime(new.remained_ns, new.interval_ns, on_expire);
+            return old;
+        }
+
+        let mut remained = new.remained_ns;
+        if absolute && remained != 0 {
+            let now = match timer.clock {
+                TimerClock::ProcessCpu => self.cpu_time_ns(),
+                _ => monotonic_time_nanos(),
+            };
+            // An expiration time in the past expires at once.
+            remained = remained.saturating_sub(now).max(1);
+        }
+
+        match timer.clock {
+            TimerClock::ProcessCpu => {
+                *timer.cpu.lock() = ITimerValue {
+                    interval_ns: new.interval_ns,
+                    remained_ns: remained,
+                };
+            }
+            _ => {
+                let deadline = match remained {
+                    0 => 0,
+                    remained => monotonic_time_nanos() + remained,
+                };
+                timer.alarm.set(deadline, new.interval_ns, on_expire);
+            }
+        }
+        old
+    }
+
+    /// Charge the CPU time spent by a thread of the process.
+    pub(crate) fn charge(&self, delta: u64) {
+        if delta == 0 {
+            return;
+        }
+        self.cpu_time_ns.fetch_add(delta, Ordering::Relaxed);
+        let expired: Vec<_> = self
+            .timers
+            .lock()
+            .values()
+            .filter(|timer| timer.clock == TimerClock::ProcessCpu)
+            .filter(|timer| timer.cpu.lock().charge(delta))
+            .cloned()
+            .collect();
+        for timer in expired {
+            timer.expire(0);
+        }
+    }
+}
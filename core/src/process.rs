@@ -1,18 +1,21 @@
+use crate::aspace::UserAddrSpace;
+use crate::itimer::ITimers;
+use crate::posix_timer::PosixTimers;
 use crate::resource::ResourceLimits;
+use crate::rusage::{ProcessUsage, UsageCounters};
 use crate::shared_memory::SharedMemory;
-use crate::task::WaitQueueWrapper;
+use crate::task::{KillableWait, WaitQueueWrapper, wait_killable_until};
 use alloc::collections::BTreeMap;
 use alloc::string::String;
 use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
-use axmm::{AddrSpace, kernel_aspace};
 use axns::AxNamespace;
-use axsignal::Signo;
 use axsignal::api::{ProcessSignalManager, SignalActions, ThreadSignalManager};
+use axsignal::{SignalSet, Signo};
 use axsync::RawMutex;
 use axtask::WaitQueue;
-use core::sync::atomic::{AtomicUsize, Ordering};
-use memory_addr::{VirtAddr, VirtAddrRange};
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use memory_addr::VirtAddr;
 use spin::Mutex;
 use undefined_process::Pid;
 
@@ -21,12 +24,8 @@ pub struct ProcessData {
     pub command_line: Mutex<Vec<String>>,
 
     // address space related are shared with all threads
-    /// The virtual memory address space.
-    pub addr_space: Arc<Mutex<AddrSpace>>,
-    /// The user heap bottom
-    heap_bottom: AtomicUsize,
-    /// The user heap top
-    heap_top: AtomicUsize,
+    /// The virtual memory address space, which is replaced on `execve`.
+    addr_space: Mutex<Arc<Mutex<UserAddrSpace>>>,
     /// resource limits
     pub resource_limits: Arc<Mutex<ResourceLimits>>,
     /// The child exit wait queue
@@ -39,20 +38,33 @@ pub struct ProcessData {
     pub futex_table: Mutex<BTreeMap<usize, Arc<WaitQueue>>>,
     /// Shared memory
     pub shared_memory: Mutex<BTreeMap<VirtAddr, Arc<SharedMemory>>>,
+    /// The interval timers, see `setitimer(2)`
+    pub itimers: ITimers,
+    /// The POSIX timers, see `timer_create(2)`
+    pub posix_timers: PosixTimers,
+    /// The seconds of CPU time `RLIMIT_CPU` is checked again at, so that it is
+    /// not looked up on every trap. Reset to 0 when the limit is changed.
+    pub cpu_limit_check_secs: AtomicU64,
+    /// The resource usage, see `getrusage(2)`
+    pub usage: Arc<ProcessUsage>,
+    /// Whether it is stopped, see [`JobControl`]
+    pub job: JobControl,
+    /// The execution domain, see `personality(2)`
+    pub personality: AtomicU32,
+    /// Completed when all the threads have exited, see [`ExitDone`]
+    pub exit_done: ExitDone,
 }
 
 impl ProcessData {
     pub fn new(
         command_line: Vec<String>,
-        addr_space: Arc<Mutex<AddrSpace>>,
+        addr_space: Arc<Mutex<UserAddrSpace>>,
         signal_actions: Arc<axsync::Mutex<SignalActions>>,
         exit_signal: Option<Signo>,
     ) -> Self {
         Self {
             command_line: Mutex::new(command_line),
-            addr_space,
-            heap_bottom: AtomicUsize::new(axconfig::plat::USER_HEAP_BASE),
-            heap_top: AtomicUsize::new(axconfig::plat::USER_HEAP_BASE),
+            addr_space: Mutex::new(addr_space),
             resource_limits: Arc::new(Mutex::new(ResourceLimits::new())),
             futex_table: Mutex::new(BTreeMap::new()),
             child_exit_wq: WaitQueue::new(),
@@ -62,23 +74,35 @@ impl ProcessData {
                 axconfig::plat::SIGNAL_TRAMPOLINE,
             )),
             shared_memory: Mutex::new(BTreeMap::new()),
+            itimers: ITimers::default(),
+            posix_timers: PosixTimers::default(),
+            cpu_limit_check_secs: AtomicU64::new(0),
+            usage: Arc::default(),
+            job: JobControl::default(),
+            personality: AtomicU32::new(0),
+            exit_done: ExitDone::default(),
         }
     }
 
-    pub fn get_heap_bottom(&self) -> usize {
-        self.heap_bottom.load(Ordering::Acquire)
-    }
-
-    pub fn set_heap_bottom(&self, bottom: usize) {
-        self.heap_bottom.store(bottom, Ordering::Release)
+    /// The virtual memory address space.
+    pub fn addr_space(&self) -> Arc<Mutex<UserAddrSpace>> {
+        self.addr_space.lock().clone()
     }
 
-    pub fn get_heap_top(&self) -> usize {
-        self.heap_top.load(Ordering::Acquire)
+    /// Replace the address space, e.g. on `execve`.
+    pub fn replace_addr_space(&self, addr_space: Arc<Mutex<UserAddrSpace>>) {
+        let old = core::mem::replace(&mut *self.addr_space.lock(), addr_space);
+        self.usage.own.update_maxrss(old.lock().resident_size());
     }
 
-    pub fn set_heap_top(&self, top: usize) {
-        self.heap_top.store(top, Ordering::Release)
+    /// Keep what the parent is told when it waits for the process `pid`, as
+    /// one of its threads exits, the last one leaves the final record.
+    pub fn keep_exit_record(&self, pid: Pid) {
+        let record = ExitRecord {
+            usage: self.usage.clone(),
+            clone_child: self.is_clone_child(),
+        };
+        EXIT_RECORDS.lock().insert(pid, record);
     }
 
     /// Linux manual: A "clone" child is one which delivers no signal, or a
@@ -91,14 +115,151 @@ impl ProcessData {
 impl Drop for ProcessData {
     fn drop(&mut self) {
         trace!("process data drop: process={:?}", self.command_line.lock());
-        // TODO: prevent memory leak
-        if !cfg!(target_arch = "aarch64") && !cfg!(target_arch = "loongarch64") {
-            // See [`crate::new_user_aspace`]
-            let kernel = kernel_aspace().lock();
-            self.addr_space
-                .lock()
-                .clear_mappings(VirtAddrRange::from_start_size(kernel.base(), kernel.size()));
+    }
+}
+
+/// The completion a `vfork` parent waits on, until the child calls `execve`
+/// or exits.
+#[derive(Default)]
+pub struct VforkDone {
+    done: AtomicBool,
+    wq: WaitQueue,
+}
+
+impl VforkDone {
+    /// Block until the child releases the address space of the parent, or
+    /// until the parent is killed.
+    pub fn wait(&self) {
+        wait_killable_until(&self.wq, || self.done.load(Ordering::Acquire));
+    }
+
+    fn complete(&self) {
+        self.done.store(true, Ordering::Release);
+        self.wq.notify_all(false);
+    }
+}
+
+/// The completion the kernel waits on for a process it runs, i.e. the init
+/// process, as its task may be replaced on `execve` and cannot be joined.
+#[derive(Default)]
+pub struct ExitDone {
+    done: AtomicBool,
+    /// The exit code of the leader, as joining its task would return.
+    exit_code: AtomicI32,
+    wq: WaitQueue,
+}
+
+impl ExitDone {
+    /// Block until all the threads have exited, returns the exit code of the
+    /// leader.
+    pub fn wait(&self) -> i32 {
+        self.wq.wait_until(|| self.done.load(Ordering::Acquire));
+        self.exit_code.load(Ordering::Relaxed)
+    }
+
+    /// Keep the exit code of the leader as it exits.
+    pub fn set_exit_code(&self, exit_code: i32) {
+        self.exit_code.store(exit_code, Ordering::Relaxed);
+    }
+
+    pub fn complete(&self) {
+        self.done.store(true, Ordering::Release);
+        self.wq.notify_all(false);
+    }
+}
+
+/// A change of the state of a process other than its exit, which its parent
+/// is told about when it waits for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobEvent {
+    /// Stopped by the signal, e.g. `SIGSTOP`.
+    Stopped(Signo),
+    /// Continued by `SIGCONT`.
+    Continued,
+}
+
+/// The job control state of a process, whose threads are stopped by
+/// `SIGSTOP` and the like until `SIGCONT`.
+#[derive(Default)]
+pub struct JobControl {
+    stopped: AtomicBool,
+    /// The last change the parent has not waited for yet.
+    event: Mutex<Option<JobEvent>>,
+    wq: WaitQueue,
+}
+
+impl JobControl {
+    /// Stop the process, returns whether it was running.
+    pub fn stop(&self, signo: Signo) -> bool {
+        let mut event = self.event.lock();
+        if self.stopped.swap(true, Ordering::AcqRel) {
+            return false;
         }
+        *event = Some(JobEvent::Stopped(signo));
+        true
+    }
+
+    /// Let the stopped process run again, returns whether it was stopped.
+    ///
+    /// The parent is told it is continued if `report` is set, i.e. unless it
+    /// is woken up to be killed.
+    pub fn resume(&self, report: bool) -> bool {
+        let mut event = self.event.lock();
+        if !self.stopped.swap(false, Ordering::AcqRel) {
+            return false;
+        }
+        if report {
+            *event = Some(JobEvent::Continued);
+        }
+        drop(event);
+        self.wq.notify_all(false);
+        true
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Acquire)
+    }
+
+    /// Block the current thread while the process is stopped.
+    pub fn wait_while_stopped(&self) {
+        self.wq.wait_until(|| !self.is_stopped());
+    }
+
+    /// The last change the parent has not waited for yet, if it is `wanted`
+    /// by it, which is then forgotten unless `keep` is set.
+    pub fn wait_event(
+        &self,
+        wanted: impl FnOnce(JobEvent) -> bool,
+        keep: bool,
+    ) -> Option<JobEvent> {
+        let mut event = self.event.lock();
+        let found = (*event).filter(|&found| wanted(found))?;
+        if !keep {
+            *event = None;
+        }
+        Some(found)
+    }
+}
+
+/// What the parent of a process is told when it waits for it, kept from when
+/// its threads exit until then, as the data of the process may be released
+/// before.
+#[derive(Clone)]
+pub struct ExitRecord {
+    pub usage: Arc<ProcessUsage>,
+    /// See [`ProcessData::is_clone_child`].
+    pub clone_child: bool,
+}
+
+static EXIT_RECORDS: Mutex<BTreeMap<Pid, ExitRecord>> = Mutex::new(BTreeMap::new());
+
+/// The record of the exited process `pid`, which is forgotten unless `keep`
+/// is set, i.e. it is not reaped.
+pub fn exit_record(pid: Pid, keep: bool) -> Option<ExitRecord> {
+    let mut exit_records = EXIT_RECORDS.lock();
+    match keep {
+        true => exit_records.get(&pid).cloned(),
+        false => exit_records.remove(&pid),
     }
 }
 
@@ -119,6 +280,24 @@ pub struct ThreadData {
     pub addr_set_child_tid: AtomicUsize,
     /// The thread-level signal manager
     pub signal: ThreadSignalManager<RawMutex, WaitQueueWrapper>,
+    /// The signal mask to restore after a `ppoll`-like wait, see `with_sigmask`.
+    ///
+    /// It is taken away when a signal is delivered during the wait, because the
+    /// original mask is then saved in the signal frame instead.
+    pub saved_sigmask: Mutex<Option<SignalSet>>,
+    /// The `vfork` parent waiting for this thread, see [`ThreadData::vfork_release`].
+    pub vfork_done: Mutex<Option<Arc<VforkDone>>>,
+    /// The resource usage of this thread, see `getrusage(2)`
+    pub usage: UsageCounters,
+    /// Set when another thread calling `execve` kills this one, which then
+    /// exits on its own instead of with the whole group.
+    pub killed_by_exec: AtomicBool,
+    /// Set once a wait which polls by yielding counted its voluntary context
+    /// switch, see [`crate::task::count_polling_switch`].
+    pub polling: AtomicBool,
+    /// The wait queue the thread blocks on in a killable wait, see
+    /// [`crate::task::wait_killable_until`].
+    pub(crate) killable_wait: Mutex<Option<KillableWait>>,
     // File system context
     // pub fs_context: Mutex<Arc<FsContext<RawMutex>>>,
 }
@@ -130,17 +309,53 @@ impl ThreadData {
             addr_clear_child_tid: AtomicUsize::new(0),
             addr_set_child_tid: AtomicUsize::new(0),
             signal: ThreadSignalManager::new(process_data.signal.clone()),
+            saved_sigmask: Mutex::new(None),
+            vfork_done: Mutex::new(None),
+            usage: UsageCounters::default(),
+            killed_by_exec: AtomicBool::new(false),
+            polling: AtomicBool::new(false),
+            killable_wait: Mutex::new(None),
             process_data,
             tid,
         }
     }
+
+    /// Wake up the `vfork` parent, if any, since the address space it lent to
+    /// this thread is no longer used, i.e. on `execve` or exit.
+    pub fn vfork_release(&self) {
+        if let Some(vfork_done) = self.vfork_done.lock().take() {
+            vfork_done.complete();
+        }
+    }
+
+    /// Whether `SIGKILL` is pending for the thread, which is going to exit.
+    pub fn is_killed(&self) -> bool {
+        self.signal.pending().has(Signo::SIGKILL)
+            || self.process_data.signal.pending().has(Signo::SIGKILL)
+    }
+
+    /// Wake the thread up from a killable wait, after `SIGKILL` is sent to it.
+    pub fn wake_killable(&self) {
+        if let Some(wait) = &*self.killable_wait.lock() {
+            // Safety: the wait queue is borrowed by the waiting thread as long
+            // as it is registered.
+            unsafe { &*wait.0 }.notify_all(false);
+        }
+    }
 }
 
 impl Drop for ThreadData {
     fn drop(&mut self) {
         // remove form the thread data table
         trace!("thread data drop: tid={}", self.tid);
-        assert!(!THREAD_DATA_TABLE.lock().remove(&self.tid).is_none())
+        let mut thread_data_table = THREAD_DATA_TABLE.lock();
+        let weak_thread_data = thread_data_table
+            .get(&self.tid)
+            .expect("Thread data is not in the table");
+        // The ID may have been taken over by a thread calling `execve`.
+        if core::ptr::eq(weak_thread_data.as_ptr(), self) {
+            thread_data_table.remove(&self.tid);
+        }
     }
 }
 
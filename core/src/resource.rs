@@ -62,13 +62,17 @@ impl ResourceLimit {
     }
 }
 
+/// The default soft limit of the size of the stack, the same as Linux.
+const DEFAULT_STACK_LIMIT: u64 = 8 * 1024 * 1024;
+
+#[derive(Clone)]
 pub struct ResourceLimits([ResourceLimit; RLIM_NLIMITS as usize]);
 
 impl ResourceLimits {
     pub fn new() -> Self {
         let mut limits = [ResourceLimit::new_infinite(); RLIM_NLIMITS as usize];
         limits[ResourceLimitType::STACK as usize] =
-            ResourceLimit::new(axconfig::plat::USER_STACK_SIZE as u64, RLIMIT_INFINITY);
+            ResourceLimit::new(DEFAULT_STACK_LIMIT, RLIMIT_INFINITY);
         limits[ResourceLimitType::CORE as usize] = ResourceLimit::new(0, RLIMIT_INFINITY);
         limits[ResourceLimitType::NPROC as usize] = ResourceLimit::new(10000, 10000);
         limits[ResourceLimitType::NOFILE as usize] =
@@ -0,0 +1,115 @@
+//! The resource usage of threads and processes, see `getrusage(2)`.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// The resource usage of a thread, a process, or of the children of a
+/// process.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Usage {
+    pub utime_ns: u64,
+    pub stime_ns: u64,
+    /// The page faults served without reading from a file.
+    pub minflt: u64,
+    /// The page faults which read a page of a file.
+    pub majflt: u64,
+    /// The times the thread blocked, e.g. waiting for I/O.
+    pub nvcsw: u64,
+    /// The times the thread was preempted.
+    pub nivcsw: u64,
+    /// The largest resident set size, in bytes.
+    pub maxrss: u64,
+}
+
+impl Usage {
+    /// Add up `other`, except the resident set size, of which the largest
+    /// one is kept.
+    pub fn add(&mut self, other: &Usage) {
+        self.utime_ns += other.utime_ns;
+        self.stime_ns += other.stime_ns;
+        self.minflt += other.minflt;
+        self.majflt += other.majflt;
+        self.nvcsw += other.nvcsw;
+        self.nivcsw += other.nivcsw;
+        self.maxrss = self.maxrss.max(other.maxrss);
+    }
+}
+
+/// The counters of a [`Usage`], updated as the thread runs.
+#[derive(Default)]
+pub struct UsageCounters {
+    utime_ns: AtomicU64,
+    stime_ns: AtomicU64,
+    minflt: AtomicU64,
+    majflt: AtomicU64,
+    nvcsw: AtomicU64,
+    nivcsw: AtomicU64,
+    maxrss: AtomicU64,
+}
+
+impl UsageCounters {
+    pub fn charge_time(&self, user_ns: u64, kernel_ns: u64) {
+        self.utime_ns.fetch_add(user_ns, Ordering::Relaxed);
+        self.stime_ns.fetch_add(kernel_ns, Ordering::Relaxed);
+    }
+
+    pub fn count_fault(&self, major: bool) {
+        match major {
+            true => self.majflt.fetch_add(1, Ordering::Relaxed),
+            false => self.minflt.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+
+    pub fn count_switch(&self, voluntary: bool) {
+        match voluntary {
+            true => self.nvcsw.fetch_add(1, Ordering::Relaxed),
+            false => self.nivcsw.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+
+    /// Record the resident set size `rss` in bytes, if it is the largest one.
+    pub fn update_maxrss(&self, rss: usize) {
+        self.maxrss.fetch_max(rss as u64, Ordering::Relaxed);
+    }
+
+    /// Add up `usage`, see [`Usage::add`].
+    pub fn add(&self, usage: &Usage) {
+        self.charge_time(usage.utime_ns, usage.stime_ns);
+        self.minflt.fetch_add(usage.minflt, Ordering::Relaxed);
+        self.majflt.fetch_add(usage.majflt, Ordering::Relaxed);
+        self.nvcsw.fetch_add(usage.nvcsw, Ordering::Relaxed);
+        self.nivcsw.fetch_add(usage.nivcsw, Ordering::Relaxed);
+        self.maxrss.fetch_max(usage.maxrss, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> Usage {
+        Usage {
+            utime_ns: self.utime_ns.load(Ordering::Relaxed),
+            stime_ns: self.stime_ns.load(Ordering::Relaxed),
+            minflt: self.minflt.load(Ordering::Relaxed),
+            majflt: self.majflt.load(Ordering::Relaxed),
+            nvcsw: self.nvcsw.load(Ordering::Relaxed),
+            nivcsw: self.nivcsw.load(Ordering::Relaxed),
+            maxrss: self.maxrss.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The resource usage of a process, and of the children it has waited for.
+#[derive(Default)]
+pub struct ProcessUsage {
+    /// The usage of all the threads of the process.
+    pub own: UsageCounters,
+    /// The usage of the children which are waited for, including the one of
+    /// their own children.
+    pub children: UsageCounters,
+}
+
+impl ProcessUsage {
+    /// The usage of the process and of its children, which its parent adds
+    /// up when it waits for it.
+    pub fn total(&self) -> Usage {
+        let mut usage = self.own.get();
+        usage.add(&self.children.get());
+        usage
+    }
+}
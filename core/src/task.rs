@@ -2,15 +2,19 @@ use core::{alloc::Layout, sync::atomic::Ordering};
 
 use crate::ctypes::TimeStat;
 use crate::process::{ProcessData, ThreadData};
+use crate::resource::ResourceLimitType;
 use alloc::{string::String, sync::Arc};
 use axhal::{
     arch::{TrapFrame, UspaceContext},
+    mem::PhysAddr,
     time::{NANOS_PER_MICROS, NANOS_PER_SEC, monotonic_time_nanos},
 };
 use axns::{AxNamespace, AxNamespaceIf};
+use axsignal::{SignalInfo, Signo};
 use axtask::{TaskExtRef, TaskInner, WaitQueue, current};
-use core::cell::RefCell;
+use core::cell::{Cell, RefCell};
 use core::time::Duration;
+use linux_raw_sys::general::SI_KERNEL;
 use spin::Once;
 use undefined_process::process::Process;
 use undefined_process::thread::Thread;
@@ -51,11 +55,74 @@ impl TaskExt {
     }
 
     pub(crate) fn time_stat_from_kernel_to_user(&self, current_tick: usize) {
-        self.time.borrow_mut().switch_into_user_mode(current_tick);
+        LAST_USER_TASK.write_current(current().id().as_u64());
+        let delta = self.time.borrow_mut().switch_into_user_mode(current_tick);
+        self.charge_cpu_time(0, delta);
     }
 
     pub(crate) fn time_stat_from_user_to_kernel(&self, current_tick: usize) {
-        self.time.borrow_mut().switch_into_kernel_mode(current_tick);
+        let delta = self.time.borrow_mut().switch_into_kernel_mode(current_tick);
+        self.charge_cpu_time(delta, 0);
+    }
+
+    /// Charge the CPU time to the CPU-time timers of the process, and check
+    /// it against `RLIMIT_CPU`.
+    ///
+    /// Beyond the soft limit `SIGXCPU` is sent once a second, and `SIGKILL`
+    /// once at the hard limit.
+    fn charge_cpu_time(&self, user_ns: usize, kernel_ns: usize) {
+        let process_data = &self.thread_data.process_data;
+        self.thread_data
+            .usage
+            .charge_time(user_ns as u64, kernel_ns as u64);
+        process_data
+            .usage
+            .own
+            .charge_time(user_ns as u64, kernel_ns as u64);
+        process_data
+            .posix_timers
+            .charge((user_ns + kernel_ns) as u64);
+        if let Some(signo) = process_data
+            .itimers
+            .charge(user_ns as u64, kernel_ns as u64)
+        {
+            process_data
+                .signal
+                .send_signal(SignalInfo::new(signo, SI_KERNEL));
+        }
+
+        let secs = process_data.posix_timers.cpu_time_ns() / NANOS_PER_SEC;
+        let check_secs = process_data.cpu_limit_check_secs.load(Ordering::Relaxed);
+        if secs < check_secs {
+            return;
+        }
+        let limit = process_data
+            .resource_limits
+            .lock()
+            .get(&ResourceLimitType::CPU);
+        let (signo, next_check_secs) = if secs >= limit.hard {
+            (Some(Signo::SIGKILL), u64::MAX)
+        } else if secs >= limit.soft {
+            (Some(Signo::SIGXCPU), secs + 1)
+        } else {
+            (None, limit.soft)
+        };
+        // only the thread which moves the check on sends the signal
+        if process_data
+            .cpu_limit_check_secs
+            .compare_exchange(
+                check_secs,
+                next_check_secs,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            )
+            .is_ok()
+            && let Some(signo) = signo
+        {
+            process_data
+                .signal
+                .send_signal(SignalInfo::new(signo, SI_KERNEL));
+        }
     }
 
     pub(crate) fn time_stat_output(&self) -> (usize, usize) {
@@ -111,6 +178,9 @@ pub fn read_trapframe_from_kstack(kstack_top: usize) -> TrapFrame {
 
 pub fn time_stat_from_kernel_to_user() {
     let curr_task = current();
+    current_thread_data()
+        .polling
+        .store(false, Ordering::Relaxed);
     curr_task
         .task_ext()
         .time_stat_from_kernel_to_user(monotonic_time_nanos() as usize);
@@ -123,6 +193,48 @@ pub fn time_stat_from_user_to_kernel() {
         .time_stat_from_user_to_kernel(monotonic_time_nanos() as usize);
 }
 
+/// The task which returned to user space last on this CPU.
+#[percpu::def_percpu]
+static mut LAST_USER_TASK: u64 = 0;
+
+/// Count a context switch of the current thread, see `getrusage(2)`.
+///
+/// The voluntary ones are counted where the thread blocks.
+pub fn count_context_switch(voluntary: bool) {
+    let thread_data = current_thread_data();
+    thread_data.usage.count_switch(voluntary);
+    thread_data.process_data.usage.own.count_switch(voluntary);
+}
+
+/// Count the voluntary context switch of a wait which polls by yielding.
+///
+/// Such a wait yields many times before its condition holds, but blocks only
+/// once, so only the first yield is counted until the thread returns to user
+/// space.
+pub fn count_polling_switch() {
+    if !current_thread_data().polling.swap(true, Ordering::Relaxed) {
+        count_context_switch(true);
+    }
+}
+
+/// Switch to user time when returning to user space from a trap.
+///
+/// Syscalls and page faults switch to kernel time at their entry. The other
+/// traps (e.g. a timer interrupt) are not seen before, so the time since the
+/// last return is accounted as user time, and the CPU time of busy loops as
+/// well.
+///
+/// If another task returned to user space on this CPU in the meantime, the
+/// current one was preempted by it. The preemptions by kernel tasks are not
+/// noticed.
+pub fn time_stat_on_user_trap() {
+    if LAST_USER_TASK.read_current() != current().id().as_u64() {
+        count_context_switch(false);
+    }
+    time_stat_from_user_to_kernel();
+    time_stat_from_kernel_to_user();
+}
+
 pub fn time_stat_output() -> (usize, usize, usize, usize) {
     let curr_task = current();
     let (utime_ns, stime_ns) = curr_task.task_ext().time_stat_output();
@@ -161,6 +273,7 @@ pub fn create_user_task(name: String, uctx: UspaceContext) -> TaskInner {
                 unsafe { addr.write(current_thread().get_tid()) };
             }
 
+            time_stat_from_kernel_to_user();
             unsafe { uctx.enter_uspace(kstack_top) }
         },
         name,
@@ -168,6 +281,65 @@ pub fn create_user_task(name: String, uctx: UspaceContext) -> TaskInner {
     )
 }
 
+/// Continue the current thread as `thread` in a new task, which enters user
+/// space with `uctx` on the page table at `page_table_root`, and exit the
+/// current task.
+///
+/// The page table root of a task can only be set before it is spawned, so this
+/// is how `execve` moves the caller to a new address space. `thread` differs
+/// from the current one if the caller took over the ID of the leader.
+pub fn respawn_current_task(
+    thread: Arc<Thread>,
+    thread_data: Arc<ThreadData>,
+    uctx: UspaceContext,
+    page_table_root: PhysAddr,
+) -> ! {
+    let curr = current();
+    let task_ext = curr.task_ext();
+    // Only the first task of a thread writes its thread ID to `set_child_tid`.
+    thread_data.addr_set_child_tid.store(0, Ordering::Relaxed);
+
+    let mut new_task = create_user_task(curr.name().into(), uctx);
+    new_task.ctx_mut().set_page_table_root(page_table_root);
+    let new_task_ext = TaskExt::new(thread, thread_data);
+    new_task_ext.time.replace(task_ext.time.take());
+    new_task.init_task_ext(new_task_ext);
+    drop(curr);
+
+    axtask::spawn_task(new_task);
+    axtask::exit(0)
+}
+
+/// The wait queue of a killable wait, see [`wait_killable_until`].
+pub(crate) struct KillableWait(pub(crate) *const WaitQueue);
+
+// Safety: it is only dereferenced while the waiting thread borrows the wait
+// queue, see `wait_killable_until`.
+unsafe impl Send for KillableWait {}
+
+/// Block the current thread on `wq` until `condition` holds, or until it is
+/// killed, i.e. `SIGKILL` is pending for it, like a `TASK_KILLABLE` wait of
+/// Linux. Returns whether it was killed.
+///
+/// The sender of `SIGKILL` wakes it up with [`ThreadData::wake_killable`].
+pub fn wait_killable_until(wq: &WaitQueue, condition: impl Fn() -> bool) -> bool {
+    let thread_data = current_thread_data();
+    // registered before `SIGKILL` is checked for, so that its sender either
+    // wakes the thread up, or it is seen pending
+    *thread_data.killable_wait.lock() = Some(KillableWait(wq));
+    wq.wait_until(|| condition() || thread_data.is_killed());
+    thread_data.killable_wait.lock().take();
+    thread_data.is_killed()
+}
+
+/// Block the current thread on `wq` until it is woken up, or killed, see
+/// [`wait_killable_until`]. Returns whether it was killed.
+pub fn wait_killable(wq: &WaitQueue) -> bool {
+    // checked once before blocking, and then after the wakeup
+    let woken = Cell::new(false);
+    wait_killable_until(wq, || woken.replace(true))
+}
+
 #[doc(hidden)]
 pub struct WaitQueueWrapper(WaitQueue);
 impl Default for WaitQueueWrapper {
@@ -177,6 +349,7 @@ impl Default for WaitQueueWrapper {
 }
 impl axsignal::api::WaitQueue for WaitQueueWrapper {
     fn wait_timeout(&self, timeout: Option<Duration>) -> bool {
+        count_context_switch(true);
         if let Some(timeout) = timeout {
             self.0.wait_timeout(timeout, false)
         } else {
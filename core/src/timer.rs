@@ -0,0 +1,228 @@
+//! Timers expiring on the monotonic clock, backing `ITIMER_REAL`, timerfds and
+//! the POSIX timers on wall clocks. Absolute realtime deadlines are moved
+//! along when the realtime clock is set.
+
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use axhal::time::{monotonic_time_nanos, wall_time_nanos};
+use axtask::WaitQueue;
+use core::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use core::time::Duration;
+use spin::Mutex;
+
+use crate::itimer::ITimerValue;
+
+/// Bumped every time the realtime clock is set.
+static CLOCK_SET_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// How far the realtime clock is set from the wall time of the platform.
+static REALTIME_OFFSET_NS: AtomicI64 = AtomicI64::new(0);
+
+/// The realtime clock, i.e. `CLOCK_REALTIME`, in nanoseconds since the epoch.
+pub fn realtime_nanos() -> u64 {
+    wall_time_nanos().wrapping_add_signed(REALTIME_OFFSET_NS.load(Ordering::Acquire))
+}
+
+/// Set the realtime clock to `nanos` since the epoch, e.g. by
+/// `clock_settime`.
+pub fn set_realtime(nanos: u64) {
+    let offset = nanos.wrapping_sub(wall_time_nanos()) as i64;
+    REALTIME_OFFSET_NS.store(offset, Ordering::Release);
+    clock_was_set();
+}
+
+/// Record that the realtime clock was set discontinuously, which cancels the
+/// timerfds armed with `TFD_TIMER_CANCEL_ON_SET` and moves the timers armed
+/// with an absolute realtime deadline.
+pub fn clock_was_set() {
+    CLOCK_SET_SEQ.fetch_add(1, Ordering::Release);
+    crate::vdso::update();
+
+    let skew = realtime_skew();
+    REALTIME_ALARMS.lock().retain(|state| {
+        let Some(state) = state.upgrade() else {
+            return false;
+        };
+        let mut alarm = state.alarm.lock();
+        let Some(old_skew) = alarm.realtime_skew else {
+            return false;
+        };
+        if alarm.deadline_ns == 0 {
+            return false;
+        }
+        alarm.deadline_ns = alarm
+            .deadline_ns
+            .wrapping_add_signed(old_skew.wrapping_sub(skew))
+            .max(1);
+        alarm.realtime_skew = Some(skew);
+        drop(alarm);
+        state.wq.notify_all(false);
+        true
+    });
+}
+
+/// The number of times the realtime clock has been set.
+pub fn clock_set_seq() -> u64 {
+    CLOCK_SET_SEQ.load(Ordering::Acquire)
+}
+
+/// How far the realtime clock is from the monotonic clock.
+fn realtime_skew() -> i64 {
+    realtime_nanos().wrapping_sub(monotonic_time_nanos()) as i64
+}
+
+/// The timers armed with an absolute realtime deadline, moved along when the
+/// realtime clock is set.
+static REALTIME_ALARMS: Mutex<Vec<Weak<AlarmState>>> = Mutex::new(Vec::new());
+
+#[derive(Default)]
+struct Alarm {
+    interval_ns: u64,
+    /// The monotonic deadline, 0 if the timer is disarmed.
+    deadline_ns: u64,
+    /// The skew of the realtime clock the deadline was computed with, if it
+    /// is an absolute realtime one.
+    realtime_skew: Option<i64>,
+    /// Bumped on every re-arm so that a stale waiter task knows to quit.
+    generation: u64,
+}
+
+#[derive(Default)]
+struct AlarmState {
+    alarm: Mutex<Alarm>,
+    wq: WaitQueue,
+}
+
+/// A timer that runs a callback when a monotonic deadline is reached.
+///
+/// An armed timer is backed by a kernel task sleeping until the deadline.
+#[derive(Default)]
+pub struct AlarmTimer {
+    state: Arc<AlarmState>,
+}
+
+impl AlarmTimer {
+    /// Get the reload value and the time left until the next expiration.
+    pub fn get(&self) -> ITimerValue {
+        let alarm = self.state.alarm.lock();
+        ITimerValue {
+            interval_ns: alarm.interval_ns,
+            remained_ns: match alarm.deadline_ns {
+                0 => 0,
+                // Report at least 1ns while the timer is still armed.
+                deadline => deadline.saturating_sub(monotonic_time_nanos()).max(1),
+            },
+        }
+    }
+
+    /// Arm the timer to expire at the monotonic time `deadline_ns` and then
+    /// every `interval_ns`, or disarm it if `deadline_ns` is 0.
+    ///
+    /// `on_expire` is called from the waiter task with the number of periods
+    /// that were missed since the previous expiration.
+    pub fn set(
+        &self,
+        deadline_ns: u64,
+        interval_ns: u64,
+        on_expire: impl FnMut(u64) + Send + 'static,
+    ) {
+        self.arm(deadline_ns, interval_ns, None, on_expire);
+    }
+
+    /// Arm the timer to expire at the realtime `deadline_ns` and then every
+    /// `interval_ns`, or disarm it if `deadline_ns` is 0.
+    ///
+    /// Unlike a relative one, the deadline follows the realtime clock when it
+    /// is set. A deadline in the past expires at once.
+    pub fn set_realtime(
+        &self,
+        deadline_ns: u64,
+        interval_ns: u64,
+        on_expire: impl FnMut(u64) + Send + 'static,
+    ) {
+        if deadline_ns == 0 {
+            self.arm(0, interval_ns, None, on_expire);
+            return;
+        }
+        // held so that the clock is not set before the timer is armed
+        let mut alarms = REALTIME_ALARMS.lock();
+        let state = Arc::downgrade(&self.state);
+        if !alarms.iter().any(|alarm| alarm.ptr_eq(&state)) {
+            alarms.push(state);
+        }
+        let skew = realtime_skew();
+        let deadline = deadline_ns.wrapping_add_signed(skew.wrapping_neg()).max(1);
+        self.arm(deadline, interval_ns, Some(skew), on_expire);
+    }
+
+    fn arm(
+        &self,
+        deadline_ns: u64,
+        interval_ns: u64,
+        realtime_skew: Option<i64>,
+        on_expire: impl FnMut(u64) + Send + 'static,
+    ) {
+        let mut alarm = self.state.alarm.lock();
+        alarm.generation += 1;
+        alarm.interval_ns = interval_ns;
+        alarm.deadline_ns = deadline_ns;
+        alarm.realtime_skew = realtime_skew;
+        let generation = alarm.generation;
+        drop(alarm);
+        // Wake up the previous waiter so that it quits.
+        self.state.wq.notify_all(false);
+        if deadline_ns != 0 {
+            let state = self.state.clone();
+            axtask::spawn(move || alarm_task(state, generation, on_expire));
+        }
+    }
+}
+
+impl Drop for AlarmTimer {
+    fn drop(&mut self) {
+        self.set(0, 0, |_| {});
+    }
+}
+
+/// The body of the kernel task backing an armed `AlarmTimer`.
+fn alarm_task(state: Arc<AlarmState>, generation: u64, mut on_expire: impl FnMut(u64)) {
+    loop {
+        let deadline = {
+            let alarm = state.alarm.lock();
+            if alarm.generation != generation {
+                return;
+            }
+            alarm.deadline_ns
+        };
+        let now = monotonic_time_nanos();
+        if now < deadline {
+            // the deadline is moved when the realtime clock is set
+            state
+                .wq
+                .wait_timeout_until(Duration::from_nanos(deadline - now), || {
+                    let alarm = state.alarm.lock();
+                    alarm.generation != generation || alarm.deadline_ns != deadline
+                });
+            continue;
+        }
+
+        let mut alarm = state.alarm.lock();
+        if alarm.generation != generation {
+            return;
+        }
+        let missed = match alarm.interval_ns {
+            0 => 0,
+            interval => (now - deadline) / interval,
+        };
+        alarm.deadline_ns = match alarm.interval_ns {
+            0 => 0,
+            interval => deadline + interval * (missed + 1),
+        };
+        let rearmed = alarm.deadline_ns != 0;
+        drop(alarm);
+        on_expire(missed);
+        if !rearmed {
+            return;
+        }
+    }
+}
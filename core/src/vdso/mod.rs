@@ -0,0 +1,254 @@
+//! The vDSO, a shared object mapped into every user process so that the time
+//! can be read without a syscall, see `vdso(7)`.
+//!
+//! It exports `__vdso_clock_gettime`, `__vdso_gettimeofday`, `__vdso_time`
+//! and `__vdso_getcpu`, and reads the time from the page mapped right below
+//! it. When the counter cannot be read in user space, or for the clocks it
+//! does not know, it falls back to the syscall.
+//!
+//! `__vdso_getcpu` reads the CPU from `TPIDRRO_EL0` on aarch64, and makes the
+//! syscall elsewhere. Like on Linux, riscv64 has no register for it readable
+//! in user space.
+
+use axalloc::global_allocator;
+use axerrno::AxResult;
+use axhal::mem::virt_to_phys;
+use axhal::paging::{MappingFlags, PageSize};
+use axhal::time::{NANOS_PER_SEC, monotonic_time_nanos, nanos_to_ticks};
+use axtask::AxCpuMask;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering, fence};
+use core::time::Duration;
+use memory_addr::{PAGE_SIZE_4K, VirtAddr};
+use spin::{Mutex, Once};
+
+use crate::aspace::UserAddrSpace;
+use crate::timer::realtime_nanos;
+
+macro_rules! vdso_image {
+    ($code:literal, $machine:literal) => {
+        core::arch::global_asm!(
+            include_str!("image.S"),
+            include_str!($code),
+            ".Lvdso_end:",
+            ".balign 4096",
+            ".popsection",
+            machine = const $machine,
+        );
+    };
+}
+
+#[cfg(target_arch = "x86_64")]
+vdso_image!("x86_64.S", 62);
+#[cfg(target_arch = "riscv64")]
+vdso_image!("riscv64.S", 243);
+#[cfg(target_arch = "aarch64")]
+vdso_image!("aarch64.S", 183);
+#[cfg(target_arch = "loongarch64")]
+vdso_image!("loongarch64.S", 258);
+
+unsafe extern "C" {
+    static vdso_image_start: u8;
+}
+
+/// The address the vDSO is mapped at, passed as `AT_SYSINFO_EHDR`.
+pub const VDSO_BASE: usize = axconfig::plat::VDSO_BASE;
+/// The address of the data page, found by the vDSO right below itself.
+const VDSO_DATA_BASE: usize = VDSO_BASE - PAGE_SIZE_4K;
+
+/// How often the data page is refreshed, so that the time computed from the
+/// counter does not drift from the one of the kernel.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The monotonic time the data page was last updated at.
+static UPDATED_NS: AtomicU64 = AtomicU64::new(0);
+
+/// The number of CPUs the counter can be read in user space on.
+static USER_ACCESS_CPUS: AtomicUsize = AtomicUsize::new(0);
+
+/// The data page, which the assembly reads at these offsets.
+///
+/// The nanoseconds of the monotonic clock are
+/// `base_ns + ((counter - base_counter) * mult >> 32)`, and the ones of the
+/// realtime clock are `wall_offset_ns` more.
+#[repr(C)]
+struct VdsoData {
+    /// Odd while the page is updated.
+    seq: AtomicU64,
+    /// Whether the counter can be read in user space, on every CPU, the vDSO
+    /// makes the syscall otherwise. On aarch64, the CPU can be read as well.
+    counter_usable: AtomicU64,
+    base_counter: AtomicU64,
+    base_ns: AtomicU64,
+    mult: AtomicU64,
+    wall_offset_ns: AtomicU64,
+}
+
+/// The kernel address of the data page.
+static DATA: Once<usize> = Once::new();
+static UPDATE_LOCK: Mutex<()> = Mutex::new(());
+
+fn data() -> Option<&'static VdsoData> {
+    // SAFETY: the page is allocated once and never freed.
+    DATA.get()
+        .map(|&addr| unsafe { &*(addr as *const VdsoData) })
+}
+
+/// Read the counter the monotonic clock is based on, the same way as the
+/// vDSO does.
+#[cfg(target_arch = "x86_64")]
+fn read_counter() -> Option<u64> {
+    // SAFETY: `rdtsc` is always available on x86_64.
+    Some(unsafe { core::arch::x86_64::_rdtsc() })
+}
+
+#[cfg(target_arch = "loongarch64")]
+fn read_counter() -> Option<u64> {
+    let counter: u64;
+    // SAFETY: the stable counter can be read at any privilege level.
+    unsafe { core::arch::asm!("rdtime.d {}, $zero", out(reg) counter) };
+    Some(counter)
+}
+
+#[cfg(target_arch = "riscv64")]
+fn read_counter() -> Option<u64> {
+    if USER_ACCESS_CPUS.load(Ordering::Acquire) < axconfig::SMP {
+        return None;
+    }
+    let counter: u64;
+    // SAFETY: reading the time CSR has no side effects.
+    unsafe { core::arch::asm!("rdtime {}", out(reg) counter) };
+    Some(counter)
+}
+
+#[cfg(target_arch = "aarch64")]
+fn read_counter() -> Option<u64> {
+    if USER_ACCESS_CPUS.load(Ordering::Acquire) < axconfig::SMP {
+        return None;
+    }
+    let counter: u64;
+    // SAFETY: reading the virtual counter has no side effects.
+    unsafe { core::arch::asm!("isb", "mrs {}, cntvct_el0", out(reg) counter) };
+    Some(counter)
+}
+
+/// Let user space read the counter on the current CPU, and on aarch64 which
+/// CPU it is running on.
+fn enable_user_access() {
+    #[cfg(target_arch = "riscv64")]
+    // SAFETY: `scounteren.TM` only lets user space read the time CSR.
+    unsafe {
+        core::arch::asm!("csrs scounteren, {}", in(reg) 1usize << 1)
+    };
+    #[cfg(target_arch = "aarch64")]
+    // SAFETY: `CNTKCTL_EL1.EL0VCTEN` only lets user space read the virtual
+    // counter, and `TPIDRRO_EL0` is not used otherwise.
+    unsafe {
+        core::arch::asm!(
+            "mrs {tmp}, cntkctl_el1",
+            "orr {tmp}, {tmp}, #2",
+            "msr cntkctl_el1, {tmp}",
+            "msr tpidrro_el0, {cpu}",
+            "isb",
+            tmp = out(reg) _,
+            cpu = in(reg) axhal::cpu::this_cpu_id(),
+        )
+    };
+}
+
+/// Update the data page from the clocks of the kernel, e.g. after the
+/// realtime clock is set.
+pub fn update() {
+    let Some(data) = data() else {
+        return;
+    };
+    let _guard = UPDATE_LOCK.lock();
+    update_locked(data);
+}
+
+/// Refresh the data page if it was not for a while, on the traps from user
+/// space, e.g. the timer interrupt.
+pub fn tick() {
+    let Some(data) = data() else {
+        return;
+    };
+    let elapsed = monotonic_time_nanos().saturating_sub(UPDATED_NS.load(Ordering::Relaxed));
+    if elapsed < REFRESH_INTERVAL.as_nanos() as u64 {
+        return;
+    }
+    // another CPU is refreshing it already
+    if let Some(_guard) = UPDATE_LOCK.try_lock() {
+        update_locked(data);
+    }
+}
+
+fn update_locked(data: &VdsoData) {
+    let seq = data.seq.load(Ordering::Relaxed);
+    data.seq.store(seq + 1, Ordering::Relaxed);
+    fence(Ordering::Release);
+
+    match read_counter() {
+        Some(counter) => {
+            data.base_counter.store(counter, Ordering::Relaxed);
+            data.base_ns
+                .store(monotonic_time_nanos(), Ordering::Relaxed);
+            data.counter_usable.store(1, Ordering::Relaxed);
+        }
+        None => data.counter_usable.store(0, Ordering::Relaxed),
+    }
+    let frequency = nanos_to_ticks(NANOS_PER_SEC);
+    let mult = ((NANOS_PER_SEC as u128) << 32) / frequency as u128;
+    data.mult.store(mult as u64, Ordering::Relaxed);
+    data.wall_offset_ns.store(
+        realtime_nanos().wrapping_sub(monotonic_time_nanos()),
+        Ordering::Relaxed,
+    );
+
+    data.seq.store(seq + 2, Ordering::Release);
+    UPDATED_NS.store(monotonic_time_nanos(), Ordering::Relaxed);
+}
+
+/// Allocate the data page, and let user space read the counter on every CPU.
+pub fn init() {
+    DATA.call_once(|| {
+        let addr = global_allocator()
+            .alloc_pages(1, PAGE_SIZE_4K)
+            .expect("Failed to allocate the vDSO data page");
+        // SAFETY: the page is just allocated.
+        unsafe { core::ptr::write_bytes(addr as *mut u8, 0, PAGE_SIZE_4K) };
+        addr
+    });
+    update();
+    for cpu in 0..axconfig::SMP {
+        axtask::spawn(move || {
+            let mut cpu_mask = AxCpuMask::new();
+            cpu_mask.set(cpu, true);
+            axtask::set_current_affinity(cpu_mask);
+            enable_user_access();
+            // the counter is used once it can be read on all of them
+            if USER_ACCESS_CPUS.fetch_add(1, Ordering::AcqRel) + 1 == axconfig::SMP {
+                update();
+            }
+        });
+    }
+}
+
+/// Map the vDSO and its data page to the user address space.
+pub fn map_vdso(aspace: &mut UserAddrSpace) -> AxResult {
+    let data = *DATA.get().expect("vDSO is not initialized");
+    aspace.map_linear(
+        VDSO_DATA_BASE.into(),
+        virt_to_phys(VirtAddr::from(data)),
+        PAGE_SIZE_4K,
+        MappingFlags::READ | MappingFlags::USER,
+        PageSize::Size4K,
+    )?;
+    let image = VirtAddr::from(&raw const vdso_image_start as usize);
+    aspace.map_linear(
+        VDSO_BASE.into(),
+        virt_to_phys(image),
+        PAGE_SIZE_4K,
+        MappingFlags::READ | MappingFlags::EXECUTE | MappingFlags::USER,
+        PageSize::Size4K,
+    )?;
+    Ok(())
+}
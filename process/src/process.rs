@@ -1,7 +1,7 @@
 use crate::Pid;
 use crate::process_group::{ProcessGroup, create_process_group};
 use crate::session::{Session, create_session};
-use crate::thread::{Thread, create_thread};
+use crate::thread::{Thread, create_thread, unregister_thread};
 use alloc::collections::BTreeMap;
 use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
@@ -150,12 +150,13 @@ impl Process {
         PROCESS_TABLE.lock().get(&1).cloned()
     }
 
-    pub(crate) fn exit(self: &Arc<Self>) {
+    pub(crate) fn exit(self: &Arc<Self>, exit_code: i32) {
         assert!(
             !self.is_zombie(),
             "[process] process {} is already exited",
             self.pid
         );
+        self.exit_code.store(exit_code, Ordering::Relaxed);
         self.set_zombie();
         // move children to reaper process
         let reaper = self.get_child_reaper();
@@ -198,12 +199,11 @@ impl Process {
         self.threads.lock().insert(thread.get_tid(), thread);
     }
 
-    pub(crate) fn remove_thread(self: &Arc<Self>, tid: Pid, exit_code: i32) {
-        self.threads.lock().remove(&tid);
-        if self.threads.lock().is_empty() {
-            self.exit_code.store(exit_code, Ordering::Relaxed);
-            self.exit()
-        }
+    /// Remove the thread `tid`, returns whether it was the last one.
+    pub(crate) fn remove_thread(&self, tid: Pid) -> bool {
+        let mut threads = self.threads.lock();
+        threads.remove(&tid);
+        threads.is_empty()
     }
 
     pub fn create_thread(self: &Arc<Self>) -> Arc<Thread> {
@@ -212,6 +212,24 @@ impl Process {
         create_thread(tid, Arc::downgrade(self))
     }
 
+    /// Let `thread` take over as the leader when it calls `execve` after all
+    /// other threads have exited.
+    ///
+    /// Returns the thread to go on with, whose ID is the process ID.
+    pub fn take_over_leader(self: &Arc<Self>, thread: &Arc<Thread>) -> Arc<Thread> {
+        let mut threads = self.threads.lock();
+        assert!(
+            threads.len() == 1 && threads.contains_key(&thread.get_tid()),
+            "[process] thread {} is not the last thread of process {}",
+            thread.get_tid(),
+            self.pid
+        );
+        threads.remove(&thread.get_tid());
+        drop(threads);
+        unregister_thread(thread.get_tid());
+        create_thread(self.pid, Arc::downgrade(self))
+    }
+
     pub fn get_main_thread(&self) -> Option<Arc<Thread>> {
         self.threads.lock().get(&self.pid).cloned()
     }
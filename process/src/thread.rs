@@ -28,8 +28,18 @@ impl Thread {
     }
 
     pub fn exit(self: &Arc<Self>, exit_code: i32) {
-        self.get_process().remove_thread(self.tid, exit_code);
-        THREAD_TABLE.lock().remove(&self.tid);
+        let process = self.get_process();
+        // removed from both tables at once, so that the thread calling
+        // `execve` never takes over the ID of the leader while it is still in
+        // the thread table, see `Process::take_over_leader`
+        let last = {
+            let mut thread_table = THREAD_TABLE.lock();
+            thread_table.remove(&self.tid);
+            process.remove_thread(self.tid)
+        };
+        if last {
+            process.exit(exit_code);
+        }
     }
 
     fn new(tid: Pid, process: Weak<Process>) -> Arc<Self> {
@@ -54,6 +64,11 @@ pub(crate) fn create_thread(tid: Pid, process: Weak<Process>) -> Arc<Thread> {
     thread
 }
 
+/// Remove a thread from the thread table without exiting its process.
+pub(crate) fn unregister_thread(tid: Pid) {
+    THREAD_TABLE.lock().remove(&tid);
+}
+
 pub fn get_thread(tid: Pid) -> Option<Arc<Thread>> {
     let thread_table = THREAD_TABLE.lock();
     thread_table.get(&tid).cloned()
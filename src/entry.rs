@@ -3,9 +3,11 @@ use axfs_ng::api::{FS_CONTEXT, resolve_path};
 use axhal::arch::UspaceContext;
 use axsignal::Signo;
 use spin::Mutex;
+use starry_core::aslr::LayoutOffsets;
 use starry_core::mm::{copy_from_kernel, load_user_app, map_trampoline, new_user_aspace_empty};
 use starry_core::process::{ProcessData, create_thread_data};
 use starry_core::task::{TaskExt, create_user_task};
+use starry_core::vdso::map_vdso;
 use undefined_os_api::core::file::fd::FD_TABLE;
 use undefined_process::process::Process;
 
@@ -14,9 +16,11 @@ pub fn run_user_app(args: &[String], envs: &[String]) -> Option<i32> {
     // to hold executable file and other data
     let mut uspace = new_user_aspace_empty()
         .and_then(|mut it| {
+            it.set_layout(LayoutOffsets::new(0));
             copy_from_kernel(&mut it)?;
             // signal trampoline
             map_trampoline(&mut it)?;
+            map_vdso(&mut it)?;
             Ok(it)
         })
         .expect("Failed to create user address space");
@@ -53,7 +57,8 @@ pub fn run_user_app(args: &[String], envs: &[String]) -> Option<i32> {
         Arc::default(),
         Some(Signo::SIGCHLD),
     );
-    let thread_data = create_thread_data(Arc::new(process_data), thread.get_tid());
+    let process_data = Arc::new(process_data);
+    let thread_data = create_thread_data(process_data.clone(), thread.get_tid());
 
     FD_TABLE
         .deref_from(&thread_data.namespace)
@@ -64,7 +69,8 @@ pub fn run_user_app(args: &[String], envs: &[String]) -> Option<i32> {
 
     user_task.init_task_ext(TaskExt::new(thread, thread_data));
 
-    // spawn and wait the task
-    let user_task = axtask::spawn_task(user_task);
-    user_task.join()
+    // spawn the task and wait for the process
+    // the task itself is not joined, since it is replaced on `execve`
+    axtask::spawn_task(user_task);
+    Some(process_data.exit_done.wait())
 }
@@ -28,6 +28,7 @@ fn main() {
     let root_dir = axfs_ng::api::FS_CONTEXT.lock().root_dir.clone();
     FS_CONTEXT.lock().change_root(root_dir).unwrap();
     FD_TABLE.init_new(FdTable::new());
+    starry_core::vdso::init();
     mount_all().expect("Mounting all filesystems failed");
 
     let command = include_str!(env!("AX_TESTCASES_FILE"));
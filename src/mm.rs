@@ -6,12 +6,18 @@ use axhal::trap::{PAGE_FAULT, register_trap_handler};
 use axsignal::{SignalInfo, Signo};
 use linux_raw_sys::general::SI_KERNEL;
 use starry_core::mm::is_accessing_user_memory;
-use starry_core::task::{current_process, current_process_data};
+use starry_core::resource::ResourceLimitType;
+use starry_core::task::{
+    current_process, current_process_data, current_thread_data, time_stat_from_user_to_kernel,
+};
 use undefined_os_api::imp::task::signal::send_signal_process;
 use undefined_os_api::imp::task::sys_exit_impl;
 
 #[register_trap_handler(PAGE_FAULT)]
 fn handle_page_fault(vaddr: VirtAddr, access_flags: MappingFlags, is_user: bool) -> bool {
+    if is_user {
+        time_stat_from_user_to_kernel();
+    }
     if vaddr.as_usize() > PHYS_VIRT_OFFSET {
         error!(
             "Kernel page fault at {:#x}, access_flags: {:#x?}",
@@ -26,11 +32,32 @@ fn handle_page_fault(vaddr: VirtAddr, access_flags: MappingFlags, is_user: bool)
         );
     }
 
-    if !current_process_data()
-        .addr_space
-        .lock()
-        .handle_page_fault(vaddr, access_flags)
-    {
+    let process_data = current_process_data();
+    let (stack_limit, as_limit) = {
+        let limits = process_data.resource_limits.lock();
+        (
+            limits.get_soft(&ResourceLimitType::STACK),
+            limits.get_soft(&ResourceLimitType::AS),
+        )
+    };
+    let (handled, major) = {
+        let aspace = process_data.addr_space();
+        let mut aspace = aspace.lock();
+        let major = aspace.is_major_fault(vaddr);
+        let handled = aspace.handle_page_fault(vaddr, access_flags)
+            || (aspace.grow_stack(vaddr, stack_limit, as_limit)
+                && aspace.handle_page_fault(vaddr, access_flags));
+        if handled {
+            process_data.usage.own.update_maxrss(aspace.resident_size());
+        }
+        (handled, major)
+    };
+    if handled {
+        let thread_data = current_thread_data();
+        thread_data.usage.count_fault(major);
+        process_data.usage.own.count_fault(major);
+    }
+    if !handled {
         warn!(
             "{}: segmentation fault at {:#x}, access_flags: {:#x?}, send SIGSEGV.",
             axtask::current().id_name(),
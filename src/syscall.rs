@@ -4,7 +4,7 @@ use axhal::{
     trap::{SYSCALL, register_trap_handler},
 };
 use linux_raw_sys::net::__be16;
-use starry_core::task::{time_stat_from_kernel_to_user, time_stat_from_user_to_kernel};
+use starry_core::task::time_stat_from_user_to_kernel;
 use syscalls::Sysno;
 use undefined_os_api::imp::fs::*;
 use undefined_os_api::imp::mm::*;
@@ -14,10 +14,13 @@ use undefined_os_api::imp::task::signal::*;
 use undefined_os_api::imp::task::*;
 use undefined_os_api::imp::utils::*;
 use undefined_os_api::interface::fs::epoll::*;
+use undefined_os_api::interface::fs::eventfd::*;
 use undefined_os_api::interface::fs::fd::*;
 use undefined_os_api::interface::fs::io::*;
 use undefined_os_api::interface::fs::path::*;
 use undefined_os_api::interface::fs::poll::*;
+use undefined_os_api::interface::fs::signalfd::*;
+use undefined_os_api::interface::fs::timerfd::*;
 use undefined_os_api::interface::fs::*;
 use undefined_os_api::interface::mm::shm::*;
 use undefined_os_api::interface::task::resource::*;
@@ -56,6 +59,8 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
         Sysno::getppid => sys_getppid(),
         Sysno::exit => sys_exit(tf.arg0() as _),
         Sysno::gettimeofday => sys_get_time_of_day(tf.arg0().into(), tf.arg1().into()),
+        Sysno::settimeofday => sys_settimeofday(tf.arg0().into(), tf.arg1().into()),
+        Sysno::getcpu => sys_getcpu(tf.arg0().into(), tf.arg1().into()),
         Sysno::getcwd => sys_getcwd(tf.arg0().into(), tf.arg1() as _),
         Sysno::dup => sys_dup(tf.arg0() as _),
         #[cfg(target_arch = "x86_64")]
@@ -78,7 +83,28 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
             tf.arg3().into(),
             tf.arg4() as _,
         ),
-        Sysno::wait4 => sys_wait4(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
+        Sysno::clone3 => sys_clone3(tf.arg0().into(), tf.arg1() as _),
+        Sysno::wait4 => sys_wait4(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2() as _,
+            tf.arg3().into(),
+        ),
+        Sysno::waitid => sys_waitid(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2().into(),
+            tf.arg3() as _,
+            tf.arg4().into(),
+        ),
+        Sysno::pidfd_open => sys_pidfd_open(tf.arg0() as _, tf.arg1() as _),
+        Sysno::pidfd_send_signal => sys_pidfd_send_signal(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2().into(),
+            tf.arg3() as _,
+        ),
+        Sysno::pidfd_getfd => sys_pidfd_getfd(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
         Sysno::pipe2 => sys_pipe2(tf.arg0().into(), tf.arg1() as _),
         Sysno::close => sys_close(tf.arg0() as _),
         Sysno::chdir => sys_chdir(tf.arg0().into()),
@@ -86,7 +112,14 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
         Sysno::chmod => sys_chmod(tf.arg0().into(), tf.arg1() as _),
         #[cfg(target_arch = "x86_64")]
         Sysno::chown => sys_chown(tf.arg0().into(), tf.arg1() as _, tf.arg2() as _),
-        Sysno::execve => sys_execve(tf, tf.arg0().into(), tf.arg1().into(), tf.arg2().into()),
+        Sysno::execve => sys_execve(tf.arg0().into(), tf.arg1().into(), tf.arg2().into()),
+        Sysno::execveat => sys_execveat(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2().into(),
+            tf.arg3().into(),
+            tf.arg4() as _,
+        ),
         Sysno::openat => sys_openat(
             tf.arg0() as _,
             tf.arg1().into(),
@@ -138,7 +171,19 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
         ),
         Sysno::munmap => sys_munmap(tf.arg0().into(), tf.arg1() as _),
         Sysno::mprotect => sys_mprotect(tf.arg0().into(), tf.arg1() as _, tf.arg2() as _),
+        Sysno::msync => sys_msync(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        Sysno::mremap => sys_mremap(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2() as _,
+            tf.arg3() as _,
+            tf.arg4() as _,
+        ),
         Sysno::times => sys_times(tf.arg0().into()),
+        Sysno::getitimer => sys_getitimer(tf.arg0() as _, tf.arg1().into()),
+        Sysno::setitimer => sys_setitimer(tf.arg0() as _, tf.arg1().into(), tf.arg2().into()),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::alarm => sys_alarm(tf.arg0() as _),
         Sysno::brk => sys_brk(tf.arg0() as _),
         #[cfg(target_arch = "x86_64")]
         Sysno::arch_prctl => sys_arch_prctl(tf.arg0() as _, tf.arg1().into(), tf),
@@ -147,6 +192,17 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
         Sysno::access => sys_access(tf.arg0().into(), tf.arg1() as _),
         Sysno::clock_getres => sys_clock_getres(tf.arg0() as _, tf.arg1().into()),
         Sysno::clock_gettime => sys_clock_gettime(tf.arg0() as _, tf.arg1().into()),
+        Sysno::clock_settime => sys_clock_settime(tf.arg0() as _, tf.arg1().into()),
+        Sysno::timer_create => sys_timer_create(tf.arg0() as _, tf.arg1().into(), tf.arg2().into()),
+        Sysno::timer_settime => sys_timer_settime(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2().into(),
+            tf.arg3().into(),
+        ),
+        Sysno::timer_gettime => sys_timer_gettime(tf.arg0() as _, tf.arg1().into()),
+        Sysno::timer_getoverrun => sys_timer_getoverrun(tf.arg0() as _),
+        Sysno::timer_delete => sys_timer_delete(tf.arg0() as _),
         Sysno::clock_nanosleep => sys_clock_nanosleep(
             tf.arg0() as _,
             tf.arg1() as _,
@@ -161,6 +217,25 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
             tf.arg4() as _,
             tf.arg5() as _,
         ),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::eventfd => sys_eventfd(tf.arg0() as _),
+        Sysno::eventfd2 => sys_eventfd2(tf.arg0() as _, tf.arg1() as _),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::signalfd => sys_signalfd(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
+        Sysno::signalfd4 => sys_signalfd4(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2() as _,
+            tf.arg3() as _,
+        ),
+        Sysno::timerfd_create => sys_timerfd_create(tf.arg0() as _, tf.arg1() as _),
+        Sysno::timerfd_settime => sys_timerfd_settime(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2().into(),
+            tf.arg3().into(),
+        ),
+        Sysno::timerfd_gettime => sys_timerfd_gettime(tf.arg0() as _, tf.arg1().into()),
         Sysno::epoll_create1 => sys_epoll_create(tf.arg0() as _),
         Sysno::epoll_ctl => sys_epoll_ctl(
             tf.arg0() as _,
@@ -168,12 +243,21 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
             tf.arg2() as _,
             tf.arg3().into(),
         ),
-        Sysno::epoll_pwait => sys_epoll_wait(
+        #[cfg(target_arch = "x86_64")]
+        Sysno::epoll_wait => sys_epoll_wait(
             tf.arg0() as _,
             tf.arg1().into(),
             tf.arg2() as _,
             tf.arg3() as _,
         ),
+        Sysno::epoll_pwait => sys_epoll_pwait(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2() as _,
+            tf.arg3() as _,
+            tf.arg4().into(),
+            tf.arg5() as _,
+        ),
         Sysno::exit_group => sys_exit_group(tf.arg0() as _),
         Sysno::faccessat => sys_faccessat(
             tf.arg0() as _,
@@ -230,6 +314,12 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
         #[cfg(target_arch = "x86_64")]
         Sysno::lchown => sys_lchown(tf.arg0().into(), tf.arg1() as _, tf.arg2() as _),
         Sysno::madvise => sys_madvise(tf.arg0().into(), tf.arg1() as _, tf.arg2() as _),
+        Sysno::mincore => sys_mincore(tf.arg0() as _, tf.arg1() as _, tf.arg2().into()),
+        Sysno::mlock => sys_mlock(tf.arg0() as _, tf.arg1() as _),
+        Sysno::mlock2 => sys_mlock2(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        Sysno::munlock => sys_munlock(tf.arg0() as _, tf.arg1() as _),
+        Sysno::mlockall => sys_mlockall(tf.arg0() as _),
+        Sysno::munlockall => sys_munlockall(),
         #[cfg(target_arch = "x86_64")]
         Sysno::mkdir => sys_mkdir(tf.arg0().into(), tf.arg1() as _),
         Sysno::mkdirat => sys_mkdirat(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
@@ -243,6 +333,7 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
         ),
         #[cfg(target_arch = "x86_64")]
         Sysno::pipe => sys_pipe(tf.arg0().into()),
+        Sysno::personality => sys_personality(tf.arg0() as _),
         #[cfg(target_arch = "x86_64")]
         Sysno::poll => sys_poll(tf.arg0().into(), tf.arg1() as _, tf.arg2() as _),
         Sysno::ppoll => sys_ppoll(
@@ -250,6 +341,7 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
             tf.arg1() as _,
             tf.arg2().into(),
             tf.arg3().into(),
+            tf.arg4() as _,
         ),
         Sysno::pread64 => sys_pread64(
             tf.arg0() as _,
@@ -291,6 +383,7 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
         ),
         Sysno::setrlimit => sys_setrlimit(tf.arg0() as _, tf.arg1().into()),
         Sysno::getrlimit => sys_getrlimit(tf.arg0() as _, tf.arg1().into()),
+        Sysno::getrusage => sys_getrusage(tf.arg0() as _, tf.arg1().into()),
         Sysno::readlinkat => sys_readlinkat(
             tf.arg0() as _,
             tf.arg1().into(),
@@ -424,8 +517,9 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
         Sysno::listen => sys_listen(tf.arg0() as _, tf.arg1() as _),
         Sysno::accept => sys_accept(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
         Sysno::connect => sys_connect(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
-        Sysno::sync => stub_bypass(sysno),
-        Sysno::fsync => stub_bypass(sysno),
+        Sysno::sync => sys_sync(),
+        Sysno::fsync => sys_fsync(tf.arg0() as _),
+        Sysno::fdatasync => sys_fdatasync(tf.arg0() as _),
         Sysno::truncate => sys_truncate(tf.arg0().into(), tf.arg1() as _),
         Sysno::ftruncate => sys_ftruncate(tf.arg0() as _, tf.arg1() as _),
         Sysno::syslog => stub_bypass(sysno),
@@ -451,7 +545,7 @@ fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
         _ => stub_unimplemented(syscall_num),
     };
     let ans = result.unwrap_or_else(|err| -err.code() as _);
-    time_stat_from_kernel_to_user();
+    // switched back to user time on the return, see `time_stat_on_user_trap`
     info!("[syscall] <{:?}> return {}", sysno, ans);
     ans
 }